@@ -0,0 +1,59 @@
+//! Compares tree-walking `eval` with the bytecode VM on a formula evaluated many times.
+//!
+//! Run with `cargo bench --bench vm`.
+
+use comprehensive_rust::day2::{vm, Arithmetic, Env, EvalError, Expression, Value};
+use std::{hint::black_box, time::Instant};
+
+const RUNS: i64 = 1_000_000;
+
+fn main() {
+    let e: Expression =
+        "(x * x + 3 * x * y - y * y) / (x - y + 1000000) + (x - 7) * (y + 11) - x / 3"
+            .parse()
+            .unwrap();
+    let program = vm::compile(&e);
+
+    let start = Instant::now();
+    let mut sum = 0i64;
+    for i in 0..RUNS {
+        let mut env = Env::new();
+        env.bind("x", i).bind("y", i % 1000);
+        sum = sum.wrapping_add(number(Arithmetic::Checked.eval(black_box(&e), &env)));
+    }
+    let tree_env = start.elapsed();
+    black_box(sum);
+
+    let start = Instant::now();
+    let mut sum = 0i64;
+    for i in 0..RUNS {
+        let mut env = Env::new();
+        env.bind("x", i).bind("y", i % 1000);
+        sum = sum.wrapping_add(number(black_box(&program).run(&env)));
+    }
+    let vm_env = start.elapsed();
+    black_box(sum);
+
+    let start = Instant::now();
+    let mut sum = 0i64;
+    for i in 0..RUNS {
+        let values = [i, i % 1000];
+        sum = sum.wrapping_add(number(
+            black_box(&program).run_slots(Arithmetic::Checked, &values),
+        ));
+    }
+    let vm_slots = start.elapsed();
+    black_box(sum);
+
+    println!("{RUNS} evaluations of {e}");
+    println!("tree eval with Env:  {tree_env:?}");
+    println!("vm run with Env:     {vm_env:?}");
+    println!(
+        "vm run_slots:        {vm_slots:?} ({:.1}x faster than tree eval)",
+        tree_env.as_secs_f64() / vm_slots.as_secs_f64()
+    );
+}
+
+fn number(result: Result<Value, EvalError>) -> i64 {
+    result.unwrap().number().unwrap()
+}
@@ -1,3 +1,5 @@
+use crate::bigint::BigInt;
+
 pub fn multiply(x: i16, y: i16) -> i16 {
     x * y
 }
@@ -39,6 +41,42 @@ fn test_factorial() {
     assert_eq!(factorial(4), 24);
 }
 
+pub fn fib_big(n: u32) -> BigInt {
+    let (mut a, mut b) = (BigInt::from(1u32), BigInt::from(1u32));
+    for _ in 2..n {
+        (a, b) = (b.clone(), &a + &b);
+    }
+    b
+}
+
+#[test]
+fn test_fib_big() {
+    for n in 0..30 {
+        assert_eq!(fib_big(n), BigInt::from(fib(n)));
+    }
+    assert_eq!(fib_big(100).to_string(), "354224848179261915075");
+}
+
+pub fn factorial_big(n: u32) -> BigInt {
+    let mut product = BigInt::from(1u32);
+    for i in 1..=n {
+        product = &product * &BigInt::from(i);
+    }
+    product
+}
+
+#[test]
+fn test_factorial_big() {
+    assert_eq!(factorial_big(0), BigInt::from(1u32));
+    assert_eq!(factorial_big(12), BigInt::from(factorial(12)));
+    assert_eq!(factorial_big(13).to_string(), "6227020800");
+    assert_eq!(
+        factorial_big(100).to_string(),
+        "93326215443944152681699238856266700490715968264381621468592963895217599993229915\
+         608941463976156518286253697920827223758251185210916864000000000000000000000000"
+    );
+}
+
 pub fn fizzbuzz(max: u32) -> () {
     for i in 1..=max {
         if i % 15 == 0 {
@@ -1,5 +1,27 @@
 use std::{cmp::Ordering, fmt::Display, time::Duration};
 
+pub mod codegen;
+pub mod dag;
+pub mod derivative;
+pub mod encode;
+pub mod export;
+pub mod interval;
+pub mod modular;
+pub mod number;
+pub mod parse;
+pub mod print;
+pub mod property;
+pub mod random;
+pub mod rational;
+pub mod repl;
+pub mod simplify;
+pub mod trace;
+pub mod visit;
+pub mod vm;
+
+use trace::{Reduction, TraceStep};
+use visit::{Next, Visitor};
+
 struct Foo {
     x: (u32, u32),
     y: u32,
@@ -56,17 +78,159 @@ fn test_sleep() {
 }
 
 /// An operation to perform on two subexpressions.
-#[derive(Debug)]
-enum Operation {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
     Add,
     Sub,
     Mul,
     Div,
+    /// Remainder of truncating division, with the sign of the left operand.
+    Rem,
+    /// Raise the left operand to the power of the right one.
+    Pow,
+    Min,
+    Max,
+}
+
+impl Operation {
+    /// Binding strength in infix notation, higher binds tighter.
+    ///
+    /// `min` and `max` are written as function calls, so they never need parentheses.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operation::Add | Operation::Sub => 4,
+            Operation::Mul | Operation::Div | Operation::Rem => 5,
+            Operation::Pow => 7,
+            Operation::Min | Operation::Max => 8,
+        }
+    }
+
+    /// Whether `a op b op c` means `a op (b op c)`, which is only the case for powers.
+    pub fn right_associative(&self) -> bool {
+        *self == Operation::Pow
+    }
+
+    /// The infix symbol for this operation, or its name if it is written as a function call.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+            Operation::Rem => "%",
+            Operation::Pow => "^",
+            Operation::Min => "min",
+            Operation::Max => "max",
+        }
+    }
+}
+
+/// An operation to perform on a single subexpression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOperation {
+    Neg,
+    Abs,
+}
+
+impl UnaryOperation {
+    /// Binding strength in infix notation, on the same scale as [`Operation::precedence`].
+    ///
+    /// Negation binds tighter than multiplication but looser than powers, so `-x ^ 2` is
+    /// `-(x ^ 2)`. `abs` is written as a function call.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            UnaryOperation::Neg => 6,
+            UnaryOperation::Abs => 8,
+        }
+    }
+
+    /// The name used in S-expressions and reverse Polish notation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnaryOperation::Neg => "neg",
+            UnaryOperation::Abs => "abs",
+        }
+    }
+}
+
+/// A comparison between two subexpressions, giving a boolean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Binding strength in infix notation, on the same scale as [`Operation::precedence`].
+    pub fn precedence(&self) -> u8 {
+        3
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    /// Whether this only asks if the operands are equal, so that booleans can be compared too.
+    pub fn is_equality(&self) -> bool {
+        matches!(self, Comparison::Eq | Comparison::Ne)
+    }
+
+    /// Compare `left` with `right`.
+    pub fn test<T: PartialOrd>(&self, left: &T, right: &T) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+/// A short-circuiting boolean connective.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Connective {
+    And,
+    Or,
+}
+
+impl Connective {
+    /// Binding strength in infix notation, on the same scale as [`Operation::precedence`].
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Connective::Or => 1,
+            Connective::And => 2,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Connective::And => "&&",
+            Connective::Or => "||",
+        }
+    }
+
+    /// The left operand value that decides the result without evaluating the right one.
+    pub fn short_circuit(&self) -> bool {
+        *self == Connective::Or
+    }
 }
 
 /// An expression, in tree form.
-#[derive(Debug)]
-enum Expression {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
     /// An operation on two subexpressions.
     Op {
         op: Operation,
@@ -74,40 +238,980 @@ enum Expression {
         right: Box<Expression>,
     },
 
+    /// An operation on one subexpression.
+    Unary {
+        op: UnaryOperation,
+        operand: Box<Expression>,
+    },
+
+    /// A comparison of two numbers, or of two booleans for equality.
+    Compare {
+        op: Comparison,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+
+    /// A boolean connective, which only evaluates `right` if `left` does not decide the result.
+    Logic {
+        op: Connective,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+
+    /// Boolean negation.
+    Not(Box<Expression>),
+
+    /// Evaluates `then` if `cond` is true and `otherwise` if it is false.
+    If {
+        cond: Box<Expression>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+
+    /// Evaluates `body` with `name` bound to the value of `value`.
+    Let {
+        name: String,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+
+    /// Evaluates `body` with `function` callable as `name`, including from its own body.
+    Define {
+        name: String,
+        function: Box<Function>,
+        body: Box<Expression>,
+    },
+
+    /// A call to a function from an enclosing [`Expression::Define`] or the [`Env`].
+    Call { name: String, args: Vec<Expression> },
+
     /// A literal value
     Value(i64),
+
+    /// A variable, looked up in the [`Env`] at evaluation time.
+    Var(String),
+}
+
+/// A function of named parameters, see [`Expression::Define`] and [`Env::define`].
+///
+/// Its body sees the parameters, the function itself, the bindings around the
+/// [`Expression::Define`] and the variables and functions of the [`Env`], but not the bindings at
+/// the places it is called from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Expression,
+}
+
+/// A step from an expression to one of its subexpressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Left,
+    Right,
+    /// Into the operand of an [`Expression::Unary`] or [`Expression::Not`].
+    Operand,
+    Cond,
+    Then,
+    Else,
+    /// Into the value of an [`Expression::Let`], or the body of the function an
+    /// [`Expression::Define`] defines.
+    Bound,
+    /// Into the part of an [`Expression::Let`] or [`Expression::Define`] that sees the binding.
+    Body,
+    /// Into the argument of an [`Expression::Call`] with this index.
+    Arg(usize),
+}
+
+impl Expression {
+    /// The subexpression reached by following `path` from `self`, if there is one.
+    pub fn at(&self, path: &[Step]) -> Option<&Expression> {
+        let mut e = self;
+        for step in path {
+            e = match (e, step) {
+                (
+                    Expression::Op { left, .. }
+                    | Expression::Compare { left, .. }
+                    | Expression::Logic { left, .. },
+                    Step::Left,
+                ) => left,
+                (
+                    Expression::Op { right, .. }
+                    | Expression::Compare { right, .. }
+                    | Expression::Logic { right, .. },
+                    Step::Right,
+                ) => right,
+                (Expression::Unary { operand, .. } | Expression::Not(operand), Step::Operand) => {
+                    operand
+                }
+                (Expression::If { cond, .. }, Step::Cond) => cond,
+                (Expression::If { then, .. }, Step::Then) => then,
+                (Expression::If { otherwise, .. }, Step::Else) => otherwise,
+                (Expression::Let { value, .. }, Step::Bound) => value,
+                (Expression::Define { function, .. }, Step::Bound) => &function.body,
+                (Expression::Let { body, .. } | Expression::Define { body, .. }, Step::Body) => {
+                    body
+                }
+                (Expression::Call { args, .. }, Step::Arg(i)) => args.get(*i)?,
+                _ => return None,
+            };
+        }
+        Some(e)
+    }
+}
+
+impl Expression {
+    /// Move the children of `self` into `out`, leaving leaves in their place.
+    fn detach_children(&mut self, out: &mut Vec<Expression>) {
+        let mut detach = |e: &mut Expression| {
+            out.push(std::mem::replace(e, Expression::Value(0)));
+        };
+        match self {
+            Expression::Op { left, right, .. }
+            | Expression::Compare { left, right, .. }
+            | Expression::Logic { left, right, .. } => {
+                detach(left);
+                detach(right);
+            }
+            Expression::Unary { operand, .. } | Expression::Not(operand) => detach(operand),
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                detach(cond);
+                detach(then);
+                detach(otherwise);
+            }
+            Expression::Let { value, body, .. } => {
+                detach(value);
+                detach(body);
+            }
+            Expression::Define { function, body, .. } => {
+                detach(&mut function.body);
+                detach(body);
+            }
+            Expression::Call { args, .. } => {
+                for arg in args {
+                    detach(arg);
+                }
+            }
+            Expression::Value(_) | Expression::Var(_) => {}
+        }
+    }
+}
+
+/// Frees deep trees with an explicit stack, as the default recursive drop could overflow.
+impl Drop for Expression {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.detach_children(&mut pending);
+        // Each detached subtree is left with only leaf children, so dropping it does not recurse.
+        while let Some(mut e) = pending.pop() {
+            e.detach_children(&mut pending);
+        }
+    }
+}
+
+/// The result of evaluating an expression: a number of type `N`, or a boolean.
+///
+/// Variables and literals are always numbers, booleans come from comparisons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<N = i64> {
+    Num(N),
+    Bool(bool),
+}
+
+/// The kind of a [`Value`], for reporting mismatches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Boolean,
+}
+
+impl<N> Value<N> {
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::Num(_) => Type::Number,
+            Value::Bool(_) => Type::Boolean,
+        }
+    }
+
+    /// The number, or a type mismatch if this is a boolean.
+    pub fn number(self) -> Result<N, EvalErrorKind> {
+        match self {
+            Value::Num(val) => Ok(val),
+            Value::Bool(_) => Err(EvalErrorKind::TypeMismatch {
+                expected: Type::Number,
+                found: Type::Boolean,
+            }),
+        }
+    }
+
+    /// The boolean, or a type mismatch if this is a number.
+    pub fn boolean(self) -> Result<bool, EvalErrorKind> {
+        match self {
+            Value::Bool(val) => Ok(val),
+            Value::Num(_) => Err(EvalErrorKind::TypeMismatch {
+                expected: Type::Boolean,
+                found: Type::Number,
+            }),
+        }
+    }
 }
 
-fn eval(e: Expression) -> Result<i64, String> {
-    match e {
-        Expression::Value(val) => Result::Ok(val),
-        Expression::Op { op, left, right } => {
-            let l = eval(*left);
-            let r = eval(*right);
-            match (l, r) {
-                (Result::Ok(left_val), Result::Ok(right_val)) => match op {
-                    Operation::Add => Ok(left_val + right_val),
-                    Operation::Sub => Ok(left_val - right_val),
-                    Operation::Mul => Ok(left_val * right_val),
-                    Operation::Div => {
-                        if right_val == 0 {
-                            Err(format!("division by zero"))
-                        } else {
-                            Ok(left_val / right_val)
-                        }
+impl<N: PartialOrd> Value<N> {
+    /// Compare with `other`. Numbers can be ordered, booleans only tested for equality.
+    pub fn compare(self, op: Comparison, other: Self) -> Result<bool, EvalErrorKind> {
+        match (self, other) {
+            (Value::Num(left), Value::Num(right)) => Ok(op.test(&left, &right)),
+            (Value::Bool(left), Value::Bool(right)) if op.is_equality() => {
+                Ok(op.test(&left, &right))
+            }
+            (Value::Bool(_), _) | (_, Value::Bool(_)) if !op.is_equality() => {
+                Err(EvalErrorKind::TypeMismatch {
+                    expected: Type::Number,
+                    found: Type::Boolean,
+                })
+            }
+            (left, right) => Err(EvalErrorKind::TypeMismatch {
+                expected: left.ty(),
+                found: right.ty(),
+            }),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(val: i64) -> Self {
+        Value::Num(val)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(val: bool) -> Self {
+        Value::Bool(val)
+    }
+}
+
+impl<N: Display> Display for Value<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Num(val) => write!(f, "{val}"),
+            Value::Bool(val) => write!(f, "{val}"),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Number => write!(f, "number"),
+            Type::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+/// Why an expression could not be evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    DivisionByZero,
+    /// The right operand of [`Operation::Rem`] was zero.
+    RemainderByZero,
+    /// A negative exponent in integer arithmetic, where the result would be a fraction.
+    NegativeExponent,
+    /// A power with a fractional exponent, whose result is generally not exact.
+    NonIntegerExponent,
+    /// The result does not fit in an `i64` under [`Arithmetic::Checked`].
+    Overflow,
+    /// A division by a [`Modular`](modular::Modular) number that shares a factor with the
+    /// modulus, so that no number gives one when multiplied by it.
+    NoInverse {
+        value: u64,
+        modulus: u64,
+    },
+    UnboundVariable(String),
+    /// An operand had the wrong type, like a boolean in arithmetic or a number in an `if`.
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+    },
+    /// A call to a function that is neither in scope nor in the [`Env`].
+    UnknownFunction(String),
+    /// A call with a different number of arguments than the function has parameters.
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Calls nested deeper than [`Env::call_limit`], usually from recursion that does not end.
+    CallDepth(usize),
+    /// Evaluation fails this way for some but not necessarily all values of the variables, found
+    /// by [`interval::bounds`].
+    Possibly(Box<EvalErrorKind>),
+}
+
+impl Display for EvalErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::RemainderByZero => write!(f, "remainder by zero"),
+            EvalErrorKind::NegativeExponent => write!(f, "negative exponent"),
+            EvalErrorKind::NonIntegerExponent => write!(f, "exponent is not an integer"),
+            EvalErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            EvalErrorKind::NoInverse { value, modulus } => {
+                write!(f, "{value} has no inverse modulo {modulus}")
+            }
+            EvalErrorKind::UnboundVariable(name) => write!(f, "unbound variable `{name}`"),
+            EvalErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch: expected {expected}, found {found}")
+            }
+            EvalErrorKind::UnknownFunction(name) => write!(f, "unknown function `{name}`"),
+            EvalErrorKind::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{name}` takes {expected} arguments but {found} were given"
+            ),
+            EvalErrorKind::CallDepth(limit) => write!(f, "calls nested more than {limit} deep"),
+            EvalErrorKind::Possibly(kind) => write!(f, "possible {kind}"),
+        }
+    }
+}
+
+/// An evaluation failure, with the path from the root to the subexpression that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub path: Vec<Step>,
+}
+
+impl EvalError {
+    /// The subexpression of `root` that failed to evaluate.
+    pub fn locate<'e>(&self, root: &'e Expression) -> Option<&'e Expression> {
+        root.at(&self.path)
+    }
+}
+
+impl From<EvalErrorKind> for EvalError {
+    fn from(kind: EvalErrorKind) -> Self {
+        EvalError {
+            kind,
+            path: Vec::new(),
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// How to handle results that do not fit in an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    /// Report [`EvalErrorKind::Overflow`].
+    #[default]
+    Checked,
+    /// Wrap around in two's complement.
+    Wrapping,
+    /// Clamp to `i64::MIN` or `i64::MAX`.
+    Saturating,
+}
+
+impl Arithmetic {
+    /// Apply `op` to two operands.
+    ///
+    /// Division or remainder by zero and negative exponents are errors in every mode.
+    pub fn apply(self, op: Operation, left: i64, right: i64) -> Result<i64, EvalErrorKind> {
+        match op {
+            Operation::Div if right == 0 => return Err(EvalErrorKind::DivisionByZero),
+            Operation::Rem if right == 0 => return Err(EvalErrorKind::RemainderByZero),
+            Operation::Pow if right < 0 => return Err(EvalErrorKind::NegativeExponent),
+            _ => {}
+        }
+        let exp = right.unsigned_abs();
+        match self {
+            Arithmetic::Checked => match op {
+                Operation::Add => left.checked_add(right),
+                Operation::Sub => left.checked_sub(right),
+                Operation::Mul => left.checked_mul(right),
+                Operation::Div => left.checked_div(right),
+                Operation::Rem => left.checked_rem(right),
+                Operation::Pow => power(left, exp, 1, |a, b| {
+                    a.checked_mul(b).ok_or(EvalErrorKind::Overflow)
+                })
+                .ok(),
+                Operation::Min => Some(left.min(right)),
+                Operation::Max => Some(left.max(right)),
+            }
+            .ok_or(EvalErrorKind::Overflow),
+            Arithmetic::Wrapping => Ok(match op {
+                Operation::Add => left.wrapping_add(right),
+                Operation::Sub => left.wrapping_sub(right),
+                Operation::Mul => left.wrapping_mul(right),
+                Operation::Div => left.wrapping_div(right),
+                Operation::Rem => left.wrapping_rem(right),
+                Operation::Pow => power(left, exp, 1, |a, b| Ok(a.wrapping_mul(b)))?,
+                Operation::Min => left.min(right),
+                Operation::Max => left.max(right),
+            }),
+            Arithmetic::Saturating => Ok(match op {
+                Operation::Add => left.saturating_add(right),
+                Operation::Sub => left.saturating_sub(right),
+                Operation::Mul => left.saturating_mul(right),
+                Operation::Div => left.saturating_div(right),
+                // The only overflowing case, `i64::MIN % -1`, is exactly zero.
+                Operation::Rem => left.wrapping_rem(right),
+                Operation::Pow => power(left, exp, 1, |a, b| Ok(a.saturating_mul(b)))?,
+                Operation::Min => left.min(right),
+                Operation::Max => left.max(right),
+            }),
+        }
+    }
+
+    /// Apply `op` to one operand.
+    pub fn apply_unary(self, op: UnaryOperation, val: i64) -> Result<i64, EvalErrorKind> {
+        match self {
+            Arithmetic::Checked => match op {
+                UnaryOperation::Neg => val.checked_neg(),
+                UnaryOperation::Abs => val.checked_abs(),
+            }
+            .ok_or(EvalErrorKind::Overflow),
+            Arithmetic::Wrapping => Ok(match op {
+                UnaryOperation::Neg => val.wrapping_neg(),
+                UnaryOperation::Abs => val.wrapping_abs(),
+            }),
+            Arithmetic::Saturating => Ok(match op {
+                UnaryOperation::Neg => val.saturating_neg(),
+                UnaryOperation::Abs => val.saturating_abs(),
+            }),
+        }
+    }
+
+    /// Evaluate `e` under these semantics, looking up variables in `env`.
+    pub fn eval(self, e: &Expression, env: &Env) -> Result<Value, EvalError> {
+        walk(
+            e,
+            env,
+            Ok,
+            |op, val| self.apply_unary(op, val),
+            |op, left, right| self.apply(op, left, right),
+            None,
+        )
+    }
+}
+
+/// `base` raised to the power `exp` by repeated squaring, multiplying with `mul`.
+fn power<T: Clone>(
+    mut base: T,
+    mut exp: u64,
+    one: T,
+    mul: impl Fn(T, T) -> Result<T, EvalErrorKind>,
+) -> Result<T, EvalErrorKind> {
+    let mut acc = one;
+    loop {
+        if exp & 1 == 1 {
+            acc = mul(acc, base.clone())?;
+        }
+        exp >>= 1;
+        if exp == 0 {
+            return Ok(acc);
+        }
+        base = mul(base.clone(), base)?;
+    }
+}
+
+/// Evaluate `e` into numbers of type `T` or booleans, given how to make literals and apply
+/// arithmetic, looking up variables and functions in `env` when they are not bound in `e`.
+///
+/// Traverses `e` with [`visit::visit`], so arbitrarily deep trees are fine. Errors inside a
+/// function body are reported at the outermost call, as the body is not part of `e`.
+///
+/// Every reduction is added to `trace` if there is one.
+fn walk<'e, T: Clone + PartialOrd>(
+    e: &'e Expression,
+    env: &'e Env<'_, T>,
+    literal: impl Fn(i64) -> Result<T, EvalErrorKind>,
+    unary: impl Fn(UnaryOperation, T) -> Result<T, EvalErrorKind>,
+    apply: impl Fn(Operation, T, T) -> Result<T, EvalErrorKind>,
+    trace: Option<&mut Vec<TraceStep<T>>>,
+) -> Result<Value<T>, EvalError> {
+    let mut evaluator = Evaluator {
+        env,
+        literal,
+        unary,
+        apply,
+        trace,
+        values: Vec::new(),
+        locals: Locals::default(),
+        scopes: Vec::new(),
+        calls: Vec::new(),
+        active: 0,
+        call_limit: env.call_limit(),
+    };
+    match visit::visit(e, &mut evaluator) {
+        Ok(()) => Ok(evaluator.values.pop().expect("missing result")),
+        Err((kind, path)) => Err(EvalError { kind, path }),
+    }
+}
+
+/// A call whose arguments or body [`Evaluator`] is evaluating.
+struct Call<'e> {
+    function: &'e Function,
+    /// The binding the body continues from.
+    scope: Option<usize>,
+    /// The innermost binding of the caller and how many bindings there were, to restore when the
+    /// body is done.
+    caller: Option<usize>,
+    len: usize,
+}
+
+/// The [`Visitor`] behind [`walk`], which pushes the value of each expression it is done with.
+struct Evaluator<'e, 'a, 't, T, L, U, A> {
+    env: &'e Env<'a, T>,
+    literal: L,
+    unary: U,
+    apply: A,
+    trace: Option<&'t mut Vec<TraceStep<T>>>,
+    values: Vec<Value<T>>,
+    locals: Locals<'e, Value<T>>,
+    /// The innermost binding outside each `let` and function definition being evaluated.
+    scopes: Vec<Option<usize>>,
+    calls: Vec<Call<'e>>,
+    /// How many function bodies are being evaluated.
+    active: usize,
+    call_limit: usize,
+}
+
+impl<T, L, U, A> Evaluator<'_, '_, '_, T, L, U, A> {
+    fn tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    fn record(&mut self, depth: usize, reduction: Reduction<T>) {
+        if let Some(steps) = self.trace.as_deref_mut() {
+            steps.push(TraceStep { depth, reduction });
+        }
+    }
+
+    fn pop(&mut self) -> Value<T> {
+        self.values.pop().expect("missing operand")
+    }
+}
+
+impl<'e, T, L, U, A> Visitor<'e> for Evaluator<'e, '_, '_, T, L, U, A>
+where
+    T: Clone + PartialOrd,
+    L: Fn(i64) -> Result<T, EvalErrorKind>,
+    U: Fn(UnaryOperation, T) -> Result<T, EvalErrorKind>,
+    A: Fn(Operation, T, T) -> Result<T, EvalErrorKind>,
+{
+    type Error = EvalErrorKind;
+
+    fn enter(&mut self, e: &'e Expression, depth: usize) -> Result<(), EvalErrorKind> {
+        match e {
+            Expression::Value(val) => {
+                let val = (self.literal)(*val)?;
+                self.values.push(Value::Num(val));
+            }
+            Expression::Var(name) => {
+                let val = match self.locals.var(name) {
+                    Some(val) => val.clone(),
+                    None => self
+                        .env
+                        .get(name)
+                        .map(Value::Num)
+                        .ok_or_else(|| EvalErrorKind::UnboundVariable(name.clone()))?,
+                };
+                if self.tracing() {
+                    let (name, value) = (name.clone(), val.clone());
+                    self.record(depth, Reduction::Var { name, value });
+                }
+                self.values.push(val);
+            }
+            Expression::Define { name, function, .. } => {
+                let outer = self.locals.push(Local::Function(name, function));
+                self.scopes.push(outer);
+            }
+            Expression::Call { name, args } => {
+                let env = self.env;
+                let found = self
+                    .locals
+                    .function(name)
+                    .or_else(|| env.function(name).map(|function| (function, None)));
+                match found {
+                    None => return Err(EvalErrorKind::UnknownFunction(name.clone())),
+                    Some((function, _)) if function.params.len() != args.len() => {
+                        return Err(EvalErrorKind::ArgumentCount {
+                            name: name.clone(),
+                            expected: function.params.len(),
+                            found: args.len(),
+                        })
                     }
+                    Some((function, scope)) => self.calls.push(Call {
+                        function,
+                        scope,
+                        caller: None,
+                        len: 0,
+                    }),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        depth: usize,
+    ) -> Result<Next<'e>, EvalErrorKind> {
+        let val = match (e, visited) {
+            (Expression::Unary { op, .. }, 1) => {
+                let val = self.pop().number()?;
+                let operand = self.tracing().then(|| val.clone());
+                let result = (self.unary)(*op, val)?;
+                if let Some(operand) = operand {
+                    let (op, result) = (*op, result.clone());
+                    self.record(
+                        depth,
+                        Reduction::Unary {
+                            op,
+                            operand,
+                            result,
+                        },
+                    );
+                }
+                Value::Num(result)
+            }
+            (Expression::Op { op, .. }, 2) => {
+                let right = self.pop();
+                let left = self.pop().number()?;
+                let right = right.number()?;
+                let operands = self.tracing().then(|| (left.clone(), right.clone()));
+                let result = (self.apply)(*op, left, right)?;
+                if let Some((left, right)) = operands {
+                    let (op, result) = (*op, result.clone());
+                    self.record(
+                        depth,
+                        Reduction::Op {
+                            op,
+                            left,
+                            right,
+                            result,
+                        },
+                    );
+                }
+                Value::Num(result)
+            }
+            (Expression::Compare { op, .. }, 2) => {
+                let right = self.pop();
+                let left = self.pop();
+                let operands = self.tracing().then(|| (left.clone(), right.clone()));
+                let result = left.compare(*op, right)?;
+                if let Some((left, right)) = operands {
+                    let op = *op;
+                    self.record(
+                        depth,
+                        Reduction::Compare {
+                            op,
+                            left,
+                            right,
+                            result,
+                        },
+                    );
+                }
+                Value::Bool(result)
+            }
+            (Expression::Not(_), 1) => {
+                let operand = self.pop().boolean()?;
+                if self.tracing() {
+                    self.record(depth, Reduction::Not { operand });
+                }
+                Value::Bool(!operand)
+            }
+            (Expression::Logic { op, right, .. }, 1) => {
+                let left = self.pop().boolean()?;
+                if left != op.short_circuit() {
+                    return Ok(Next::Child(Step::Right, right));
+                }
+                if self.tracing() {
+                    let op = *op;
+                    self.record(
+                        depth,
+                        Reduction::Logic {
+                            op,
+                            left,
+                            right: None,
+                        },
+                    );
+                }
+                Value::Bool(left)
+            }
+            (Expression::Logic { op, .. }, 2) => {
+                let right = self.pop().boolean()?;
+                if self.tracing() {
+                    let (op, left) = (*op, !op.short_circuit());
+                    self.record(
+                        depth,
+                        Reduction::Logic {
+                            op,
+                            left,
+                            right: Some(right),
+                        },
+                    );
+                }
+                Value::Bool(right)
+            }
+            (
+                Expression::If {
+                    then, otherwise, ..
                 },
-                (e @ Err(_), _) => return e,
-                (_, e @ Err(_)) => return e,
-                _ => panic!(), // oh very cool, it already knows this is unreachable
+                1,
+            ) => {
+                let cond = self.pop().boolean()?;
+                if self.tracing() {
+                    self.record(depth, Reduction::If { cond });
+                }
+                return Ok(if cond {
+                    Next::Child(Step::Then, then)
+                } else {
+                    Next::Child(Step::Else, otherwise)
+                });
             }
+            (Expression::Let { name, body, .. }, 1) => {
+                let value = self.pop();
+                if self.tracing() {
+                    let (name, value) = (name.clone(), value.clone());
+                    self.record(depth, Reduction::Let { name, value });
+                }
+                let outer = self.locals.push(Local::Var(name, value));
+                self.scopes.push(outer);
+                return Ok(Next::Child(Step::Body, body));
+            }
+            // The body of the function is only evaluated when it is called.
+            (Expression::Define { body, .. }, 0) => return Ok(Next::Child(Step::Body, body)),
+            (Expression::Call { args, .. }, i) if i < args.len() => {
+                return Ok(Next::Child(Step::Arg(i), &args[i]))
+            }
+            (Expression::Call { args, .. }, i)
+                if i == args.len() && self.active == self.call_limit =>
+            {
+                return Err(EvalErrorKind::CallDepth(self.call_limit))
+            }
+            (Expression::Call { name, args }, i) if i == args.len() => {
+                let args = self.values.split_off(self.values.len() - args.len());
+                if self.tracing() {
+                    let (name, args) = (name.clone(), args.clone());
+                    self.record(depth, Reduction::Call { name, args });
+                }
+                let call = self.calls.last_mut().expect("missing call");
+                (call.caller, call.len) = (self.locals.innermost, self.locals.bindings.len());
+                self.locals.innermost = call.scope;
+                let function = call.function;
+                for (param, arg) in function.params.iter().zip(args) {
+                    self.locals.push(Local::Var(param, arg));
+                }
+                self.active += 1;
+                return Ok(Next::Outside(&function.body));
+            }
+            (Expression::Call { name, .. }, _) => {
+                let call = self.calls.pop().expect("missing call");
+                self.locals.bindings.truncate(call.len);
+                self.locals.innermost = call.caller;
+                self.active -= 1;
+                if self.tracing() {
+                    let name = name.clone();
+                    let value = self.values.last().expect("missing result").clone();
+                    self.record(depth, Reduction::Return { name, value });
+                }
+                return Ok(Next::Leave);
+            }
+            // The value of the branch or body is the value of the whole.
+            (Expression::If { .. }, 2) | (Expression::Define { .. }, 1) => return Ok(Next::Leave),
+            (e, i) => {
+                return Ok(match e.child(i) {
+                    Some((step, child)) => Next::Child(step, child),
+                    None => Next::Leave,
+                })
+            }
+        };
+        self.values.push(val);
+        Ok(Next::Leave)
+    }
+
+    fn leave(&mut self, e: &'e Expression, _depth: usize) -> Result<(), EvalErrorKind> {
+        if let Expression::Let { .. } | Expression::Define { .. } = e {
+            let outer = self.scopes.pop().expect("missing scope");
+            self.locals.pop(outer);
         }
+        Ok(())
     }
 }
 
+/// A binding made while evaluating an expression, of a variable to a value of type `V`.
+enum Local<'e, V> {
+    Var(&'e str, V),
+    Function(&'e str, &'e Function),
+}
+
+/// The bindings of [`walk`] or [`interval::bounds`] in the order they were made.
+///
+/// Each one is linked to the innermost binding visible where it was made, so scopes are lexical
+/// even though all bindings share a stack: a function body continues from the binding of the
+/// function itself rather than from the bindings of its caller.
+struct Locals<'e, V> {
+    bindings: Vec<(Local<'e, V>, Option<usize>)>,
+    innermost: Option<usize>,
+}
+
+impl<V> Default for Locals<'_, V> {
+    fn default() -> Self {
+        Locals {
+            bindings: Vec::new(),
+            innermost: None,
+        }
+    }
+}
+
+impl<'e, V> Locals<'e, V> {
+    /// Add a binding in the innermost scope, returning the previous innermost one.
+    fn push(&mut self, local: Local<'e, V>) -> Option<usize> {
+        let outer = self.innermost;
+        self.bindings.push((local, outer));
+        self.innermost = Some(self.bindings.len() - 1);
+        outer
+    }
+
+    /// Remove the last binding, making `outer` innermost again.
+    fn pop(&mut self, outer: Option<usize>) {
+        self.bindings.pop();
+        self.innermost = outer;
+    }
+
+    /// The visible bindings, innermost first, with their indices.
+    fn visible(&self) -> impl Iterator<Item = (usize, &Local<'e, V>)> {
+        std::iter::successors(self.innermost, |&i| self.bindings[i].1)
+            .map(|i| (i, &self.bindings[i].0))
+    }
+
+    fn var(&self, name: &str) -> Option<&V> {
+        self.visible().find_map(|(_, local)| match local {
+            Local::Var(bound, val) if *bound == name => Some(val),
+            _ => None,
+        })
+    }
+
+    /// The function bound to `name`, with the binding its body continues from.
+    fn function(&self, name: &str) -> Option<(&'e Function, Option<usize>)> {
+        self.visible().find_map(|(i, local)| match local {
+            Local::Function(bound, function) if *bound == name => Some((*function, Some(i))),
+            _ => None,
+        })
+    }
+}
+
+/// How deeply calls may nest unless [`Env::limit_calls`] says otherwise.
+pub const DEFAULT_CALL_LIMIT: usize = 1000;
+
+/// Variable and function bindings for evaluation, in a chain of nested scopes.
+#[derive(Debug)]
+pub struct Env<'a, T = i64> {
+    vars: HashMap<String, T>,
+    functions: HashMap<String, Function>,
+    call_limit: Option<usize>,
+    parent: Option<&'a Env<'a, T>>,
+}
+
+impl<T> Default for Env<'_, T> {
+    fn default() -> Self {
+        Env {
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+            call_limit: None,
+            parent: None,
+        }
+    }
+}
+
+impl<'a, T: Clone> Env<'a, T> {
+    /// An empty, outermost scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A nested scope whose bindings shadow those of `self`.
+    pub fn scope(&'a self) -> Env<'a, T> {
+        Env {
+            parent: Some(self),
+            ..Env::default()
+        }
+    }
+
+    /// Bind `name` to `value` in this scope, replacing any previous binding here.
+    pub fn bind(&mut self, name: impl Into<String>, value: T) -> &mut Self {
+        self.vars.insert(name.into(), value);
+        self
+    }
+
+    /// Look up `name`, starting from the innermost scope.
+    pub fn get(&self, name: &str) -> Option<T> {
+        match self.vars.get(name) {
+            Some(val) => Some(val.clone()),
+            None => self.parent?.get(name),
+        }
+    }
+
+    /// Define the function `name` in this scope, replacing any previous definition here.
+    pub fn define(&mut self, name: impl Into<String>, function: Function) -> &mut Self {
+        self.functions.insert(name.into(), function);
+        self
+    }
+
+    /// Look up the function `name`, starting from the innermost scope.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        match self.functions.get(name) {
+            Some(function) => Some(function),
+            None => self.parent?.function(name),
+        }
+    }
+
+    /// Fail evaluation in this scope and nested ones if calls nest more than `depth` deep.
+    pub fn limit_calls(&mut self, depth: usize) -> &mut Self {
+        self.call_limit = Some(depth);
+        self
+    }
+
+    /// The call depth limit set with [`Env::limit_calls`] here or in an enclosing scope, or
+    /// [`DEFAULT_CALL_LIMIT`].
+    pub fn call_limit(&self) -> usize {
+        match (self.call_limit, self.parent) {
+            (Some(depth), _) => depth,
+            (None, Some(parent)) => parent.call_limit(),
+            (None, None) => DEFAULT_CALL_LIMIT,
+        }
+    }
+}
+
+/// Evaluate `e` with no variables bound.
+pub fn eval(e: Expression) -> Result<Value, EvalError> {
+    eval_with(&e, &Env::new())
+}
+
+/// Evaluate `e` with [`Arithmetic::Checked`], looking up variables in `env`.
+pub fn eval_with(e: &Expression, env: &Env) -> Result<Value, EvalError> {
+    Arithmetic::Checked.eval(e, env)
+}
+
 #[test]
 fn test_value() {
-    assert_eq!(eval(Expression::Value(19)), Ok(19));
+    assert_eq!(eval(Expression::Value(19)), Ok(Value::Num(19)));
 }
 
 #[test]
@@ -118,7 +1222,7 @@ fn test_sum() {
             left: Box::new(Expression::Value(10)),
             right: Box::new(Expression::Value(20)),
         }),
-        Ok(30)
+        Ok(Value::Num(30))
     );
 }
 
@@ -144,7 +1248,7 @@ fn test_recursion() {
             left: Box::new(term1),
             right: Box::new(term2),
         }),
-        Ok(85)
+        Ok(Value::Num(85))
     );
 }
 
@@ -156,8 +1260,420 @@ fn test_error() {
             left: Box::new(Expression::Value(99)),
             right: Box::new(Expression::Value(0)),
         }),
-        Err(String::from("division by zero"))
+        Err(EvalErrorKind::DivisionByZero.into())
+    );
+}
+
+#[test]
+fn test_variables() {
+    let e: Expression = "x * x + y".parse().unwrap();
+    let mut env = Env::new();
+    env.bind("x", 3).bind("y", 4);
+    assert_eq!(eval_with(&e, &env), Ok(Value::Num(13)));
+    env.bind("x", 5);
+    assert_eq!(eval_with(&e, &env), Ok(Value::Num(29)));
+
+    let mut inner = env.scope();
+    inner.bind("y", -25);
+    assert_eq!(eval_with(&e, &inner), Ok(Value::Num(0)));
+    assert_eq!(inner.get("x"), Some(5));
+    assert_eq!(env.get("y"), Some(4));
+
+    assert_eq!(
+        eval_with(&e, &Env::new()),
+        Err(EvalError {
+            kind: EvalErrorKind::UnboundVariable(String::from("x")),
+            path: vec![Step::Left, Step::Left],
+        })
+    );
+}
+
+#[test]
+fn test_overflow() {
+    let e: Expression = "x * 2 + 1".parse().unwrap();
+    let mut env = Env::new();
+    env.bind("x", i64::MAX / 2 + 1);
+    assert_eq!(
+        eval_with(&e, &env).unwrap_err().kind,
+        EvalErrorKind::Overflow
+    );
+    assert_eq!(
+        Arithmetic::Wrapping.eval(&e, &env),
+        Ok(Value::Num(i64::MIN + 1))
+    );
+    assert_eq!(
+        Arithmetic::Saturating.eval(&e, &env),
+        Ok(Value::Num(i64::MAX))
+    );
+
+    let e: Expression = "x / -1".parse().unwrap();
+    env.bind("x", i64::MIN);
+    assert_eq!(
+        Arithmetic::Checked.eval(&e, &env).unwrap_err().kind,
+        EvalErrorKind::Overflow
+    );
+    assert_eq!(
+        Arithmetic::Wrapping.eval(&e, &env),
+        Ok(Value::Num(i64::MIN))
+    );
+    assert_eq!(
+        Arithmetic::Saturating.eval(&e, &env),
+        Ok(Value::Num(i64::MAX))
+    );
+
+    let e: Expression = "0 - x - 1".parse().unwrap();
+    env.bind("x", i64::MAX);
+    assert_eq!(Arithmetic::Checked.eval(&e, &env), Ok(Value::Num(i64::MIN)));
+    let e: Expression = "x + x / 0".parse().unwrap();
+    assert_eq!(
+        Arithmetic::Saturating.eval(&e, &env).unwrap_err().kind,
+        EvalErrorKind::DivisionByZero
+    );
+}
+
+#[test]
+fn test_more_operators() {
+    let mut env = Env::new();
+    env.bind("x", 7).bind("min", i64::MIN);
+    let check = |text: &str| eval_with(&text.parse().unwrap(), &env);
+    assert_eq!(check("x % 3"), Ok(Value::Num(1)));
+    assert_eq!(check("-x % 3"), Ok(Value::Num(-1)));
+    assert_eq!(check("2 ^ 3 ^ 2"), Ok(Value::Num(512)));
+    assert_eq!(check("-x ^ 2"), Ok(Value::Num(-49)));
+    assert_eq!(check("-2 ^ 63"), Ok(Value::Num(i64::MIN)));
+    assert_eq!(check("x ^ 0"), Ok(Value::Num(1)));
+    assert_eq!(
+        check("min(x, -3) * max(x, -3) - abs(-x)"),
+        Ok(Value::Num(-28))
+    );
+    assert_eq!(check("-1 ^ 9223372036854775807"), Ok(Value::Num(-1)));
+
+    let kind = |arithmetic: Arithmetic, text: &str| {
+        arithmetic
+            .eval(&text.parse().unwrap(), &env)
+            .map_err(|err| err.kind)
+    };
+    for arithmetic in [
+        Arithmetic::Checked,
+        Arithmetic::Wrapping,
+        Arithmetic::Saturating,
+    ] {
+        assert_eq!(
+            kind(arithmetic, "2 ^ (1 - x)"),
+            Err(EvalErrorKind::NegativeExponent)
+        );
+        assert_eq!(
+            kind(arithmetic, "x % (x - 7)"),
+            Err(EvalErrorKind::RemainderByZero)
+        );
+    }
+    assert_eq!(
+        kind(Arithmetic::Checked, "-min"),
+        Err(EvalErrorKind::Overflow)
+    );
+    assert_eq!(
+        kind(Arithmetic::Wrapping, "abs(min)"),
+        Ok(Value::Num(i64::MIN))
+    );
+    assert_eq!(
+        kind(Arithmetic::Saturating, "abs(min)"),
+        Ok(Value::Num(i64::MAX))
+    );
+    assert_eq!(
+        kind(Arithmetic::Checked, "min % -1"),
+        Err(EvalErrorKind::Overflow)
+    );
+    assert_eq!(kind(Arithmetic::Saturating, "min % -1"), Ok(Value::Num(0)));
+    assert_eq!(
+        kind(Arithmetic::Checked, "3 ^ 40"),
+        Err(EvalErrorKind::Overflow)
+    );
+    assert_eq!(
+        kind(Arithmetic::Wrapping, "2 ^ 64 + 3 ^ 2"),
+        Ok(Value::Num(9))
+    );
+    assert_eq!(
+        kind(Arithmetic::Saturating, "-3 ^ 41"),
+        Ok(Value::Num(i64::MIN))
     );
+
+    let e: Expression = "1 + abs(10 / (x % 7))".parse().unwrap();
+    let err = eval_with(&e, &env).unwrap_err();
+    assert_eq!(err.path, [Step::Right, Step::Operand]);
+    assert_eq!(
+        err.report(&e),
+        "division by zero\n1 + abs(10 / (x % 7))\n        ^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn test_conditionals() {
+    let mut env = Env::new();
+    env.bind("x", 0).bind("y", -4);
+    let check = |text: &str| eval_with(&text.parse().unwrap(), &env);
+    assert_eq!(check("1 < 2"), Ok(Value::Bool(true)));
+    assert_eq!(check("2 + 2 == 4 && 3 != 3"), Ok(Value::Bool(false)));
+    assert_eq!(check("y <= -4 && !(x >= 1)"), Ok(Value::Bool(true)));
+    assert_eq!(check("(1 < 2) == (y > 0)"), Ok(Value::Bool(false)));
+    assert_eq!(check("if y < 0 then -y else y"), Ok(Value::Num(4)));
+    assert_eq!(check("if x == 0 then 1 else 1 / x"), Ok(Value::Num(1)));
+    assert_eq!(
+        check("if x > 0 then x > 1 else y > 1"),
+        Ok(Value::Bool(false))
+    );
+
+    // The right operand is only evaluated when the left one does not decide the result.
+    assert_eq!(check("x == 0 || 10 / x > 1"), Ok(Value::Bool(true)));
+    assert_eq!(check("x != 0 && 10 / x > 1"), Ok(Value::Bool(false)));
+    assert_eq!(check("x == 0 || unbound"), Ok(Value::Bool(true)));
+    assert_eq!(
+        check("x == 0 && 10 / x > 1").unwrap_err().kind,
+        EvalErrorKind::DivisionByZero
+    );
+
+    let mismatch = |expected, found| EvalErrorKind::TypeMismatch { expected, found };
+    let error = |text: &str| {
+        let err = check(text).unwrap_err();
+        (err.kind, err.path)
+    };
+    assert_eq!(
+        error("(1 < 2) + 1"),
+        (mismatch(Type::Number, Type::Boolean), vec![])
+    );
+    assert_eq!(
+        error("1 && 2"),
+        (mismatch(Type::Boolean, Type::Number), vec![])
+    );
+    assert_eq!(
+        error("1 > 2 || y"),
+        (mismatch(Type::Boolean, Type::Number), vec![])
+    );
+    assert_eq!(
+        error("(1 < 2) < (3 < 4)"),
+        (mismatch(Type::Number, Type::Boolean), vec![])
+    );
+    assert_eq!(
+        error("y == (y < 0)"),
+        (mismatch(Type::Number, Type::Boolean), vec![])
+    );
+    assert_eq!(
+        error("if y then 1 else 2"),
+        (mismatch(Type::Boolean, Type::Number), vec![])
+    );
+    assert_eq!(
+        error("if y < 0 then 1 / x else 0"),
+        (EvalErrorKind::DivisionByZero, vec![Step::Then])
+    );
+    assert_eq!(
+        error("1 + -(x > y)"),
+        (mismatch(Type::Number, Type::Boolean), vec![Step::Right])
+    );
+    assert_eq!(
+        EvalErrorKind::TypeMismatch {
+            expected: Type::Boolean,
+            found: Type::Number,
+        }
+        .to_string(),
+        "type mismatch: expected boolean, found number"
+    );
+}
+
+#[test]
+fn test_functions() {
+    let mut env = Env::new();
+    env.bind("x", 10);
+    let check = |text: &str, env: &Env| eval_with(&text.parse().unwrap(), env);
+    let num = |val| Ok(Value::Num(val));
+    assert_eq!(check("let x = 3 in x * x", &env), num(9));
+    assert_eq!(check("let y = 1 in let y = y + 1 in y", &env), num(2));
+    assert_eq!(check("(let x = 2 in x) + x", &env), num(12));
+    assert_eq!(
+        check(
+            "let fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(10)",
+            &env
+        ),
+        num(3628800)
+    );
+    assert_eq!(
+        check(
+            "let fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2) in fib(15)",
+            &env
+        ),
+        num(610)
+    );
+    // Functions see the bindings where they are defined, not where they are called.
+    assert_eq!(
+        check(
+            "let k = 1 in let add(a) = a + k + x in let k = 100 in add(5)",
+            &env
+        ),
+        num(16)
+    );
+    assert_eq!(
+        check(
+            "let outer(a) = let inner(b) = a * b in inner(3) + inner(4) in outer(2)",
+            &env
+        ),
+        num(14)
+    );
+    assert_eq!(
+        check("let pos(n) = n > 0 in pos(3) && !pos(-1)", &env),
+        Ok(Value::Bool(true))
+    );
+
+    let mut library = env.scope();
+    library
+        .define(
+            "even",
+            Function {
+                params: vec![String::from("n")],
+                body: "if n == 0 then 1 < 2 else odd(n - 1)".parse().unwrap(),
+            },
+        )
+        .define(
+            "odd",
+            Function {
+                params: vec![String::from("n")],
+                body: "if n == 0 then 1 > 2 else even(n - 1)".parse().unwrap(),
+            },
+        )
+        .define(
+            "inv",
+            Function {
+                params: vec![String::from("n")],
+                body: "100 / n".parse().unwrap(),
+            },
+        );
+    assert_eq!(check("even(x) && odd(7)", &library), Ok(Value::Bool(true)));
+    // Functions in the expression shadow those in the environment.
+    assert_eq!(check("let inv(n) = -n in inv(x)", &library), num(-10));
+
+    let error = |text: &str, env: &Env| {
+        let err = check(text, env).unwrap_err();
+        (err.kind, err.path)
+    };
+    assert_eq!(
+        error("g(1)", &env),
+        (EvalErrorKind::UnknownFunction(String::from("g")), vec![])
+    );
+    assert_eq!(
+        error("let f(a) = a in f(1, 2)", &env),
+        (
+            EvalErrorKind::ArgumentCount {
+                name: String::from("f"),
+                expected: 1,
+                found: 2,
+            },
+            vec![Step::Body]
+        )
+    );
+    assert_eq!(
+        error("let f(a) = a + y in let y = 1 in f(1)", &env),
+        (
+            EvalErrorKind::UnboundVariable(String::from("y")),
+            vec![Step::Body, Step::Body]
+        )
+    );
+    assert_eq!(
+        error("let f(a, b) = a in f(1, 2 / (x - x))", &env),
+        (
+            EvalErrorKind::DivisionByZero,
+            vec![Step::Body, Step::Arg(1)]
+        )
+    );
+    // Errors inside a function are reported at the outermost call.
+    let e = "1 + inv(x - 10)".parse().unwrap();
+    let err = eval_with(&e, &library).unwrap_err();
+    assert_eq!(err.path, [Step::Right]);
+    assert_eq!(
+        err.report(&e),
+        "division by zero\n1 + inv(x - 10)\n    ^^^^^^^^^^^\n"
+    );
+
+    let down = "let down(n) = if n == 0 then 0 else down(n - 1) in down";
+    assert_eq!(
+        error(&format!("{down}(-1)"), &env),
+        (
+            EvalErrorKind::CallDepth(DEFAULT_CALL_LIMIT),
+            vec![Step::Body]
+        )
+    );
+    let mut limited = env.scope();
+    limited.limit_calls(5);
+    assert_eq!(check(&format!("{down}(4)"), &limited), num(0));
+    assert_eq!(
+        error(&format!("{down}(5)"), &limited),
+        (EvalErrorKind::CallDepth(5), vec![Step::Body])
+    );
+    assert_eq!(limited.scope().call_limit(), 5);
+
+    // Deep recursion does not use up the native stack.
+    let mut deep = Env::new();
+    deep.limit_calls(100_000);
+    assert_eq!(
+        check(
+            "let sum(n) = if n == 0 then 0 else n + sum(n - 1) in sum(50000)",
+            &deep
+        ),
+        num(1_250_025_000)
+    );
+}
+
+/// A chain of `depth` additions of one, nested to the left or right.
+#[cfg(test)]
+fn deep_sum(depth: i64, left_deep: bool) -> Expression {
+    let mut e = Expression::Value(1);
+    for _ in 0..depth {
+        let (left, right) = if left_deep {
+            (e, Expression::Value(1))
+        } else {
+            (Expression::Value(1), e)
+        };
+        e = Expression::Op {
+            op: Operation::Add,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+    }
+    e
+}
+
+#[test]
+fn test_deep_nesting() {
+    for left_deep in [true, false] {
+        let e = deep_sum(200_000, left_deep);
+        assert_eq!(eval_with(&e, &Env::new()), Ok(Value::Num(200_001)));
+    }
+
+    let e = Expression::Op {
+        op: Operation::Div,
+        left: Box::new(deep_sum(100_000, true)),
+        right: Box::new(Expression::Var(String::from("zero"))),
+    };
+    let mut env = Env::new();
+    env.bind("zero", 0);
+    let err = eval_with(&e, &env).unwrap_err();
+    assert_eq!(err.kind, EvalErrorKind::DivisionByZero);
+    assert!(err.path.is_empty());
+}
+
+#[test]
+fn test_error_location() {
+    let e: Expression = "10 * 9 + 3 / (x - 4) * 5".parse().unwrap();
+    let mut env = Env::new();
+    env.bind("x", 4);
+    let err = eval_with(&e, &env).unwrap_err();
+    assert_eq!(err.kind, EvalErrorKind::DivisionByZero);
+    assert_eq!(err.path, [Step::Right, Step::Left]);
+    assert_eq!(err.locate(&e).unwrap().to_string(), "3 / (x - 4)");
+    assert_eq!(err.to_string(), "division by zero");
+    assert_eq!(
+        err.report(&e),
+        "division by zero\n10 * 9 + 3 / (x - 4) * 5\n         ^^^^^^^^^^^\n"
+    );
+
+    let err: Box<dyn std::error::Error> = Box::new(eval_with(&e, &Env::new()).unwrap_err());
+    assert_eq!(err.to_string(), "unbound variable `x`");
 }
 
 struct Race {
@@ -0,0 +1,1486 @@
+//! Generating C or Rust source for a function that computes an [`Expression`].
+//!
+//! The free variables of the expression become `i64` parameters, in order of first appearance,
+//! and the generated function fails exactly when [`eval_with`](super::eval_with) would, with the
+//! same message: on division or remainder by zero, negative exponents, overflow, and calls nested
+//! deeper than [`DEFAULT_CALL_LIMIT`]. Functions defined in the expression become separate
+//! functions, taking the variables their bodies use from around the definition as extra
+//! parameters.
+//!
+//! For example, the Rust source for `let sq(a) = a * a in sq(x) / (y - 1)` is
+//!
+//! ```
+//! pub fn ratio(x: i64, y: i64) -> Result<i64, &'static str> {
+//!     fn mul(a: i64, b: i64) -> Result<i64, &'static str> {
+//!         a.checked_mul(b).ok_or("arithmetic overflow")
+//!     }
+//!
+//!     fn sub(a: i64, b: i64) -> Result<i64, &'static str> {
+//!         a.checked_sub(b).ok_or("arithmetic overflow")
+//!     }
+//!
+//!     fn div(a: i64, b: i64) -> Result<i64, &'static str> {
+//!         if b == 0 {
+//!             return Err("division by zero");
+//!         }
+//!         a.checked_div(b).ok_or("arithmetic overflow")
+//!     }
+//!
+//!     fn sq(depth: usize, a: i64) -> Result<i64, &'static str> {
+//!         if depth > 1000 {
+//!             return Err("calls nested more than 1000 deep");
+//!         }
+//!         Ok(mul(a, a)?)
+//!     }
+//!
+//!     Ok(div(sq(1, x)?, sub(y, 1)?)?)
+//! }
+//!
+//! assert_eq!(ratio(6, 3), Ok(18));
+//! assert_eq!(ratio(6, 1), Err("division by zero"));
+//! assert_eq!(ratio(i64::MAX, 2), Err("arithmetic overflow"));
+//! ```
+//!
+//! The generated C, from [`to_c`], reports errors the same way through its return value, and
+//! uses the overflow checking builtins of GCC and Clang.
+
+use super::{
+    Comparison, Connective, EvalError, EvalErrorKind, Expression, Operation, Step, Type,
+    UnaryOperation, DEFAULT_CALL_LIMIT,
+};
+use std::collections::{BTreeSet, HashSet};
+
+/// Identifiers the generated code must not use for variables and functions: the keywords of C
+/// and Rust, and names the generated code refers to.
+const RESERVED: &[&str] = &[
+    "_",
+    "_Bool",
+    "Err",
+    "INT64_MAX",
+    "INT64_MIN",
+    "NULL",
+    "Ok",
+    "Result",
+    "Self",
+    "abstract",
+    "as",
+    "async",
+    "auto",
+    "await",
+    "become",
+    "bool",
+    "box",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "crate",
+    "default",
+    "do",
+    "double",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "final",
+    "float",
+    "fn",
+    "for",
+    "gen",
+    "goto",
+    "i64",
+    "if",
+    "impl",
+    "in",
+    "inline",
+    "int",
+    "int64_t",
+    "let",
+    "long",
+    "loop",
+    "macro",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "override",
+    "priv",
+    "pub",
+    "ref",
+    "register",
+    "restrict",
+    "return",
+    "self",
+    "short",
+    "signed",
+    "size_t",
+    "sizeof",
+    "static",
+    "std",
+    "struct",
+    "super",
+    "switch",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "typedef",
+    "typeof",
+    "uint64_t",
+    "union",
+    "unsafe",
+    "unsigned",
+    "unsized",
+    "use",
+    "usize",
+    "virtual",
+    "void",
+    "volatile",
+    "where",
+    "while",
+    "yield",
+];
+
+/// Names the generated code gives to things of its own.
+const DEPTH: &str = "depth";
+const RESULT: &str = "result";
+const ERR: &str = "err";
+
+/// The identifiers used so far in the generated code.
+#[derive(Debug, Clone, Default)]
+struct Names(HashSet<String>);
+
+impl Names {
+    /// A new identifier like `name`, made valid and distinct from the others by a suffix.
+    fn fresh(&mut self, name: &str) -> String {
+        let mut base: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            base.insert(0, '_');
+        }
+        let mut ident = base.clone();
+        for n in 1.. {
+            if !self.0.contains(&ident) && !RESERVED.contains(&ident.as_str()) {
+                break;
+            }
+            ident = format!("{base}_{n}");
+        }
+        self.0.insert(ident.clone());
+        ident
+    }
+
+    fn reserve(&mut self, ident: &str) {
+        self.0.insert(String::from(ident));
+    }
+}
+
+type VarId = usize;
+type FnId = usize;
+
+/// The type of a node, which may depend on a function whose type is still being worked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    Known(Type),
+    /// Whatever the function returns.
+    Result(FnId),
+}
+
+/// An expression with names resolved, its functions lifted out and its types checked.
+#[derive(Debug, Clone)]
+enum Node {
+    Value(i64),
+    Var(VarId),
+    Op(Operation, Box<Node>, Box<Node>),
+    Unary(UnaryOperation, Box<Node>),
+    Compare(Comparison, Box<Node>, Box<Node>),
+    Logic(Connective, Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    If(Box<Node>, Box<Node>, Box<Node>, Ty),
+    Let(VarId, Box<Node>, Box<Node>),
+    Call(FnId, Vec<Node>),
+}
+
+/// A variable of the generated code: a parameter or a `let`.
+#[derive(Debug, Clone)]
+struct Var {
+    name: String,
+    ident: String,
+    ty: Ty,
+    /// The function whose code binds it.
+    owner: FnId,
+}
+
+/// A function of the generated code. The first is the one computing the whole expression, with
+/// the free variables as parameters.
+#[derive(Debug, Clone)]
+struct Func {
+    name: String,
+    ident: String,
+    params: Vec<VarId>,
+    /// `None` until its body or a use of its result decides it.
+    ty: Option<Type>,
+    body: Node,
+    /// The variables its code refers to.
+    uses: BTreeSet<VarId>,
+    calls: BTreeSet<FnId>,
+    /// The variables from outside it needs as extra parameters, used by it or by the functions
+    /// it calls.
+    captures: BTreeSet<VarId>,
+}
+
+#[derive(Debug, Clone)]
+enum Binding<'e> {
+    Var(&'e str, VarId),
+    Function(&'e str, FnId),
+}
+
+/// Turns an [`Expression`] into a [`Program`].
+#[derive(Debug)]
+struct Resolver<'e> {
+    vars: Vec<Var>,
+    functions: Vec<Func>,
+    /// For each function, another one found to have the same type, or itself. Following these
+    /// leads to the one whose type is recorded.
+    same: Vec<FnId>,
+    scope: Vec<Binding<'e>>,
+    /// The function whose body is being resolved.
+    current: FnId,
+    path: Vec<Step>,
+}
+
+impl<'e> Resolver<'e> {
+    fn error(&self, kind: EvalErrorKind) -> EvalError {
+        EvalError {
+            kind,
+            path: self.path.clone(),
+        }
+    }
+
+    /// The function whose type `id` has been found to share.
+    fn root(&self, mut id: FnId) -> FnId {
+        while self.same[id] != id {
+            id = self.same[id];
+        }
+        id
+    }
+
+    /// `ty` with what is known so far about the types of functions filled in.
+    fn known(&self, ty: Ty) -> Ty {
+        match ty {
+            Ty::Known(_) => ty,
+            Ty::Result(id) => {
+                let id = self.root(id);
+                self.functions[id].ty.map_or(Ty::Result(id), Ty::Known)
+            }
+        }
+    }
+
+    /// Require `found` to have the same type as `expected`, deciding the types of functions
+    /// that are not known yet.
+    fn unify(&mut self, expected: Ty, found: Ty) -> Result<(), EvalError> {
+        match (self.known(expected), self.known(found)) {
+            (Ty::Known(expected), Ty::Known(found)) if expected != found => {
+                Err(self.error(EvalErrorKind::TypeMismatch { expected, found }))
+            }
+            (Ty::Known(ty), Ty::Result(id)) | (Ty::Result(id), Ty::Known(ty)) => {
+                self.functions[id].ty = Some(ty);
+                Ok(())
+            }
+            (Ty::Result(a), Ty::Result(b)) => {
+                self.same[a] = b;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn child(&mut self, step: Step, e: &'e Expression) -> Result<(Node, Ty), EvalError> {
+        self.path.push(step);
+        let result = self.resolve(e)?;
+        self.path.pop();
+        Ok(result)
+    }
+
+    fn var(&mut self, name: &str, ty: Ty) -> VarId {
+        self.vars.push(Var {
+            name: String::from(name),
+            ident: String::new(),
+            ty,
+            owner: self.current,
+        });
+        self.vars.len() - 1
+    }
+
+    /// Resolve `e`, with its type.
+    fn resolve(&mut self, e: &'e Expression) -> Result<(Node, Ty), EvalError> {
+        let number = Ty::Known(Type::Number);
+        let boolean = Ty::Known(Type::Boolean);
+        Ok(match e {
+            Expression::Value(val) => (Node::Value(*val), number),
+            Expression::Var(name) => {
+                let bound = self.scope.iter().rev().find_map(|binding| match binding {
+                    Binding::Var(bound, id) if bound == name => Some(*id),
+                    _ => None,
+                });
+                let free = || {
+                    let params = &self.functions[0].params;
+                    params
+                        .iter()
+                        .copied()
+                        .find(|&id| self.vars[id].name == *name)
+                };
+                let id = match bound.or_else(free) {
+                    Some(id) => id,
+                    None => {
+                        let current = std::mem::replace(&mut self.current, 0);
+                        let id = self.var(name, number);
+                        self.current = current;
+                        self.functions[0].params.push(id);
+                        id
+                    }
+                };
+                self.functions[self.current].uses.insert(id);
+                (Node::Var(id), self.vars[id].ty)
+            }
+            Expression::Op { op, left, right } => {
+                let (left, left_ty) = self.child(Step::Left, left)?;
+                let (right, right_ty) = self.child(Step::Right, right)?;
+                self.unify(number, left_ty)?;
+                self.unify(number, right_ty)?;
+                (Node::Op(*op, Box::new(left), Box::new(right)), number)
+            }
+            Expression::Unary { op, operand } => {
+                let (operand, ty) = self.child(Step::Operand, operand)?;
+                self.unify(number, ty)?;
+                (Node::Unary(*op, Box::new(operand)), number)
+            }
+            Expression::Compare { op, left, right } => {
+                let (left, left_ty) = self.child(Step::Left, left)?;
+                let (right, right_ty) = self.child(Step::Right, right)?;
+                // The same checks as `Value::compare`.
+                if op.is_equality() {
+                    self.unify(left_ty, right_ty)?;
+                } else {
+                    self.unify(number, left_ty)?;
+                    self.unify(number, right_ty)?;
+                }
+                (Node::Compare(*op, Box::new(left), Box::new(right)), boolean)
+            }
+            Expression::Logic { op, left, right } => {
+                let (left, left_ty) = self.child(Step::Left, left)?;
+                self.unify(boolean, left_ty)?;
+                let (right, right_ty) = self.child(Step::Right, right)?;
+                self.unify(boolean, right_ty)?;
+                (Node::Logic(*op, Box::new(left), Box::new(right)), boolean)
+            }
+            Expression::Not(operand) => {
+                let (operand, ty) = self.child(Step::Operand, operand)?;
+                self.unify(boolean, ty)?;
+                (Node::Not(Box::new(operand)), boolean)
+            }
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let (cond, cond_ty) = self.child(Step::Cond, cond)?;
+                self.unify(boolean, cond_ty)?;
+                let (then, then_ty) = self.child(Step::Then, then)?;
+                let (otherwise, otherwise_ty) = self.child(Step::Else, otherwise)?;
+                // Unlike `eval`, the branches must have the same type for the generated code.
+                self.unify(then_ty, otherwise_ty)?;
+                let node = Node::If(Box::new(cond), Box::new(then), Box::new(otherwise), then_ty);
+                (node, then_ty)
+            }
+            Expression::Let { name, value, body } => {
+                let (value, ty) = self.child(Step::Bound, value)?;
+                let id = self.var(name, ty);
+                self.scope.push(Binding::Var(name, id));
+                let (body, ty) = self.child(Step::Body, body)?;
+                self.scope.pop();
+                (Node::Let(id, Box::new(value), Box::new(body)), ty)
+            }
+            Expression::Define {
+                name,
+                function,
+                body,
+            } => {
+                let id = self.functions.len();
+                self.functions.push(Func {
+                    name: name.clone(),
+                    ident: String::new(),
+                    params: Vec::new(),
+                    ty: None,
+                    body: Node::Value(0),
+                    uses: BTreeSet::new(),
+                    calls: BTreeSet::new(),
+                    captures: BTreeSet::new(),
+                });
+                self.same.push(id);
+                self.scope.push(Binding::Function(name, id));
+                let outer = std::mem::replace(&mut self.current, id);
+                let params: Vec<_> = (function.params.iter())
+                    .map(|param| self.var(param, Ty::Known(Type::Number)))
+                    .collect();
+                self.functions[id].params = params.clone();
+                let len = self.scope.len();
+                for (param, &id) in function.params.iter().zip(&params) {
+                    self.scope.push(Binding::Var(param, id));
+                }
+                // Calls made before the type is known, as in a recursive function, have the type
+                // of the result, which the body or how those calls are used decides.
+                let (node, ty) = self.child(Step::Bound, &function.body)?;
+                self.path.push(Step::Bound);
+                self.unify(Ty::Result(id), ty)?;
+                self.path.pop();
+                self.functions[id].body = node;
+                self.scope.truncate(len);
+                self.current = outer;
+                let result = self.child(Step::Body, body)?;
+                self.scope.pop();
+                result
+            }
+            Expression::Call { name, args } => {
+                let found = self.scope.iter().rev().find_map(|binding| match binding {
+                    Binding::Function(bound, id) if bound == name => Some(*id),
+                    _ => None,
+                });
+                let Some(id) = found else {
+                    return Err(self.error(EvalErrorKind::UnknownFunction(name.clone())));
+                };
+                if self.functions[id].params.len() != args.len() {
+                    return Err(self.error(EvalErrorKind::ArgumentCount {
+                        name: name.clone(),
+                        expected: self.functions[id].params.len(),
+                        found: args.len(),
+                    }));
+                }
+                let mut nodes = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    let (node, ty) = self.child(Step::Arg(i), arg)?;
+                    // Parameters are always numbers in the generated code.
+                    self.path.push(Step::Arg(i));
+                    self.unify(number, ty)?;
+                    self.path.pop();
+                    nodes.push(node);
+                }
+                self.functions[self.current].calls.insert(id);
+                (Node::Call(id, nodes), Ty::Result(id))
+            }
+        })
+    }
+}
+
+/// A whole expression ready to generate code for.
+#[derive(Debug)]
+struct Program {
+    vars: Vec<Var>,
+    functions: Vec<Func>,
+    /// Which variables the generated code refers to.
+    used: Vec<bool>,
+    /// Which functions are called, directly or indirectly.
+    reachable: Vec<bool>,
+    names: Names,
+    /// What the names of helpers and lifted functions start with.
+    prefix: String,
+}
+
+impl Program {
+    /// Resolve `e` into the function `name`, starting the names of the helpers and the other
+    /// functions with `prefix`.
+    fn new(e: &Expression, name: &str, prefix: &str) -> Result<Self, EvalError> {
+        let mut resolver = Resolver {
+            vars: Vec::new(),
+            functions: vec![Func {
+                name: String::from(name),
+                ident: String::from(name),
+                params: Vec::new(),
+                ty: None,
+                body: Node::Value(0),
+                uses: BTreeSet::new(),
+                calls: BTreeSet::new(),
+                captures: BTreeSet::new(),
+            }],
+            same: vec![0],
+            scope: Vec::new(),
+            current: 0,
+            path: Vec::new(),
+        };
+        let (body, ty) = resolver.resolve(e)?;
+        resolver.unify(Ty::Result(0), ty)?;
+        // Functions whose result is never used as anything are numbers.
+        for id in 0..resolver.functions.len() {
+            let ty = match resolver.known(Ty::Result(id)) {
+                Ty::Known(ty) => ty,
+                Ty::Result(_) => Type::Number,
+            };
+            resolver.functions[id].ty = Some(ty);
+        }
+        let Resolver {
+            mut vars,
+            mut functions,
+            ..
+        } = resolver;
+        functions[0].body = body;
+
+        // A function needs whatever it and its callees use from outside it, which for recursive
+        // functions takes a few rounds to settle.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 1..functions.len() {
+                let mut captures = functions[id].uses.clone();
+                for &callee in &functions[id].calls {
+                    captures.extend(&functions[callee].captures);
+                }
+                captures.retain(|&var| vars[var].owner != id);
+                if captures != functions[id].captures {
+                    functions[id].captures = captures;
+                    changed = true;
+                }
+            }
+        }
+        let mut reachable = vec![false; functions.len()];
+        let mut pending = vec![0];
+        while let Some(id) = pending.pop() {
+            if !std::mem::replace(&mut reachable[id], true) {
+                pending.extend(&functions[id].calls);
+            }
+        }
+        let mut used = vec![false; vars.len()];
+        for (_, func) in functions
+            .iter()
+            .enumerate()
+            .filter(|&(id, _)| reachable[id])
+        {
+            let captured = func.calls.iter().flat_map(|&id| &functions[id].captures);
+            for &var in func.uses.iter().chain(captured) {
+                used[var] = true;
+            }
+        }
+
+        let mut names = Names::default();
+        for ident in [name, DEPTH, RESULT, ERR] {
+            names.reserve(ident);
+        }
+        for helper in Helper::ALL {
+            names.reserve(&format!("{prefix}{}", helper.name()));
+        }
+        // The parameters of the main function are named first, so they keep their names if
+        // possible.
+        for &var in &functions[0].params {
+            vars[var].ident = names.fresh(&vars[var].name);
+        }
+        for var in vars.iter_mut().filter(|var| var.ident.is_empty()) {
+            var.ident = names.fresh(&var.name);
+        }
+        for func in &mut functions[1..] {
+            func.ident = names.fresh(&format!("{prefix}{}", func.name));
+        }
+        Ok(Program {
+            vars,
+            functions,
+            used,
+            reachable,
+            names,
+            prefix: String::from(prefix),
+        })
+    }
+
+    fn ty(&self, ty: Ty) -> Type {
+        match ty {
+            Ty::Known(ty) => ty,
+            Ty::Result(func) => self.fn_ty(func),
+        }
+    }
+
+    fn var_ty(&self, var: VarId) -> Type {
+        self.ty(self.vars[var].ty)
+    }
+
+    fn fn_ty(&self, func: FnId) -> Type {
+        self.functions[func].ty.unwrap_or(Type::Number)
+    }
+
+    /// The functions to generate besides the main one.
+    fn lifted(&self) -> impl Iterator<Item = (FnId, &Func)> {
+        (self.functions.iter().enumerate().skip(1)).filter(|&(id, _)| self.reachable[id])
+    }
+
+    /// The parameters of a lifted function: its own, then the variables it captures.
+    fn params(&self, func: FnId) -> impl Iterator<Item = VarId> + '_ {
+        let func = &self.functions[func];
+        func.params.iter().chain(&func.captures).copied()
+    }
+
+    fn helper(&self, helper: Helper) -> String {
+        format!("{}{}", self.prefix, helper.name())
+    }
+
+    /// The arguments to call `callee` with from `caller`: the call depth, then `args`, then the
+    /// captured variables.
+    fn call_args(&self, caller: FnId, callee: FnId, args: Vec<String>) -> String {
+        let depth = match caller {
+            0 => String::from("1"),
+            _ => format!("{DEPTH} + 1"),
+        };
+        let captures = self.functions[callee].captures.iter();
+        let captures = captures.map(|&var| self.vars[var].ident.clone());
+        let all: Vec<_> = std::iter::once(depth).chain(args).chain(captures).collect();
+        all.join(", ")
+    }
+}
+
+/// An operation the generated code has a helper function for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Helper {
+    Binary(Operation),
+    Unary(UnaryOperation),
+}
+
+impl Helper {
+    const ALL: [Helper; 10] = [
+        Helper::Binary(Operation::Add),
+        Helper::Binary(Operation::Sub),
+        Helper::Binary(Operation::Mul),
+        Helper::Binary(Operation::Div),
+        Helper::Binary(Operation::Rem),
+        Helper::Binary(Operation::Pow),
+        Helper::Binary(Operation::Min),
+        Helper::Binary(Operation::Max),
+        Helper::Unary(UnaryOperation::Neg),
+        Helper::Unary(UnaryOperation::Abs),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Helper::Binary(Operation::Add) => "add",
+            Helper::Binary(Operation::Sub) => "sub",
+            Helper::Binary(Operation::Mul) => "mul",
+            Helper::Binary(Operation::Div) => "div",
+            Helper::Binary(Operation::Rem) => "rem",
+            Helper::Binary(Operation::Pow) => "pow",
+            Helper::Binary(Operation::Min) => "min",
+            Helper::Binary(Operation::Max) => "max",
+            Helper::Unary(op) => op.name(),
+        }
+    }
+}
+
+/// The error message as a string literal, which reads the same in C and Rust.
+fn message(kind: EvalErrorKind) -> String {
+    format!("{:?}", kind.to_string())
+}
+
+/// Check that `name` can name the generated function.
+fn check_name(name: &str) {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED.contains(&name);
+    assert!(valid, "`{name}` is not a valid function name");
+}
+
+/// Source for a Rust function called `name` computing `e`, returning an `i64` or a `bool`, or the
+/// message of the error [`eval_with`](super::eval_with) would fail with.
+///
+/// Everything it needs is nested inside it, so the source can be pasted into any module. Fails
+/// like [`eval_with`](super::eval_with) does on a type mismatch, an unknown function or a call
+/// with the wrong number of arguments, but wherever it is in `e` rather than only if evaluation
+/// reaches it. As Rust is statically typed, both branches of an `if` must have the same type,
+/// and the arguments of functions must be numbers.
+///
+/// Panics if `name` is not a valid identifier.
+pub fn to_rust(e: &Expression, name: &str) -> Result<String, EvalError> {
+    check_name(name);
+    let program = Program::new(e, name, "")?;
+    let mut rust = Rust {
+        program: &program,
+        func: 0,
+        helpers: Vec::new(),
+    };
+    let mut functions = Vec::new();
+    for (id, func) in program.lifted() {
+        rust.func = id;
+        let body = rust.expr(&func.body);
+        let params: String = (program.params(id))
+            .map(|var| {
+                format!(
+                    ", {}: {}",
+                    rust.binding(var),
+                    rust_type(program.var_ty(var))
+                )
+            })
+            .collect();
+        functions.push(format!(
+            "    fn {}({DEPTH}: usize{params}) -> Result<{}, &'static str> {{
+        if {DEPTH} > {DEFAULT_CALL_LIMIT} {{
+            return Err({});
+        }}
+        Ok({body})
+    }}
+",
+            func.ident,
+            rust_type(program.fn_ty(id)),
+            message(EvalErrorKind::CallDepth(DEFAULT_CALL_LIMIT)),
+        ));
+    }
+    rust.func = 0;
+    let body = rust.expr(&program.functions[0].body);
+    let params: Vec<_> = (program.functions[0].params.iter())
+        .map(|&var| format!("{}: i64", rust.binding(var)))
+        .collect();
+    let mut out = format!(
+        "pub fn {name}({}) -> Result<{}, &'static str> {{\n",
+        params.join(", "),
+        rust_type(program.fn_ty(0))
+    );
+    let helpers = rust.helpers.iter().map(|&helper| rust_helper(helper));
+    for item in helpers.chain(functions) {
+        out.push_str(&item);
+        out.push('\n');
+    }
+    out.push_str(&format!("    Ok({body})\n}}\n"));
+    Ok(out)
+}
+
+fn rust_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Number => "i64",
+        Type::Boolean => "bool",
+    }
+}
+
+/// A Rust literal for `val`, with a suffix if it does not fit the `i32` an unconstrained literal
+/// would be.
+fn rust_literal(val: i64) -> String {
+    match val {
+        i64::MIN => String::from("i64::MIN"),
+        _ if i32::try_from(val).is_err() => format!("{val}i64"),
+        _ => val.to_string(),
+    }
+}
+
+/// A helper function for `helper`, nested in the generated function.
+fn rust_helper(helper: Helper) -> String {
+    let overflow = message(EvalErrorKind::Overflow);
+    let name = helper.name();
+    let checked = |zero: Option<EvalErrorKind>| {
+        let check = match zero {
+            Some(kind) => format!(
+                "        if b == 0 {{\n            return Err({});\n        }}\n",
+                message(kind)
+            ),
+            None => String::new(),
+        };
+        format!(
+            "    fn {name}(a: i64, b: i64) -> Result<i64, &'static str> {{
+{check}        a.checked_{name}(b).ok_or({overflow})
+    }}
+"
+        )
+    };
+    match helper {
+        Helper::Binary(Operation::Div) => checked(Some(EvalErrorKind::DivisionByZero)),
+        Helper::Binary(Operation::Rem) => checked(Some(EvalErrorKind::RemainderByZero)),
+        Helper::Binary(Operation::Pow) => format!(
+            "    fn pow(mut base: i64, exp: i64) -> Result<i64, &'static str> {{
+        if exp < 0 {{
+            return Err({});
+        }}
+        let (mut bits, mut acc) = (exp as u64, 1i64);
+        loop {{
+            if bits & 1 == 1 {{
+                acc = acc.checked_mul(base).ok_or({overflow})?;
+            }}
+            bits >>= 1;
+            if bits == 0 {{
+                return Ok(acc);
+            }}
+            base = base.checked_mul(base).ok_or({overflow})?;
+        }}
+    }}
+",
+            message(EvalErrorKind::NegativeExponent)
+        ),
+        Helper::Binary(_) => checked(None),
+        Helper::Unary(_) => format!(
+            "    fn {name}(a: i64) -> Result<i64, &'static str> {{
+        a.checked_{name}().ok_or({overflow})
+    }}
+"
+        ),
+    }
+}
+
+/// Writes the expressions of the generated Rust, noting the helpers they need.
+struct Rust<'p> {
+    program: &'p Program,
+    /// The function being written.
+    func: FnId,
+    helpers: Vec<Helper>,
+}
+
+impl Rust<'_> {
+    /// The name to bind `var` to, which is `_` if nothing refers to it.
+    fn binding(&self, var: VarId) -> &str {
+        match self.program.used[var] {
+            true => &self.program.vars[var].ident,
+            false => "_",
+        }
+    }
+
+    fn helper(&mut self, helper: Helper) -> String {
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+        }
+        self.program.helper(helper)
+    }
+
+    /// `node` as the operand of an operator, in parentheses unless it binds tighter than any.
+    fn operand(&mut self, node: &Node) -> String {
+        let expr = self.expr(node);
+        match node {
+            Node::Compare(..) | Node::Logic(..) | Node::If(..) | Node::Let(..) => {
+                format!("({expr})")
+            }
+            _ => expr,
+        }
+    }
+
+    fn expr(&mut self, node: &Node) -> String {
+        let program = self.program;
+        match node {
+            Node::Value(val) => rust_literal(*val),
+            Node::Var(id) => program.vars[*id].ident.clone(),
+            Node::Op(op @ (Operation::Min | Operation::Max), left, right) => {
+                let (left, right) = (self.expr(left), self.expr(right));
+                format!("std::cmp::{}({left}, {right})", op.symbol())
+            }
+            Node::Op(op, left, right) => {
+                let (left, right) = (self.expr(left), self.expr(right));
+                format!("{}({left}, {right})?", self.helper(Helper::Binary(*op)))
+            }
+            Node::Unary(op, operand) => {
+                let operand = self.expr(operand);
+                format!("{}({operand})?", self.helper(Helper::Unary(*op)))
+            }
+            Node::Compare(op, left, right) => {
+                let (left, right) = (self.operand(left), self.operand(right));
+                format!("{left} {} {right}", op.symbol())
+            }
+            Node::Logic(op, left, right) => {
+                let (left, right) = (self.operand(left), self.operand(right));
+                format!("{left} {} {right}", op.symbol())
+            }
+            Node::Not(operand) => format!("!{}", self.operand(operand)),
+            Node::If(cond, then, otherwise, _) => {
+                let cond = self.expr(cond);
+                let (then, otherwise) = (self.expr(then), self.expr(otherwise));
+                format!("if {cond} {{ {then} }} else {{ {otherwise} }}")
+            }
+            Node::Let(id, value, body) => {
+                let value = self.expr(value);
+                let body = self.expr(body);
+                let ty = rust_type(program.var_ty(*id));
+                format!("{{ let {}: {ty} = {value}; {body} }}", self.binding(*id))
+            }
+            Node::Call(callee, args) => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                let ident = &program.functions[*callee].ident;
+                format!("{ident}({})?", program.call_args(self.func, *callee, args))
+            }
+        }
+    }
+}
+
+/// Source for a C function called `name` computing `e`, which stores an `int64_t` or a `bool`
+/// through its last parameter and returns `NULL`, or returns the message of the error
+/// [`eval_with`](super::eval_with) would fail with.
+///
+/// Fails like [`to_rust`] does. The helpers and other functions it needs are `static` and named
+/// after it, so several generated functions can share a file. For example, the C for `x / 2`
+/// is
+///
+/// ```c
+/// #include <stdbool.h>
+/// #include <stddef.h>
+/// #include <stdint.h>
+///
+/// static const char *half_div(int64_t a, int64_t b, int64_t *result) {
+///     if (b == 0) return "division by zero";
+///     if (a == INT64_MIN && b == -1) return "arithmetic overflow";
+///     *result = a / b;
+///     return NULL;
+/// }
+///
+/// const char *half(int64_t x, int64_t *result) {
+///     const char *err;
+///     int64_t t1 = 0;
+///     if ((err = half_div(x, 2, &t1))) return err;
+///     *result = t1;
+///     return NULL;
+/// }
+/// ```
+///
+/// Panics if `name` is not a valid identifier.
+pub fn to_c(e: &Expression, name: &str) -> Result<String, EvalError> {
+    check_name(name);
+    let program = Program::new(e, name, &format!("{name}_"))?;
+    let mut c = C {
+        program: &program,
+        func: 0,
+        helpers: Vec::new(),
+        names: Names::default(),
+        temps: 0,
+        checked: false,
+        out: String::new(),
+        indent: 1,
+    };
+    let mut prototypes = String::new();
+    let mut functions = Vec::new();
+    for (id, func) in program.lifted() {
+        let params: String = (program.params(id))
+            .map(|var| {
+                format!(
+                    ", {} {}",
+                    c_type(program.var_ty(var)),
+                    program.vars[var].ident
+                )
+            })
+            .collect();
+        let signature = format!(
+            "static const char *{}(size_t {DEPTH}{params}, {} *{RESULT})",
+            func.ident,
+            c_type(program.fn_ty(id))
+        );
+        prototypes.push_str(&format!("{signature};\n"));
+        let limit = message(EvalErrorKind::CallDepth(DEFAULT_CALL_LIMIT));
+        let body = c.body(id);
+        functions.push(format!(
+            "{signature} {{\n    if ({DEPTH} > {DEFAULT_CALL_LIMIT}) return {limit};\n{body}}}\n"
+        ));
+    }
+    let body = c.body(0);
+    let params: String = (program.functions[0].params.iter())
+        .map(|&var| format!("int64_t {}, ", program.vars[var].ident))
+        .collect();
+    let mut out = String::from("#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n");
+    let helpers = c.helpers.iter().map(|&helper| c_helper(&program, helper));
+    let prototypes = Some(prototypes).filter(|prototypes| !prototypes.is_empty());
+    for item in helpers.chain(prototypes).chain(functions) {
+        out.push('\n');
+        out.push_str(&item);
+    }
+    out.push_str(&format!(
+        "\nconst char *{name}({params}{} *{RESULT}) {{\n{body}}}\n",
+        c_type(program.fn_ty(0))
+    ));
+    Ok(out)
+}
+
+fn c_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Number => "int64_t",
+        Type::Boolean => "bool",
+    }
+}
+
+fn c_literal(val: i64) -> String {
+    match val {
+        i64::MIN => String::from("INT64_MIN"),
+        _ => val.to_string(),
+    }
+}
+
+/// A `static` helper function for `helper`.
+fn c_helper(program: &Program, helper: Helper) -> String {
+    let overflow = message(EvalErrorKind::Overflow);
+    let name = program.helper(helper);
+    let signature = |params| format!("static const char *{name}({params}, int64_t *result)");
+    let binary = signature("int64_t a, int64_t b");
+    match helper {
+        Helper::Binary(op @ (Operation::Add | Operation::Sub | Operation::Mul)) => format!(
+            "{binary} {{
+    if (__builtin_{}_overflow(a, b, result)) return {overflow};
+    return NULL;
+}}
+",
+            Helper::Binary(op).name()
+        ),
+        Helper::Binary(op @ (Operation::Div | Operation::Rem)) => {
+            let zero = match op {
+                Operation::Div => EvalErrorKind::DivisionByZero,
+                _ => EvalErrorKind::RemainderByZero,
+            };
+            format!(
+                "{binary} {{
+    if (b == 0) return {};
+    if (a == INT64_MIN && b == -1) return {overflow};
+    *result = a {} b;
+    return NULL;
+}}
+",
+                message(zero),
+                op.symbol()
+            )
+        }
+        Helper::Binary(Operation::Pow) => format!(
+            "{} {{
+    if (exp < 0) return {};
+    uint64_t bits = (uint64_t)exp;
+    int64_t acc = 1;
+    for (;;) {{
+        if ((bits & 1) && __builtin_mul_overflow(acc, base, &acc)) return {overflow};
+        bits >>= 1;
+        if (bits == 0) break;
+        if (__builtin_mul_overflow(base, base, &base)) return {overflow};
+    }}
+    *result = acc;
+    return NULL;
+}}
+",
+            signature("int64_t base, int64_t exp"),
+            message(EvalErrorKind::NegativeExponent)
+        ),
+        Helper::Binary(op) => format!(
+            "static int64_t {name}(int64_t a, int64_t b) {{
+    return a {} b ? a : b;
+}}
+",
+            match op {
+                Operation::Min => "<",
+                _ => ">",
+            }
+        ),
+        Helper::Unary(UnaryOperation::Neg) => format!(
+            "{} {{
+    if (__builtin_sub_overflow(0, a, result)) return {overflow};
+    return NULL;
+}}
+",
+            signature("int64_t a")
+        ),
+        Helper::Unary(UnaryOperation::Abs) => format!(
+            "{} {{
+    if (a == INT64_MIN) return {overflow};
+    *result = a < 0 ? -a : a;
+    return NULL;
+}}
+",
+            signature("int64_t a")
+        ),
+    }
+}
+
+/// Writes the statements of the functions in the generated C, noting the helpers they need.
+///
+/// Anything that can fail is evaluated into a temporary first, returning the error if it does,
+/// so what is left is an expression that cannot fail. Such an expression is either atomic or in
+/// parentheses as a whole.
+struct C<'p> {
+    program: &'p Program,
+    /// The function being written.
+    func: FnId,
+    helpers: Vec<Helper>,
+    /// The identifiers used in the function, including its temporaries.
+    names: Names,
+    temps: usize,
+    /// Whether any statement has checked for an error, which needs a variable for it.
+    checked: bool,
+    out: String,
+    indent: usize,
+}
+
+impl C<'_> {
+    /// The statements of the function `func` after any check of the call depth, storing the
+    /// result.
+    fn body(&mut self, func: FnId) -> String {
+        self.func = func;
+        self.names = self.program.names.clone();
+        self.temps = 0;
+        self.checked = false;
+        let val = self.value(&self.program.functions[func].body);
+        let mut body = std::mem::take(&mut self.out);
+        if self.checked {
+            body.insert_str(0, &format!("    const char *{ERR};\n"));
+        }
+        body.push_str(&format!("    *{RESULT} = {val};\n    return NULL;\n"));
+        body
+    }
+
+    fn line(&mut self, line: &str) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn helper(&mut self, helper: Helper) -> String {
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+        }
+        self.program.helper(helper)
+    }
+
+    /// Declare a new temporary of type `ty`, starting out as `init` if there is one.
+    fn temp(&mut self, ty: Type, init: Option<&str>) -> String {
+        self.temps += 1;
+        let temp = self.names.fresh(&format!("t{}", self.temps));
+        match init {
+            Some(init) => self.line(&format!("{} {temp} = {init};", c_type(ty))),
+            None => self.line(&format!("{} {temp};", c_type(ty))),
+        }
+        temp
+    }
+
+    /// Call `function` with `args` and a new temporary of type `ty` for the result, returning
+    /// the error if it fails.
+    fn call(&mut self, ty: Type, function: &str, args: &str) -> String {
+        // Initialized anyway, as compilers cannot always tell it is only read after the call
+        // succeeds.
+        let zero = match ty {
+            Type::Number => "0",
+            Type::Boolean => "false",
+        };
+        let temp = self.temp(ty, Some(zero));
+        self.line(&format!(
+            "if (({ERR} = {function}({args}, &{temp}))) return {ERR};"
+        ));
+        self.checked = true;
+        temp
+    }
+
+    /// Write the statements evaluating `node` in a nested block, storing its value in `temp`.
+    fn block(&mut self, node: &Node, temp: &str) {
+        self.indent += 1;
+        let val = self.value(node);
+        self.line(&format!("{temp} = {val};"));
+        self.indent -= 1;
+    }
+
+    /// Write the statements evaluating `node`, returning the expression for its value.
+    fn value(&mut self, node: &Node) -> String {
+        let program = self.program;
+        match node {
+            Node::Value(val) => c_literal(*val),
+            Node::Var(id) => program.vars[*id].ident.clone(),
+            Node::Op(op, left, right) => {
+                let (left, right) = (self.value(left), self.value(right));
+                let helper = self.helper(Helper::Binary(*op));
+                match op {
+                    Operation::Min | Operation::Max => format!("{helper}({left}, {right})"),
+                    _ => self.call(Type::Number, &helper, &format!("{left}, {right}")),
+                }
+            }
+            Node::Unary(op, operand) => {
+                let operand = self.value(operand);
+                let helper = self.helper(Helper::Unary(*op));
+                self.call(Type::Number, &helper, &operand)
+            }
+            Node::Compare(op, left, right) => {
+                let (left, right) = (self.value(left), self.value(right));
+                format!("({left} {} {right})", op.symbol())
+            }
+            Node::Logic(op, left, right) => {
+                let left = self.value(left);
+                if single(right) {
+                    return format!("({left} {} {})", op.symbol(), self.value(right));
+                }
+                // Only evaluate the right operand if the left one does not decide the result.
+                let temp = self.temp(Type::Boolean, Some(&left));
+                let not = if op.short_circuit() { "!" } else { "" };
+                self.line(&format!("if ({not}{temp}) {{"));
+                self.block(right, &temp);
+                self.line("}");
+                temp
+            }
+            Node::Not(operand) => format!("!{}", self.value(operand)),
+            Node::If(cond, then, otherwise, ty) => {
+                let cond = self.value(cond);
+                if single(then) && single(otherwise) {
+                    let (then, otherwise) = (self.value(then), self.value(otherwise));
+                    return format!("({cond} ? {then} : {otherwise})");
+                }
+                let temp = self.temp(program.ty(*ty), None);
+                let cond = match cond.strip_prefix('(') {
+                    Some(inner) => inner.strip_suffix(')').unwrap_or(inner),
+                    None => &cond,
+                };
+                self.line(&format!("if ({cond}) {{"));
+                self.block(then, &temp);
+                self.line("} else {");
+                self.block(otherwise, &temp);
+                self.line("}");
+                temp
+            }
+            Node::Let(id, value, body) => {
+                let value = self.value(value);
+                let var = &program.vars[*id];
+                if program.used[*id] {
+                    let ty = c_type(program.var_ty(*id));
+                    self.line(&format!("{ty} {} = {value};", var.ident));
+                } else {
+                    // Still refer to the value, which may be all that uses a variable or helper.
+                    self.line(&format!("(void){value};"));
+                }
+                self.value(body)
+            }
+            Node::Call(callee, args) => {
+                let args = args.iter().map(|arg| self.value(arg)).collect();
+                let args = program.call_args(self.func, *callee, args);
+                let callee = &program.functions[*callee];
+                let ty = callee.ty.unwrap_or(Type::Number);
+                self.call(ty, &callee.ident, &args)
+            }
+        }
+    }
+}
+
+/// Whether the C for `node` is a single expression, with no statements before it.
+fn single(node: &Node) -> bool {
+    match node {
+        Node::Value(_) | Node::Var(_) => true,
+        Node::Op(Operation::Min | Operation::Max, left, right)
+        | Node::Compare(_, left, right)
+        | Node::Logic(_, left, right) => single(left) && single(right),
+        Node::Not(operand) => single(operand),
+        Node::If(cond, then, otherwise, _) => single(cond) && single(then) && single(otherwise),
+        Node::Op(..) | Node::Unary(..) | Node::Let(..) | Node::Call(..) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rust(text: &str, name: &str) -> String {
+        to_rust(&text.parse().unwrap(), name).unwrap()
+    }
+
+    fn c(text: &str, name: &str) -> String {
+        to_c(&text.parse().unwrap(), name).unwrap()
+    }
+
+    fn error(text: &str) -> EvalError {
+        to_rust(&text.parse().unwrap(), "f").unwrap_err()
+    }
+
+    #[test]
+    fn rust_source() {
+        // The example at the top of the module, where it is compiled and run.
+        assert_eq!(
+            rust("let sq(a) = a * a in sq(x) / (y - 1)", "ratio"),
+            r#"pub fn ratio(x: i64, y: i64) -> Result<i64, &'static str> {
+    fn mul(a: i64, b: i64) -> Result<i64, &'static str> {
+        a.checked_mul(b).ok_or("arithmetic overflow")
+    }
+
+    fn sub(a: i64, b: i64) -> Result<i64, &'static str> {
+        a.checked_sub(b).ok_or("arithmetic overflow")
+    }
+
+    fn div(a: i64, b: i64) -> Result<i64, &'static str> {
+        if b == 0 {
+            return Err("division by zero");
+        }
+        a.checked_div(b).ok_or("arithmetic overflow")
+    }
+
+    fn sq(depth: usize, a: i64) -> Result<i64, &'static str> {
+        if depth > 1000 {
+            return Err("calls nested more than 1000 deep");
+        }
+        Ok(mul(a, a)?)
+    }
+
+    Ok(div(sq(1, x)?, sub(y, 1)?)?)
+}
+"#
+        );
+        let source = rust(
+            "let f(k) = if k <= 0 then y else k * f(k - 1) in f(x) > 100 || x / y == 0",
+            "big",
+        );
+        assert!(source.starts_with("pub fn big(y: i64, x: i64) -> Result<bool, &'static str> {\n"));
+        assert!(source.contains(
+            "    fn f(depth: usize, k: i64, y: i64) -> Result<i64, &'static str> {
+        if depth > 1000 {
+            return Err(\"calls nested more than 1000 deep\");
+        }
+        Ok(if k <= 0 { y } else { mul(k, f(depth + 1, sub(k, 1)?, y)?)? })
+    }
+"
+        ));
+        assert!(source.ends_with("    Ok((f(1, x, y)? > 100) || (div(x, y)? == 0))\n}\n"));
+        let source = rust(
+            "let unused = x / 0 in min(-x, 3000000000) == 2 && !(x > 2)",
+            "g",
+        );
+        assert!(source.ends_with(
+            "    Ok({ let _: i64 = div(x, 0)?; (std::cmp::min(neg(x)?, 3000000000i64) == 2) && !(x > 2) })\n}\n"
+        ));
+    }
+
+    #[test]
+    fn c_source() {
+        // The example in the documentation of `to_c`.
+        assert_eq!(
+            c("x / 2", "half"),
+            r#"#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+static const char *half_div(int64_t a, int64_t b, int64_t *result) {
+    if (b == 0) return "division by zero";
+    if (a == INT64_MIN && b == -1) return "arithmetic overflow";
+    *result = a / b;
+    return NULL;
+}
+
+const char *half(int64_t x, int64_t *result) {
+    const char *err;
+    int64_t t1 = 0;
+    if ((err = half_div(x, 2, &t1))) return err;
+    *result = t1;
+    return NULL;
+}
+"#
+        );
+        let source = c(
+            "let f(k) = if k <= 0 then y else k * f(k - 1) in f(x) > 100 || x / y == 0",
+            "big",
+        );
+        assert!(source.contains(
+            "static const char *big_f(size_t depth, int64_t k, int64_t y, int64_t *result);\n"
+        ));
+        assert!(source.contains(
+            "    if (depth > 1000) return \"calls nested more than 1000 deep\";
+    const char *err;
+    int64_t t1;
+    if (k <= 0) {
+        t1 = y;
+    } else {
+        int64_t t2 = 0;
+        if ((err = big_sub(k, 1, &t2))) return err;
+        int64_t t3 = 0;
+        if ((err = big_f(depth + 1, t2, y, &t3))) return err;
+        int64_t t4 = 0;
+        if ((err = big_mul(k, t3, &t4))) return err;
+        t1 = t4;
+    }
+"
+        ));
+        // The right operand of `||` is only evaluated, and can only fail, if the left is false.
+        assert!(source.ends_with(
+            "const char *big(int64_t y, int64_t x, bool *result) {
+    const char *err;
+    int64_t t1 = 0;
+    if ((err = big_f(1, x, y, &t1))) return err;
+    bool t2 = (t1 > 100);
+    if (!t2) {
+        int64_t t3 = 0;
+        if ((err = big_div(x, y, &t3))) return err;
+        t2 = (t3 == 0);
+    }
+    *result = t2;
+    return NULL;
+}
+"
+        ));
+        let source = c("if x > 0 then min(x, 1) else max(x, -1)", "clamp");
+        assert!(source.contains("static int64_t clamp_min(int64_t a, int64_t b) {\n"));
+        assert!(source.contains("    *result = ((x > 0) ? clamp_min(x, 1) : clamp_max(x, -1));\n"));
+        let min = to_c(&Expression::Value(i64::MIN), "min").unwrap();
+        assert!(min.contains("    *result = INT64_MIN;\n"));
+        let min = to_rust(&Expression::Value(i64::MIN), "min").unwrap();
+        assert!(min.contains("    Ok(i64::MIN)\n"));
+    }
+
+    #[test]
+    fn names() {
+        let source = rust("let int = -result in int + t1", "f");
+        assert!(source.starts_with("pub fn f(result_1: i64, t1: i64) -> "));
+        assert!(source.contains("{ let int_1: i64 = neg(result_1)?; add(int_1, t1)? }"));
+        let source = c("let int = -result in int + t1", "f");
+        assert!(source.contains("const char *f(int64_t result_1, int64_t t1, int64_t *result)"));
+        assert!(source.contains("    int64_t int_1 = t1_1;\n"));
+        let source = c("let add(a) = a + 1 in add(x)", "f");
+        assert!(source.contains("static const char *f_add(int64_t a, int64_t b, int64_t *result)"));
+        assert!(
+            source.contains("static const char *f_add_1(size_t depth, int64_t a, int64_t *result)")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mismatch = |expected, found| EvalErrorKind::TypeMismatch { expected, found };
+        assert_eq!(
+            error("1 + (if x then 1 else 2)"),
+            EvalError {
+                kind: mismatch(Type::Boolean, Type::Number),
+                path: vec![Step::Right]
+            }
+        );
+        assert_eq!(
+            error("if x > 0 then 1 else x < 0").kind,
+            mismatch(Type::Number, Type::Boolean)
+        );
+        assert_eq!(
+            error("let f(a) = a > 0 in f(f(1))"),
+            EvalError {
+                kind: mismatch(Type::Number, Type::Boolean),
+                path: vec![Step::Body, Step::Arg(0)]
+            }
+        );
+        assert_eq!(
+            error("g(1)").kind,
+            EvalErrorKind::UnknownFunction(String::from("g"))
+        );
+        assert_eq!(
+            error("let f(a) = a in 1 + f(1, 2)"),
+            EvalError {
+                kind: EvalErrorKind::ArgumentCount {
+                    name: String::from("f"),
+                    expected: 1,
+                    found: 2
+                },
+                path: vec![Step::Body, Step::Right]
+            }
+        );
+        // Recursive functions get their type from the branches that do not recurse.
+        assert!(to_rust(
+            &"let f(n) = if n > 0 then f(n - 1) else n == 0 in f(x)"
+                .parse()
+                .unwrap(),
+            "f"
+        )
+        .is_ok());
+        // Or from how the result of a call is used.
+        assert_eq!(
+            error("let f(n) = if f(n - 1) then 1 else 2 in f(x)"),
+            EvalError {
+                kind: mismatch(Type::Boolean, Type::Number),
+                path: vec![Step::Bound]
+            }
+        );
+    }
+
+    #[test]
+    fn nested_functions() {
+        let source = rust(
+            "let f(n) = (let g(m) = f(m) in if n == 0 then 1 < 2 else g(n - 1)) in f(x)",
+            "f",
+        );
+        assert!(source.contains("fn g(depth: usize, m: i64) -> Result<bool, &'static str>"));
+        // Each body is resolved once, however deeply definitions nest.
+        let mut text = String::from("x");
+        for i in (0..40).rev() {
+            text = format!("let f{i}(a) = ({text}) + a in f{i}(1)");
+        }
+        let source = rust(&text, "f");
+        assert!(source.contains("fn f39("));
+    }
+}
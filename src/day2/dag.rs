@@ -0,0 +1,861 @@
+//! Expressions as a directed acyclic graph in an arena, where identical subexpressions are stored
+//! once and evaluated once per run.
+
+use super::{
+    Arithmetic, Comparison, Connective, Env, EvalError, EvalErrorKind, Expression, Function,
+    Operation, Step, UnaryOperation, Value,
+};
+use std::{borrow::Cow, collections::HashMap, ops::Index};
+
+/// A node of a [`Dag`], valid only in the DAG that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// A variable, function or parameter name interned in a [`Dag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Name(usize);
+
+/// An [`Expression`] whose subexpressions are [`NodeId`]s in the same [`Dag`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Op {
+        op: Operation,
+        left: NodeId,
+        right: NodeId,
+    },
+    Unary {
+        op: UnaryOperation,
+        operand: NodeId,
+    },
+    Compare {
+        op: Comparison,
+        left: NodeId,
+        right: NodeId,
+    },
+    Logic {
+        op: Connective,
+        left: NodeId,
+        right: NodeId,
+    },
+    Not(NodeId),
+    If {
+        cond: NodeId,
+        then: NodeId,
+        otherwise: NodeId,
+    },
+    Let {
+        name: Name,
+        value: NodeId,
+        body: NodeId,
+    },
+    /// Like [`Expression::Define`], with the parameters and body of the function inline.
+    Define {
+        name: Name,
+        params: Vec<Name>,
+        function: NodeId,
+        body: NodeId,
+    },
+    Call {
+        name: Name,
+        args: Vec<NodeId>,
+    },
+    Value(i64),
+    Var(Name),
+}
+
+/// What evaluation needs to know about a node.
+#[derive(Debug, Clone)]
+struct Info {
+    /// How many times the node is a child of other nodes.
+    uses: usize,
+    /// The names the node refers to without binding them, sorted.
+    free: Vec<Name>,
+}
+
+/// An arena of hash-consed [`Node`]s: adding a node equal to an existing one returns the existing
+/// one, so every distinct subexpression is stored once however often it occurs.
+#[derive(Debug, Clone, Default)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    info: Vec<Info>,
+    ids: HashMap<Node, NodeId>,
+    names: Vec<String>,
+    name_ids: HashMap<String, Name>,
+}
+
+impl Index<NodeId> for Dag {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+
+/// The sorted union of `a` and `b`.
+fn union(a: &[Name], b: &[Name]) -> Vec<Name> {
+    let mut out = [a, b].concat();
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// `names` without `bound`.
+fn without(names: &[Name], bound: &[Name]) -> Vec<Name> {
+    names
+        .iter()
+        .filter(|name| !bound.contains(name))
+        .copied()
+        .collect()
+}
+
+impl Dag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of distinct nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Intern `name`, returning the existing [`Name`] if it was interned before.
+    pub fn name(&mut self, name: &str) -> Name {
+        if let Some(&id) = self.name_ids.get(name) {
+            return id;
+        }
+        let id = Name(self.names.len());
+        self.names.push(String::from(name));
+        self.name_ids.insert(String::from(name), id);
+        id
+    }
+
+    /// The text of an interned name.
+    pub fn resolve(&self, name: Name) -> &str {
+        &self.names[name.0]
+    }
+
+    /// Intern `node`, returning the existing [`NodeId`] if an equal node was interned before.
+    ///
+    /// Panics if `node` refers to nodes or names that are not in this DAG.
+    pub fn intern(&mut self, node: Node) -> NodeId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let free = |id: NodeId| self.info[id.0].free.as_slice();
+        let free = match &node {
+            Node::Value(_) => Vec::new(),
+            Node::Var(name) => vec![*name],
+            Node::Unary { operand, .. } | Node::Not(operand) => free(*operand).to_vec(),
+            Node::Op { left, right, .. }
+            | Node::Compare { left, right, .. }
+            | Node::Logic { left, right, .. } => union(free(*left), free(*right)),
+            Node::If {
+                cond,
+                then,
+                otherwise,
+            } => union(&union(free(*cond), free(*then)), free(*otherwise)),
+            Node::Let { name, value, body } => union(free(*value), &without(free(*body), &[*name])),
+            Node::Define {
+                name,
+                params,
+                function,
+                body,
+            } => {
+                let function = without(free(*function), &[params.as_slice(), &[*name]].concat());
+                union(&function, &without(free(*body), &[*name]))
+            }
+            Node::Call { name, args } => args
+                .iter()
+                .fold(vec![*name], |names, arg| union(&names, free(*arg))),
+        };
+        assert!(
+            free.iter().all(|name| name.0 < self.names.len()),
+            "name from another DAG"
+        );
+        for child in children(&node) {
+            self.info[child.0].uses += 1;
+        }
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.info.push(Info { uses: 0, free });
+        self.ids.insert(node, id);
+        id
+    }
+
+    /// Add `e`, sharing its subexpressions with each other and with those already added.
+    pub fn add(&mut self, e: &Expression) -> NodeId {
+        enum Visit<'e> {
+            Enter(&'e Expression),
+            Build(&'e Expression),
+        }
+        let mut visits = vec![Visit::Enter(e)];
+        let mut ids: Vec<NodeId> = Vec::new();
+        while let Some(visit) = visits.pop() {
+            let e = match visit {
+                Visit::Enter(e) => {
+                    visits.push(Visit::Build(e));
+                    visits.extend(
+                        e.children()
+                            .into_iter()
+                            .rev()
+                            .map(|(_, child)| Visit::Enter(child)),
+                    );
+                    continue;
+                }
+                Visit::Build(e) => e,
+            };
+            let mut children = ids.split_off(ids.len() - e.children().len()).into_iter();
+            let mut next = || children.next().expect("missing child");
+            let node = match e {
+                Expression::Value(val) => Node::Value(*val),
+                Expression::Var(name) => Node::Var(self.name(name)),
+                Expression::Op { op, .. } => Node::Op {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Expression::Unary { op, .. } => Node::Unary {
+                    op: *op,
+                    operand: next(),
+                },
+                Expression::Compare { op, .. } => Node::Compare {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Expression::Logic { op, .. } => Node::Logic {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Expression::Not(_) => Node::Not(next()),
+                Expression::If { .. } => Node::If {
+                    cond: next(),
+                    then: next(),
+                    otherwise: next(),
+                },
+                Expression::Let { name, .. } => Node::Let {
+                    name: self.name(name),
+                    value: next(),
+                    body: next(),
+                },
+                Expression::Define { name, function, .. } => Node::Define {
+                    name: self.name(name),
+                    params: function.params.iter().map(|p| self.name(p)).collect(),
+                    function: next(),
+                    body: next(),
+                },
+                Expression::Call { name, .. } => Node::Call {
+                    name: self.name(name),
+                    args: children.by_ref().collect(),
+                },
+            };
+            ids.push(self.intern(node));
+        }
+        ids.pop().expect("missing result")
+    }
+
+    /// The tree that `id` stands for, with every shared subexpression copied out.
+    ///
+    /// This can be exponentially larger than the DAG.
+    pub fn to_expression(&self, id: NodeId) -> Expression {
+        let mut visits = vec![(id, false)];
+        let mut built: Vec<Expression> = Vec::new();
+        while let Some((id, ready)) = visits.pop() {
+            let node = &self[id];
+            if !ready {
+                visits.push((id, true));
+                visits.extend(children(node).into_iter().rev().map(|child| (child, false)));
+                continue;
+            }
+            let mut children = built
+                .split_off(built.len() - children(node).len())
+                .into_iter();
+            let mut next = || Box::new(children.next().expect("missing child"));
+            let name = |name: &Name| String::from(self.resolve(*name));
+            built.push(match node {
+                Node::Value(val) => Expression::Value(*val),
+                Node::Var(var) => Expression::Var(name(var)),
+                Node::Op { op, .. } => Expression::Op {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Node::Unary { op, .. } => Expression::Unary {
+                    op: *op,
+                    operand: next(),
+                },
+                Node::Compare { op, .. } => Expression::Compare {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Node::Logic { op, .. } => Expression::Logic {
+                    op: *op,
+                    left: next(),
+                    right: next(),
+                },
+                Node::Not(_) => Expression::Not(next()),
+                Node::If { .. } => Expression::If {
+                    cond: next(),
+                    then: next(),
+                    otherwise: next(),
+                },
+                Node::Let { name: var, .. } => Expression::Let {
+                    name: name(var),
+                    value: next(),
+                    body: next(),
+                },
+                Node::Define {
+                    name: function_name,
+                    params,
+                    ..
+                } => Expression::Define {
+                    name: name(function_name),
+                    function: Box::new(Function {
+                        params: params.iter().map(name).collect(),
+                        body: *next(),
+                    }),
+                    body: next(),
+                },
+                Node::Call { name: callee, .. } => Expression::Call {
+                    name: name(callee),
+                    args: children.collect(),
+                },
+            });
+        }
+        built.pop().expect("missing result")
+    }
+
+    /// Evaluate `root` with [`Arithmetic::Checked`], looking up variables in `env`.
+    pub fn eval(&self, root: NodeId, env: &Env) -> Result<Value, EvalError> {
+        self.eval_arithmetic(root, env, Arithmetic::Checked)
+    }
+
+    /// Evaluate `root` like [`Arithmetic::eval`] evaluates the corresponding tree.
+    ///
+    /// A node used in several places is evaluated once for each set of bindings its free names
+    /// can see, so a subexpression repeated anywhere outside `let`s and functions is evaluated once
+    /// per run. Functions from `env` are added to a copy of the DAG when first called. An error is
+    /// reported at the first place the failing node occurs.
+    pub fn eval_arithmetic(
+        &self,
+        root: NodeId,
+        env: &Env,
+        arithmetic: Arithmetic,
+    ) -> Result<Value, EvalError> {
+        let mut dag = Cow::Borrowed(self);
+        let mut tasks = vec![Task::Eval(root)];
+        let mut values: Vec<Value> = Vec::new();
+        let mut locals = Locals::default();
+        let mut cache: HashMap<(NodeId, usize), Value> = HashMap::new();
+        // The parameters and body of each function from `env` called so far.
+        let mut env_functions: HashMap<Name, (Vec<Name>, NodeId)> = HashMap::new();
+        let call_limit = env.call_limit();
+        let mut calls = 0;
+        let mut path = Vec::new();
+        while let Some(task) = tasks.pop() {
+            let mut pop = || values.pop().expect("missing operand");
+            let result = match task {
+                Task::Enter(step) => {
+                    if calls == 0 {
+                        path.push(step);
+                    }
+                    continue;
+                }
+                Task::Leave => {
+                    if calls == 0 {
+                        path.pop();
+                    }
+                    continue;
+                }
+                Task::Store(key) => {
+                    cache.insert(key, *values.last().expect("missing result"));
+                    continue;
+                }
+                Task::Eval(id) => {
+                    let info = &dag.info[id.0];
+                    if info.uses > 1 {
+                        let key = (id, locals.scope_of(&info.free));
+                        if let Some(&val) = cache.get(&key) {
+                            values.push(val);
+                            continue;
+                        }
+                        tasks.push(Task::Store(key));
+                    }
+                    match &dag[id] {
+                        Node::Value(val) => Ok(Value::Num(*val)),
+                        Node::Var(name) => match locals.var(*name) {
+                            Some(val) => Ok(val),
+                            None => {
+                                let name = dag.resolve(*name);
+                                env.get(name)
+                                    .map(Value::Num)
+                                    .ok_or_else(|| EvalErrorKind::UnboundVariable(name.into()))
+                            }
+                        },
+                        Node::Unary { op, operand } => {
+                            tasks.extend(descend(
+                                Task::ApplyUnary(*op),
+                                [(Step::Operand, *operand)],
+                            ));
+                            continue;
+                        }
+                        Node::Not(operand) => {
+                            tasks.extend(descend(Task::Not, [(Step::Operand, *operand)]));
+                            continue;
+                        }
+                        Node::Op { op, left, right } => {
+                            let operands = [(Step::Left, *left), (Step::Right, *right)];
+                            tasks.extend(descend(Task::Apply(*op), operands));
+                            continue;
+                        }
+                        Node::Compare { op, left, right } => {
+                            let operands = [(Step::Left, *left), (Step::Right, *right)];
+                            tasks.extend(descend(Task::Compare(*op), operands));
+                            continue;
+                        }
+                        Node::Logic { op, left, right } => {
+                            tasks
+                                .extend(descend(Task::Connect(*op, *right), [(Step::Left, *left)]));
+                            continue;
+                        }
+                        Node::If {
+                            cond,
+                            then,
+                            otherwise,
+                        } => {
+                            let branch = Task::Branch(*then, *otherwise);
+                            tasks.extend(descend(branch, [(Step::Cond, *cond)]));
+                            continue;
+                        }
+                        Node::Let { name, value, body } => {
+                            let bind = Task::Bind(*name, *body);
+                            tasks.extend(descend(bind, [(Step::Bound, *value)]));
+                            continue;
+                        }
+                        Node::Define { name, body, .. } => {
+                            let outer = locals.push(*name, Local::Function(Callee::Defined(id)));
+                            tasks.extend(descend(Task::Unbind(outer), [(Step::Body, *body)]));
+                            continue;
+                        }
+                        Node::Call { name, args } => {
+                            let (name, args) = (*name, args.clone());
+                            let found = match locals.function(name) {
+                                Some(found) => Some(found),
+                                None => env.function(dag.resolve(name)).map(|function| {
+                                    env_functions.entry(name).or_insert_with(|| {
+                                        let dag = dag.to_mut();
+                                        let params =
+                                            function.params.iter().map(|p| dag.name(p)).collect();
+                                        (params, dag.add(&function.body))
+                                    });
+                                    (Callee::Env(name), None)
+                                }),
+                            };
+                            let params = |callee| signature(&dag, &env_functions, callee).0.len();
+                            match found {
+                                None => {
+                                    Err(EvalErrorKind::UnknownFunction(dag.resolve(name).into()))
+                                }
+                                Some((callee, _)) if params(callee) != args.len() => {
+                                    Err(EvalErrorKind::ArgumentCount {
+                                        name: dag.resolve(name).into(),
+                                        expected: params(callee),
+                                        found: args.len(),
+                                    })
+                                }
+                                Some((callee, scope)) => {
+                                    let args = args.iter().enumerate();
+                                    let operands = args.map(|(i, arg)| (Step::Arg(i), *arg));
+                                    tasks.extend(descend(Task::Call(callee, scope), operands));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                }
+                Task::ApplyUnary(op) => pop()
+                    .number()
+                    .and_then(|val| arithmetic.apply_unary(op, val))
+                    .map(Value::Num),
+                Task::Apply(op) => {
+                    let right = pop();
+                    let left = pop();
+                    left.number()
+                        .and_then(|left| arithmetic.apply(op, left, right.number()?))
+                        .map(Value::Num)
+                }
+                Task::Compare(op) => {
+                    let right = pop();
+                    pop().compare(op, right).map(Value::Bool)
+                }
+                Task::Not => pop().boolean().map(|val| Value::Bool(!val)),
+                Task::Connect(op, right) => match pop().boolean() {
+                    Ok(val) if val != op.short_circuit() => {
+                        tasks.extend(descend(Task::CheckBool, [(Step::Right, right)]));
+                        continue;
+                    }
+                    result => result.map(Value::Bool),
+                },
+                Task::CheckBool => pop().boolean().map(Value::Bool),
+                Task::Branch(then, otherwise) => match pop().boolean() {
+                    Ok(cond) => {
+                        let branch = if cond {
+                            (Step::Then, then)
+                        } else {
+                            (Step::Else, otherwise)
+                        };
+                        tasks.extend(descend_last([branch]));
+                        continue;
+                    }
+                    Err(kind) => Err(kind),
+                },
+                Task::Bind(name, body) => {
+                    let value = pop();
+                    let outer = locals.push(name, Local::Var(value));
+                    tasks.extend(descend(Task::Unbind(outer), [(Step::Body, body)]));
+                    continue;
+                }
+                Task::Unbind(outer) => {
+                    locals.pop(outer);
+                    continue;
+                }
+                Task::Call(..) if calls == call_limit => Err(EvalErrorKind::CallDepth(call_limit)),
+                Task::Call(callee, scope) => {
+                    let (params, body) = signature(&dag, &env_functions, callee);
+                    let args = values.split_off(values.len() - params.len());
+                    let (caller, len) = (locals.innermost, locals.bindings.len());
+                    locals.innermost = scope;
+                    for (param, arg) in params.iter().zip(args) {
+                        locals.push(*param, Local::Var(arg));
+                    }
+                    calls += 1;
+                    tasks.extend([Task::Return(caller, len), Task::Eval(body)]);
+                    continue;
+                }
+                Task::Return(caller, len) => {
+                    locals.bindings.truncate(len);
+                    locals.innermost = caller;
+                    calls -= 1;
+                    continue;
+                }
+            };
+            match result {
+                Ok(val) => values.push(val),
+                Err(kind) => return Err(EvalError { kind, path }),
+            }
+        }
+        Ok(values.pop().expect("missing result"))
+    }
+}
+
+/// The parameters and body of a function being called.
+fn signature<'a>(
+    dag: &'a Dag,
+    env_functions: &'a HashMap<Name, (Vec<Name>, NodeId)>,
+    callee: Callee,
+) -> (&'a [Name], NodeId) {
+    match callee {
+        Callee::Defined(define) => match &dag[define] {
+            Node::Define {
+                params, function, ..
+            } => (params, *function),
+            _ => unreachable!("function from a node other than a define"),
+        },
+        Callee::Env(name) => {
+            let (params, body) = &env_functions[&name];
+            (params, *body)
+        }
+    }
+}
+
+/// The children of `node`, in order.
+fn children(node: &Node) -> Vec<NodeId> {
+    match node {
+        Node::Value(_) | Node::Var(_) => Vec::new(),
+        Node::Unary { operand, .. } | Node::Not(operand) => vec![*operand],
+        Node::Op { left, right, .. }
+        | Node::Compare { left, right, .. }
+        | Node::Logic { left, right, .. } => vec![*left, *right],
+        Node::If {
+            cond,
+            then,
+            otherwise,
+        } => vec![*cond, *then, *otherwise],
+        Node::Let { value, body, .. } => vec![*value, *body],
+        Node::Define { function, body, .. } => vec![*function, *body],
+        Node::Call { args, .. } => args.clone(),
+    }
+}
+
+/// Tasks to evaluate each of `operands` in turn, then do `then`, in the order to push them.
+fn descend(
+    then: Task,
+    operands: impl IntoIterator<Item = (Step, NodeId), IntoIter: DoubleEndedIterator>,
+) -> impl Iterator<Item = Task> {
+    std::iter::once(then).chain(descend_last(operands))
+}
+
+/// Tasks to evaluate each of `operands` in turn, in the order to push them.
+fn descend_last(
+    operands: impl IntoIterator<Item = (Step, NodeId), IntoIter: DoubleEndedIterator>,
+) -> impl Iterator<Item = Task> {
+    operands
+        .into_iter()
+        .rev()
+        .flat_map(|(step, id)| [Task::Leave, Task::Eval(id), Task::Enter(step)])
+}
+
+/// Pending work for [`Dag::eval_arithmetic`], like the tasks of the tree evaluator.
+enum Task {
+    Eval(NodeId),
+    /// Remember the value on top of the stack as that of a node in a scope.
+    Store((NodeId, usize)),
+    ApplyUnary(UnaryOperation),
+    Apply(Operation),
+    Compare(Comparison),
+    Not,
+    Connect(Connective, NodeId),
+    CheckBool,
+    Branch(NodeId, NodeId),
+    Bind(Name, NodeId),
+    Unbind(Option<usize>),
+    Call(Callee, Option<usize>),
+    Return(Option<usize>, usize),
+    Enter(Step),
+    Leave,
+}
+
+/// A function being called.
+#[derive(Clone, Copy)]
+enum Callee {
+    /// Defined by this [`Node::Define`].
+    Defined(NodeId),
+    /// From the [`Env`].
+    Env(Name),
+}
+
+enum Local {
+    Var(Value),
+    Function(Callee),
+}
+
+/// A binding made during evaluation, with a serial number unique within the run.
+struct Binding {
+    name: Name,
+    local: Local,
+    outer: Option<usize>,
+    serial: usize,
+}
+
+/// The bindings of an evaluation, linked into lexical scopes like those of the tree evaluator.
+#[derive(Default)]
+struct Locals {
+    bindings: Vec<Binding>,
+    innermost: Option<usize>,
+    serials: usize,
+}
+
+impl Locals {
+    fn push(&mut self, name: Name, local: Local) -> Option<usize> {
+        let outer = self.innermost;
+        self.serials += 1;
+        self.bindings.push(Binding {
+            name,
+            local,
+            outer,
+            serial: self.serials,
+        });
+        self.innermost = Some(self.bindings.len() - 1);
+        outer
+    }
+
+    fn pop(&mut self, outer: Option<usize>) {
+        self.bindings.pop();
+        self.innermost = outer;
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Binding> {
+        std::iter::successors(self.innermost, |&i| self.bindings[i].outer)
+            .map(|i| &self.bindings[i])
+    }
+
+    fn var(&self, name: Name) -> Option<Value> {
+        self.visible().find_map(|binding| match binding.local {
+            Local::Var(val) if binding.name == name => Some(val),
+            _ => None,
+        })
+    }
+
+    /// The function bound to `name`, with the binding its body continues from.
+    fn function(&self, name: Name) -> Option<(Callee, Option<usize>)> {
+        std::iter::successors(self.innermost, |&i| self.bindings[i].outer).find_map(|i| match self
+            .bindings[i]
+            .local
+        {
+            Local::Function(callee) if self.bindings[i].name == name => Some((callee, Some(i))),
+            _ => None,
+        })
+    }
+
+    /// The serial of the innermost visible binding of any of the sorted `names`, or zero if they
+    /// all come from the [`Env`]. Two evaluations of a node with the same scope see the same
+    /// values for all its free names, as each binding is made inside a fixed chain of others.
+    fn scope_of(&self, names: &[Name]) -> usize {
+        if names.is_empty() {
+            return 0;
+        }
+        self.visible()
+            .find(|binding| names.binary_search(&binding.name).is_ok())
+            .map_or(0, |binding| binding.serial)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{deep_sum, eval_with, parse::parse};
+
+    fn env() -> Env<'static> {
+        let mut env = Env::new();
+        env.bind("x", 5).bind("y", -3);
+        env.define(
+            "sq",
+            Function {
+                params: vec![String::from("a")],
+                body: parse("a * a").unwrap(),
+            },
+        );
+        env
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in [
+            "1 + 2 * x",
+            "(x * y + 1) * (x * y + 1) - x * y",
+            "if x > y && !(x == 1) then abs(x - y) else -(x - y)",
+            "let x = x + 1 in x * x + (let x = 2 in x * x) + x * x",
+            "let f(a, b) = a * b + x in f(1, 2) + f(f(1, 2), sq(y))",
+            "let down(n) = if n <= 0 then 0 else down(n - 1) in down(x) + down(x)",
+            "min(x, y) ^ 2 % 7 + max(sq(x), sq(x))",
+            "z",
+            "f(1)",
+            "sq(1, 2)",
+            "1 / (x - 5) + 1 / (x - 5)",
+        ] {
+            let e = parse(text).unwrap();
+            let mut dag = Dag::new();
+            let root = dag.add(&e);
+            assert_eq!(dag.to_expression(root), e, "{text}");
+            assert_eq!(dag.eval(root, &env()), eval_with(&e, &env()), "{text}");
+        }
+    }
+
+    #[test]
+    fn sharing() {
+        let mut dag = Dag::new();
+        let e = parse("(x * y + 1) * (x * y + 1) - x * y").unwrap();
+        let root = dag.add(&e);
+        // x, y, x * y, 1, x * y + 1, the product and the difference.
+        assert_eq!(dag.len(), 7);
+        assert_eq!(dag.add(&e.clone()), root);
+        assert_eq!(dag.len(), 7);
+        let other = dag.add(&parse("1 + x * y").unwrap());
+        assert_ne!(other, root);
+        assert_eq!(dag.len(), 8);
+        let x = dag.name("x");
+        let (left, right) = (dag.add(&parse("x").unwrap()), dag.add(&parse("y").unwrap()));
+        let product = dag.intern(Node::Op {
+            op: Operation::Mul,
+            left,
+            right,
+        });
+        assert_eq!(dag.len(), 8);
+        assert_eq!(dag.resolve(x), "x");
+        assert_eq!(dag.to_expression(product), parse("x * y").unwrap());
+    }
+
+    #[test]
+    fn evaluated_once() {
+        // A tree of 2^60 additions, which could not be evaluated node by node.
+        let mut dag = Dag::new();
+        let x = dag.name("x");
+        let mut e = dag.intern(Node::Var(x));
+        for _ in 0..60 {
+            e = dag.intern(Node::Op {
+                op: Operation::Add,
+                left: e,
+                right: e,
+            });
+        }
+        assert_eq!(dag.len(), 61);
+        assert_eq!(dag.eval(e, &env()), Ok(Value::Num(5 << 60)));
+        let mut env = Env::new();
+        env.bind("x", 8);
+        assert_eq!(dag.eval(e, &env).unwrap_err().kind, EvalErrorKind::Overflow);
+        assert_eq!(
+            dag.eval_arithmetic(e, &env, Arithmetic::Wrapping),
+            Ok(Value::Num(i64::MIN))
+        );
+
+        // Each call of a function evaluates its body again, but repeats within one call do not.
+        let mut dag = Dag::new();
+        let e = parse("let f(n) = if n == 0 then 1 else f(n - 1) + f(n - 1) in f(62)").unwrap();
+        let root = dag.add(&e);
+        assert_eq!(dag.eval(root, &Env::new()), Ok(Value::Num(1 << 62)));
+    }
+
+    #[test]
+    fn scopes() {
+        // The same nodes under different bindings must not share values.
+        for text in [
+            "(let x = 1 in x + x) + (x + x)",
+            "let x = x + x in (let x = x + x in x + x) + (x + x)",
+            "let f(a) = a * a + x in f(2) + f(3) + (x * x + x) + f(x)",
+            "let g(x) = x + y in let y = 100 in g(1) + (1 + y) + g(y)",
+            "sq(x + 1) + sq(y) + sq(x + 1) + (let a = 2 in a * a)",
+            "let f() = x + 1 in (let x = 10 in f() + (x + 1)) + f()",
+        ] {
+            let e = parse(text).unwrap();
+            let mut dag = Dag::new();
+            let root = dag.add(&e);
+            assert_eq!(dag.eval(root, &env()), eval_with(&e, &env()), "{text}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for text in [
+            "(x + 1) * 2 + 1 / (y + 3) + 1 / (y + 3)",
+            "let f(a) = a / 0 in 1 + f(2)",
+            "if x then 1 else 2",
+            "1 + (x > 2)",
+            "let f(n) = f(n) in f(1)",
+            "sq(undefined)",
+        ] {
+            let e = parse(text).unwrap();
+            let mut dag = Dag::new();
+            let root = dag.add(&e);
+            let err = dag.eval(root, &env()).unwrap_err();
+            assert_eq!(Err(err), eval_with(&e, &env()), "{text}");
+        }
+    }
+
+    #[test]
+    fn deep() {
+        let e = deep_sum(100_000, true);
+        let mut dag = Dag::new();
+        let root = dag.add(&e);
+        assert_eq!(dag.len(), 100_001);
+        assert_eq!(dag.eval(root, &Env::new()), Ok(Value::Num(100_001)));
+        // Comparing deep trees would recurse, so evaluate the copy instead.
+        let copy = dag.to_expression(root);
+        assert_eq!(eval_with(&copy, &Env::new()), Ok(Value::Num(100_001)));
+    }
+}
@@ -0,0 +1,451 @@
+//! Symbolic differentiation of an [`Expression`] with respect to one variable.
+//!
+//! `abs`, `min`, `max` and `%` are differentiated piecewise, so the result is only meaningful
+//! away from the points where they switch. Comparisons and connectives are constant almost
+//! everywhere and have no derivative.
+
+use super::{
+    simplify::simplify_real,
+    visit::{self, Next, Visitor},
+    Comparison, Expression, Operation, UnaryOperation,
+};
+use std::{collections::HashSet, convert::Infallible, fmt};
+
+/// Why an expression could not be differentiated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerivativeError {
+    /// A power whose exponent depends on the variable, which would need a logarithm.
+    VariableExponent,
+    /// A call to a function, whose derivative is not known.
+    Call(String),
+}
+
+impl fmt::Display for DerivativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DerivativeError::VariableExponent => write!(f, "exponent depends on the variable"),
+            DerivativeError::Call(name) => write!(f, "cannot differentiate a call to `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for DerivativeError {}
+
+/// The derivative of `e` with respect to `var`, simplified without truncating divisions, as it
+/// is meant for real numbers.
+///
+/// Other variables are constants. A `let` whose value depends on `var` gets a second binding for
+/// the derivative of that value, named after it, such as `d_y` for `y`.
+pub fn derivative(e: &Expression, var: &str) -> Result<Expression, DerivativeError> {
+    let mut names = Names(HashSet::new());
+    let Ok(()) = visit::visit(e, &mut names);
+    let mut differentiator = Differentiator {
+        used: names.0,
+        derivatives: vec![(var, Some(Expression::Value(1)))],
+        derived: Vec::new(),
+    };
+    visit::visit(e, &mut differentiator).map_err(|(err, _)| err)?;
+    let d = differentiator.derived.pop().expect("missing derivative");
+    Ok(simplify_real(&d.unwrap_or(Expression::Value(0))))
+}
+
+struct Differentiator<'e> {
+    /// Every name in the expression, and those picked for derivatives so far.
+    used: HashSet<String>,
+    /// The derivative of each variable in scope that depends on the one being differentiated
+    /// for, innermost last.
+    derivatives: Vec<(&'e str, Option<Expression>)>,
+    /// The derivatives of the subexpressions left so far that are still needed, where `None`
+    /// is zero.
+    derived: Vec<Option<Expression>>,
+}
+
+impl<'e> Visitor<'e> for Differentiator<'e> {
+    type Error = DerivativeError;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), DerivativeError> {
+        match e {
+            Expression::Call { name, .. } => Err(DerivativeError::Call(name.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Goes only into the subexpressions whose derivatives make up that of `e`, so not into
+    /// conditions, the bodies of functions, or anything compared.
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, DerivativeError> {
+        let child = match (e, visited) {
+            (Expression::Unary { .. } | Expression::Op { .. }, _) => e.child(visited),
+            (Expression::If { .. }, _) => e.child(visited + 1),
+            (Expression::Let { name, .. }, 1) => {
+                // The derivative of the value is bound before `name` is, so that both see the
+                // bindings from outside the `let`.
+                let depends = matches!(self.derived.last(), Some(Some(_)));
+                let fresh = depends.then(|| Expression::Var(self.fresh(name)));
+                self.derivatives.push((name, fresh));
+                e.child(1)
+            }
+            (Expression::Let { .. }, _) => e.child(visited),
+            (Expression::Define { .. }, 0) => e.child(1),
+            _ => None,
+        };
+        Ok(match child {
+            Some((step, child)) => Next::Child(step, child),
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), DerivativeError> {
+        let d = self.derive(e)?;
+        self.derived.push(d);
+        Ok(())
+    }
+}
+
+impl<'e> Differentiator<'e> {
+    /// The derivative of `e`, or `None` if it is zero, from those of its subexpressions.
+    fn derive(&mut self, e: &'e Expression) -> Result<Option<Expression>, DerivativeError> {
+        let mut pop = || self.derived.pop().expect("missing derivative");
+        let d = match e {
+            Expression::Value(_)
+            | Expression::Compare { .. }
+            | Expression::Logic { .. }
+            | Expression::Not(_) => None,
+            Expression::Var(name) => self
+                .derivatives
+                .iter()
+                .rev()
+                .find(|(var, _)| var == name)
+                .and_then(|(_, d)| d.clone()),
+            Expression::Unary { op, operand } => {
+                let Some(d) = pop() else {
+                    return Ok(None);
+                };
+                Some(match op {
+                    UnaryOperation::Neg => neg(d),
+                    UnaryOperation::Abs => choose(
+                        compare(Comparison::Lt, (**operand).clone(), Expression::Value(0)),
+                        neg(d.clone()),
+                        d,
+                    ),
+                })
+            }
+            Expression::Op { op, left, right } => {
+                let (u, v) = (&**left, &**right);
+                let dv = pop();
+                let du = pop();
+                if du.is_none() && dv.is_none() {
+                    return Ok(None);
+                }
+                Some(match op {
+                    Operation::Add => sum(du, dv),
+                    Operation::Sub => difference(du, dv),
+                    Operation::Mul => sum(
+                        du.map(|du| binary(Operation::Mul, du, v.clone())),
+                        dv.map(|dv| binary(Operation::Mul, u.clone(), dv)),
+                    ),
+                    Operation::Div => match dv {
+                        None => binary(Operation::Div, du.unwrap(), v.clone()),
+                        Some(dv) => binary(
+                            Operation::Div,
+                            difference(
+                                du.map(|du| binary(Operation::Mul, du, v.clone())),
+                                Some(binary(Operation::Mul, u.clone(), dv)),
+                            ),
+                            binary(Operation::Pow, v.clone(), Expression::Value(2)),
+                        ),
+                    },
+                    // `u % v` is `u - v * q` for the truncated quotient `q`, which is constant
+                    // between the points where it jumps.
+                    Operation::Rem => {
+                        let quotient = binary(
+                            Operation::Div,
+                            binary(
+                                Operation::Sub,
+                                u.clone(),
+                                binary(Operation::Rem, u.clone(), v.clone()),
+                            ),
+                            v.clone(),
+                        );
+                        difference(du, dv.map(|dv| binary(Operation::Mul, dv, quotient)))
+                    }
+                    Operation::Pow => {
+                        let (Some(du), None) = (du, dv) else {
+                            return Err(DerivativeError::VariableExponent);
+                        };
+                        let lower = binary(Operation::Sub, v.clone(), Expression::Value(1));
+                        binary(
+                            Operation::Mul,
+                            binary(
+                                Operation::Mul,
+                                v.clone(),
+                                binary(Operation::Pow, u.clone(), lower),
+                            ),
+                            du,
+                        )
+                    }
+                    Operation::Min | Operation::Max => {
+                        let op = if *op == Operation::Min {
+                            Comparison::Le
+                        } else {
+                            Comparison::Ge
+                        };
+                        choose(
+                            compare(op, u.clone(), v.clone()),
+                            du.unwrap_or(Expression::Value(0)),
+                            dv.unwrap_or(Expression::Value(0)),
+                        )
+                    }
+                })
+            }
+            Expression::If { cond, .. } => match (pop(), pop()) {
+                (None, None) => None,
+                (otherwise, then) => Some(choose(
+                    (**cond).clone(),
+                    then.unwrap_or(Expression::Value(0)),
+                    otherwise.unwrap_or(Expression::Value(0)),
+                )),
+            },
+            Expression::Let { name, value, .. } => {
+                let body = pop();
+                let dv = pop();
+                let fresh = match self.derivatives.pop() {
+                    Some((_, Some(Expression::Var(ref fresh)))) => Some(fresh.clone()),
+                    _ => None,
+                };
+                let Some(body) = body else {
+                    return Ok(None);
+                };
+                let inner = Expression::Let {
+                    name: name.clone(),
+                    value: value.clone(),
+                    body: Box::new(body),
+                };
+                match (fresh, dv) {
+                    (Some(fresh), Some(dv)) => Some(Expression::Let {
+                        name: fresh,
+                        value: Box::new(dv),
+                        body: Box::new(inner),
+                    }),
+                    _ => Some(inner),
+                }
+            }
+            Expression::Define { name, function, .. } => pop().map(|body| Expression::Define {
+                name: name.clone(),
+                function: function.clone(),
+                body: Box::new(body),
+            }),
+            Expression::Call { name, .. } => unreachable!("`{name}` is rejected on entering it"),
+        };
+        Ok(d)
+    }
+
+    /// A name for the derivative of `name` that is not used anywhere else.
+    fn fresh(&mut self, name: &str) -> String {
+        let mut fresh = format!("d_{name}");
+        while self.used.contains(&fresh) {
+            fresh.push('_');
+        }
+        self.used.insert(fresh.clone());
+        fresh
+    }
+}
+
+/// Collects every variable and function name in an expression.
+struct Names(HashSet<String>);
+
+impl<'e> Visitor<'e> for Names {
+    type Error = Infallible;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        match e {
+            Expression::Var(name)
+            | Expression::Let { name, .. }
+            | Expression::Call { name, .. } => {
+                self.0.insert(name.clone());
+            }
+            Expression::Define { name, function, .. } => {
+                self.0.insert(name.clone());
+                self.0.extend(function.params.iter().cloned());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// `a + b`, where `None` is zero.
+fn sum(a: Option<Expression>, b: Option<Expression>) -> Expression {
+    match (a, b) {
+        (Some(a), Some(b)) => binary(Operation::Add, a, b),
+        (Some(e), None) | (None, Some(e)) => e,
+        (None, None) => Expression::Value(0),
+    }
+}
+
+/// `a - b`, where `None` is zero.
+fn difference(a: Option<Expression>, b: Option<Expression>) -> Expression {
+    match (a, b) {
+        (Some(a), Some(b)) => binary(Operation::Sub, a, b),
+        (Some(a), None) => a,
+        (None, Some(b)) => neg(b),
+        (None, None) => Expression::Value(0),
+    }
+}
+
+fn neg(operand: Expression) -> Expression {
+    Expression::Unary {
+        op: UnaryOperation::Neg,
+        operand: Box::new(operand),
+    }
+}
+
+fn binary(op: Operation, left: Expression, right: Expression) -> Expression {
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn compare(op: Comparison, left: Expression, right: Expression) -> Expression {
+    Expression::Compare {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn choose(cond: Expression, then: Expression, otherwise: Expression) -> Expression {
+    Expression::If {
+        cond: Box::new(cond),
+        then: Box::new(then),
+        otherwise: Box::new(otherwise),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{number::eval_number, Env, Value};
+
+    fn derived(text: &str, var: &str) -> Result<String, DerivativeError> {
+        derivative(&text.parse().unwrap(), var).map(|d| d.to_string())
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(derived("x * x", "x"), Ok(String::from("x + x")));
+        assert_eq!(
+            derived("3 * x ^ 2 + 2 * x + 1", "x"),
+            Ok(String::from("3 * (2 * x) + 2"))
+        );
+        assert_eq!(derived("y * 3 + z", "x"), Ok(String::from("0")));
+        assert_eq!(derived("x / y", "y"), Ok(String::from("-x / y ^ 2")));
+        assert_eq!(derived("x / 2", "x"), Ok(String::from("1 / 2")));
+        assert_eq!(derived("3 * x / 4", "x"), Ok(String::from("3 / 4")));
+        assert_eq!(derived("6 * x / 3", "x"), Ok(String::from("2")));
+        assert_eq!(
+            derived("if 1 / 2 > 0 then x else 2 * x", "x"),
+            Ok(String::from("1"))
+        );
+        assert_eq!(
+            derived("x ^ (1 / 2)", "x"),
+            Ok(String::from("1 / 2 * x ^ (1 / 2 - 1)"))
+        );
+        assert_eq!(
+            derived("abs(x)", "x"),
+            Ok(String::from("if x < 0 then -1 else 1"))
+        );
+        assert_eq!(
+            derived("if x > 0 then x * y else 2", "x"),
+            Ok(String::from("if x > 0 then y else 0"))
+        );
+        assert_eq!(
+            derived("let y = x * x in y + 1", "x"),
+            Ok(String::from("let d_y = x + x in let y = x * x in d_y"))
+        );
+        assert_eq!(
+            derived("let d_y = 1 in let y = x in y * d_y", "x"),
+            Ok(String::from(
+                "let d_y = 1 in let d_y_ = 1 in let y = x in d_y_ * d_y"
+            ))
+        );
+        assert_eq!(derived("let x = 2 in x * 3", "x"), Ok(String::from("0")));
+        assert_eq!(derived("2 ^ y", "x"), Ok(String::from("0")));
+        assert_eq!(
+            derived("x ^ x", "x"),
+            Err(DerivativeError::VariableExponent)
+        );
+        assert_eq!(
+            derived("1 + f(x)", "x"),
+            Err(DerivativeError::Call(String::from("f")))
+        );
+        assert_eq!(
+            DerivativeError::Call(String::from("f")).to_string(),
+            "cannot differentiate a call to `f`"
+        );
+    }
+
+    #[test]
+    fn finite_differences() {
+        let at = |e: &Expression, x: f64| {
+            let mut env = Env::new();
+            env.bind("x", x).bind("y", 1.7);
+            eval_number(e, &env)
+                .map_err(|e| e.kind)
+                .and_then(Value::number)
+        };
+        for text in [
+            "x * x * x - 2 * x",
+            "(x + 1) / (x * x + 1)",
+            "-x ^ 3 + abs(x - 1) * y",
+            "min(x * x, 2 * x + 1) + max(x, y)",
+            "x % 3 + 5 % x",
+            "y ^ 2 / x - x / y",
+            "x / 2",
+            "3 * x / 4",
+            "(x * x + 1) ^ (1 / 2) + (x * x + 2) ^ (3 / 2)",
+            "(2 * x + 1) ^ 3 / abs(-x * x + 3)",
+            "if x < 1 then x * x else 3 * x - 1",
+            "if 1 / 2 > 0 then x else 2 * x",
+            "let a = x * y in let b = a + x in a * b - b / 2",
+            "let x = x * x in let y = x + y in x * y",
+            "let f(t) = t * t in x * 2",
+            "let x = 2 in x * 3",
+        ] {
+            let e = text.parse().unwrap();
+            let d = derivative(&e, "x").unwrap();
+            for x in [-2.3, -0.7, 0.4, 1.9, 3.1] {
+                let h = 1e-6;
+                let estimate = (at(&e, x + h).unwrap() - at(&e, x - h).unwrap()) / (2.0 * h);
+                let exact = at(&d, x).unwrap();
+                assert!(
+                    (exact - estimate).abs() <= 1e-4 * exact.abs().max(1.0),
+                    "{text} at {x}: {d} is {exact}, not {estimate}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn deep() {
+        let x = || Expression::Var(String::from("x"));
+        for left_deep in [true, false] {
+            let mut e = x();
+            for _ in 0..200_000 {
+                let (left, right) = if left_deep { (e, x()) } else { (x(), e) };
+                e = Expression::Op {
+                    op: Operation::Add,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+            }
+            assert_eq!(derivative(&e, "x"), Ok(Expression::Value(200_001)));
+        }
+    }
+}
@@ -0,0 +1,1273 @@
+//! Storing an [`Expression`] as compact versioned bytes, or as JSON text.
+//!
+//! The binary form starts with the magic bytes `EXPR`, a version byte and the number of nodes,
+//! followed by the nodes in reverse Polish order: each is a tag byte with its operator, name or
+//! literal, and operands come before the nodes that use them. Integers are LEB128 varints, signed
+//! ones zigzag encoded, and names are a length followed by UTF-8.
+//!
+//! The JSON form writes literals as numbers and every other node as an object named by its kind,
+//! like `{"op":"+","left":1,"right":{"var":"x"}}`.
+
+use super::visit::{self, Next, Visitor};
+use super::{Comparison, Connective, Expression, Function, Operation, UnaryOperation};
+use std::convert::Infallible;
+use std::fmt;
+
+/// The version of the binary format written by [`encode`], the only one [`decode`] reads.
+pub const VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"EXPR";
+
+const OPERATIONS: [Operation; 8] = [
+    Operation::Add,
+    Operation::Sub,
+    Operation::Mul,
+    Operation::Div,
+    Operation::Rem,
+    Operation::Pow,
+    Operation::Min,
+    Operation::Max,
+];
+const UNARY_OPERATIONS: [UnaryOperation; 2] = [UnaryOperation::Neg, UnaryOperation::Abs];
+const COMPARISONS: [Comparison; 6] = [
+    Comparison::Eq,
+    Comparison::Ne,
+    Comparison::Lt,
+    Comparison::Le,
+    Comparison::Gt,
+    Comparison::Ge,
+];
+const CONNECTIVES: [Connective; 2] = [Connective::And, Connective::Or];
+
+// Tags of the binary nodes.
+const VALUE: u8 = 0;
+const VAR: u8 = 1;
+const OP: u8 = 2;
+const UNARY: u8 = 3;
+const COMPARE: u8 = 4;
+const LOGIC: u8 = 5;
+const NOT: u8 = 6;
+const IF: u8 = 7;
+const LET: u8 = 8;
+const DEFINE: u8 = 9;
+const CALL: u8 = 10;
+
+/// Why the input could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The input does not start with the magic bytes.
+    NotAnExpression,
+    /// The input was written by a newer or unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The input ends in the middle of a node.
+    UnexpectedEnd,
+    /// A node tag or JSON kind that does not exist.
+    UnknownNode,
+    /// An operator that does not exist for its kind of node.
+    UnknownOperator,
+    /// An integer that does not fit, or a varint that is too long.
+    NumberOutOfRange,
+    /// A name that is not a valid identifier.
+    InvalidName,
+    /// A node needs more operands than precede it.
+    MissingOperand,
+    /// More than one expression is left at the end.
+    ExtraOperand,
+    /// Bytes or text after the end of the expression.
+    TrailingData,
+    /// JSON syntax was malformed, and this was expected instead.
+    Expected(&'static str),
+    /// A JSON node lacks one of the fields of its kind.
+    MissingField(&'static str),
+    /// A JSON node has a field that is not part of its kind, or has it twice.
+    UnexpectedField(String),
+}
+
+/// A decoding failure, with the byte offset in the input where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErrorKind::NotAnExpression => write!(f, "not an encoded expression"),
+            DecodeErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            DecodeErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeErrorKind::UnknownNode => write!(f, "unknown kind of node"),
+            DecodeErrorKind::UnknownOperator => write!(f, "unknown operator"),
+            DecodeErrorKind::NumberOutOfRange => write!(f, "number out of range"),
+            DecodeErrorKind::InvalidName => write!(f, "invalid name"),
+            DecodeErrorKind::MissingOperand => write!(f, "missing operand"),
+            DecodeErrorKind::ExtraOperand => write!(f, "more than one expression"),
+            DecodeErrorKind::TrailingData => write!(f, "data after the end of the expression"),
+            DecodeErrorKind::Expected(what) => write!(f, "expected {what}"),
+            DecodeErrorKind::MissingField(field) => write!(f, "missing field `{field}`"),
+            DecodeErrorKind::UnexpectedField(field) => write!(f, "unexpected field `{field}`"),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Whether `name` could have been parsed as a variable, function or parameter name.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The position of `item` in `all`, as a byte.
+fn index<T: PartialEq>(all: &[T], item: &T) -> u8 {
+    all.iter()
+        .position(|x| x == item)
+        .expect("missing operator") as u8
+}
+
+/// Encode `e` in the binary format.
+pub fn encode(e: &Expression) -> Vec<u8> {
+    let mut encoder = Encoder {
+        nodes: Vec::new(),
+        count: 0,
+    };
+    let Ok(()) = visit::visit(e, &mut encoder);
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    write_varint(&mut out, encoder.count);
+    out.extend(encoder.nodes);
+    out
+}
+
+/// Writes each node once its operands have been written, so that they come out in reverse
+/// Polish order without recursing.
+struct Encoder {
+    nodes: Vec<u8>,
+    count: u64,
+}
+
+impl<'e> Visitor<'e> for Encoder {
+    type Error = Infallible;
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let out = &mut self.nodes;
+        self.count += 1;
+        match e {
+            Expression::Value(val) => {
+                out.push(VALUE);
+                // Zigzag, so that small negative numbers are short too.
+                write_varint(out, ((val << 1) ^ (val >> 63)) as u64);
+            }
+            Expression::Var(name) => {
+                out.push(VAR);
+                write_name(out, name);
+            }
+            Expression::Op { op, .. } => out.extend([OP, index(&OPERATIONS, op)]),
+            Expression::Unary { op, .. } => out.extend([UNARY, index(&UNARY_OPERATIONS, op)]),
+            Expression::Compare { op, .. } => out.extend([COMPARE, index(&COMPARISONS, op)]),
+            Expression::Logic { op, .. } => out.extend([LOGIC, index(&CONNECTIVES, op)]),
+            Expression::Not(_) => out.push(NOT),
+            Expression::If { .. } => out.push(IF),
+            Expression::Let { name, .. } => {
+                out.push(LET);
+                write_name(out, name);
+            }
+            Expression::Define { name, function, .. } => {
+                out.push(DEFINE);
+                write_name(out, name);
+                write_varint(out, function.params.len() as u64);
+                for param in &function.params {
+                    write_name(out, param);
+                }
+            }
+            Expression::Call { name, args } => {
+                out.push(CALL);
+                write_name(out, name);
+                write_varint(out, args.len() as u64);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_varint(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+/// Reads the binary format, tracking the offset for errors.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            kind,
+            offset: self.pos,
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| self.error(DecodeErrorKind::UnexpectedEnd))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.pos;
+        let mut val = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            val |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+        Err(DecodeError {
+            kind: DecodeErrorKind::NumberOutOfRange,
+            offset: start,
+        })
+    }
+
+    fn count(&mut self) -> Result<usize, DecodeError> {
+        let start = self.pos;
+        usize::try_from(self.varint()?).map_err(|_| DecodeError {
+            kind: DecodeErrorKind::NumberOutOfRange,
+            offset: start,
+        })
+    }
+
+    fn name(&mut self) -> Result<String, DecodeError> {
+        let start = self.pos;
+        let len = self.count()?;
+        if self.bytes.len() - self.pos < len {
+            return Err(self.error(DecodeErrorKind::UnexpectedEnd));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        match std::str::from_utf8(bytes) {
+            Ok(name) if is_name(name) => {
+                self.pos += len;
+                Ok(String::from(name))
+            }
+            _ => Err(DecodeError {
+                kind: DecodeErrorKind::InvalidName,
+                offset: start,
+            }),
+        }
+    }
+
+    /// One of `all`, by its index.
+    fn operator<T: Copy>(&mut self, all: &[T]) -> Result<T, DecodeError> {
+        let byte = self.byte()?;
+        all.get(usize::from(byte)).copied().ok_or(DecodeError {
+            kind: DecodeErrorKind::UnknownOperator,
+            offset: self.pos - 1,
+        })
+    }
+}
+
+/// Decode bytes written by [`encode`].
+///
+/// The input is checked completely, so truncated, corrupted or padded data is an error rather
+/// than a different expression. Decoding does not recurse, so any depth of tree is fine.
+pub fn decode(bytes: &[u8]) -> Result<Expression, DecodeError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if !bytes.starts_with(MAGIC) {
+        return Err(reader.error(DecodeErrorKind::NotAnExpression));
+    }
+    reader.pos = MAGIC.len();
+    match reader.byte()? {
+        VERSION => {}
+        version => {
+            reader.pos -= 1;
+            return Err(reader.error(DecodeErrorKind::UnsupportedVersion(version)));
+        }
+    }
+    let count = reader.count()?;
+    let mut stack: Vec<Expression> = Vec::new();
+    for _ in 0..count {
+        let start = reader.pos;
+        let missing = DecodeError {
+            kind: DecodeErrorKind::MissingOperand,
+            offset: start,
+        };
+        // Pop the last `n` operands, in order.
+        let pop = |stack: &mut Vec<Expression>, n: usize| match stack.len().checked_sub(n) {
+            Some(first) => Ok(stack.split_off(first)),
+            None => Err(missing.clone()),
+        };
+        let node = match reader.byte()? {
+            VALUE => {
+                let zigzag = reader.varint()?;
+                Expression::Value((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+            }
+            VAR => Expression::Var(reader.name()?),
+            OP => {
+                let op = reader.operator(&OPERATIONS)?;
+                let [left, right] = two(pop(&mut stack, 2)?);
+                Expression::Op {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            UNARY => {
+                let op = reader.operator(&UNARY_OPERATIONS)?;
+                let operand = pop(&mut stack, 1)?.pop().expect("missing operand");
+                Expression::Unary {
+                    op,
+                    operand: Box::new(operand),
+                }
+            }
+            COMPARE => {
+                let op = reader.operator(&COMPARISONS)?;
+                let [left, right] = two(pop(&mut stack, 2)?);
+                Expression::Compare {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            LOGIC => {
+                let op = reader.operator(&CONNECTIVES)?;
+                let [left, right] = two(pop(&mut stack, 2)?);
+                Expression::Logic {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            NOT => Expression::Not(Box::new(
+                pop(&mut stack, 1)?.pop().expect("missing operand"),
+            )),
+            IF => {
+                let mut operands = pop(&mut stack, 3)?.into_iter();
+                let mut next = || Box::new(operands.next().expect("missing operand"));
+                Expression::If {
+                    cond: next(),
+                    then: next(),
+                    otherwise: next(),
+                }
+            }
+            LET => {
+                let name = reader.name()?;
+                let [value, body] = two(pop(&mut stack, 2)?);
+                Expression::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                }
+            }
+            DEFINE => {
+                let name = reader.name()?;
+                let mut params = Vec::new();
+                for _ in 0..reader.count()? {
+                    let offset = reader.pos;
+                    let param = reader.name()?;
+                    if params.contains(&param) {
+                        return Err(duplicate_param(offset));
+                    }
+                    params.push(param);
+                }
+                let [function, body] = two(pop(&mut stack, 2)?);
+                Expression::Define {
+                    name,
+                    function: Box::new(Function {
+                        params,
+                        body: function,
+                    }),
+                    body: Box::new(body),
+                }
+            }
+            CALL => {
+                let name = reader.name()?;
+                let args = pop(&mut stack, reader.count()?)?;
+                Expression::Call { name, args }
+            }
+            _ => {
+                return Err(DecodeError {
+                    kind: DecodeErrorKind::UnknownNode,
+                    offset: start,
+                })
+            }
+        };
+        stack.push(node);
+    }
+    if reader.pos < bytes.len() {
+        return Err(reader.error(DecodeErrorKind::TrailingData));
+    }
+    match (stack.pop(), stack.is_empty()) {
+        (Some(e), true) => Ok(e),
+        (Some(_), false) => Err(reader.error(DecodeErrorKind::ExtraOperand)),
+        (None, _) => Err(reader.error(DecodeErrorKind::MissingOperand)),
+    }
+}
+
+/// The two operands popped for a binary node.
+fn two(operands: Vec<Expression>) -> [Expression; 2] {
+    operands.try_into().expect("missing operand")
+}
+
+/// A function with a repeated parameter name, which could never have been encoded.
+fn duplicate_param(offset: usize) -> DecodeError {
+    DecodeError {
+        kind: DecodeErrorKind::InvalidName,
+        offset,
+    }
+}
+
+/// Encode `e` as JSON text.
+pub fn to_json(e: &Expression) -> String {
+    let mut writer = JsonWriter(String::new());
+    let Ok(()) = visit::visit(e, &mut writer);
+    writer.0
+}
+
+/// Writes the JSON for each node around that of its subexpressions: the start on entering it,
+/// the key of each subexpression after the first before going into it, and the end on leaving.
+/// Names are identifiers, so they never need escaping.
+struct JsonWriter(String);
+
+impl<'e> Visitor<'e> for JsonWriter {
+    type Error = Infallible;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let start = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => format!("{{\"var\":\"{name}\"}}"),
+            Expression::Op { op, .. } => format!("{{\"op\":\"{}\",\"left\":", op.symbol()),
+            Expression::Compare { op, .. } => {
+                format!("{{\"compare\":\"{}\",\"left\":", op.symbol())
+            }
+            Expression::Logic { op, .. } => {
+                format!("{{\"logic\":\"{}\",\"left\":", op.symbol())
+            }
+            Expression::Unary { op, .. } => {
+                format!("{{\"unary\":\"{}\",\"operand\":", op.name())
+            }
+            Expression::Not(_) => String::from("{\"not\":"),
+            Expression::If { .. } => String::from("{\"if\":"),
+            Expression::Let { name, .. } => format!("{{\"let\":\"{name}\",\"value\":"),
+            Expression::Define { name, function, .. } => {
+                let params: Vec<_> = function.params.iter().map(|p| format!("\"{p}\"")).collect();
+                format!(
+                    "{{\"define\":\"{name}\",\"params\":[{}],\"function\":",
+                    params.join(",")
+                )
+            }
+            Expression::Call { name, .. } => format!("{{\"call\":\"{name}\",\"args\":["),
+        };
+        self.0.push_str(&start);
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        let Some((step, child)) = e.child(visited) else {
+            return Ok(Next::Leave);
+        };
+        let key = match (e, visited) {
+            (_, 0) => "",
+            (Expression::Op { .. } | Expression::Compare { .. } | Expression::Logic { .. }, _) => {
+                ",\"right\":"
+            }
+            (Expression::If { .. }, 1) => ",\"then\":",
+            (Expression::If { .. }, _) => ",\"else\":",
+            (Expression::Let { .. } | Expression::Define { .. }, _) => ",\"body\":",
+            _ => ",",
+        };
+        self.0.push_str(key);
+        Ok(Next::Child(step, child))
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        match e {
+            Expression::Value(_) | Expression::Var(_) => {}
+            Expression::Call { .. } => self.0.push_str("]}"),
+            _ => self.0.push('}'),
+        }
+        Ok(())
+    }
+}
+
+/// A JSON value, with the offset where it starts.
+struct Json {
+    kind: JsonKind,
+    offset: usize,
+}
+
+/// The JSON values that can appear in an encoded expression.
+enum JsonKind {
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Take the value out, leaving a number that is cheap to drop.
+    fn into_kind(mut self) -> JsonKind {
+        std::mem::replace(&mut self.kind, JsonKind::Number(0))
+    }
+}
+
+impl Drop for Json {
+    fn drop(&mut self) {
+        let mut pending = vec![std::mem::replace(&mut self.kind, JsonKind::Number(0))];
+        // Each value is emptied before it is dropped, so dropping it does not recurse.
+        while let Some(kind) = pending.pop() {
+            match kind {
+                JsonKind::Array(items) => pending.extend(items.into_iter().map(Json::into_kind)),
+                JsonKind::Object(fields) => {
+                    pending.extend(fields.into_iter().map(|(_, json)| json.into_kind()))
+                }
+                JsonKind::Number(_) | JsonKind::String(_) => {}
+            }
+        }
+    }
+}
+
+/// An array or object whose items [`JsonReader::value`] is reading, with the offset where it
+/// starts.
+enum Open {
+    Array(usize, Vec<Json>),
+    /// An object, with the key of the field whose value comes next.
+    Object(usize, Vec<(String, Json)>, String),
+}
+
+/// Reads JSON text, tracking the offset for errors.
+struct JsonReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonReader<'_> {
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            kind,
+            offset: self.pos,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    /// Consume `byte` if it comes next.
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8, what: &'static str) -> Result<(), DecodeError> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(self.error(self.expected(what)))
+        }
+    }
+
+    /// `Expected(what)`, or `UnexpectedEnd` if there is nothing left.
+    fn expected(&self, what: &'static str) -> DecodeErrorKind {
+        if self.pos < self.text.len() {
+            DecodeErrorKind::Expected(what)
+        } else {
+            DecodeErrorKind::UnexpectedEnd
+        }
+    }
+
+    /// A value, read with a stack of the arrays and objects it is inside rather than by
+    /// recursing, so that it can nest as deeply as [`to_json`] writes.
+    fn value(&mut self) -> Result<Json, DecodeError> {
+        let mut open: Vec<Open> = Vec::new();
+        loop {
+            let offset = match self.peek() {
+                Some(_) => self.pos,
+                None => return Err(self.error(DecodeErrorKind::UnexpectedEnd)),
+            };
+            let kind = match self.text.as_bytes()[offset] {
+                b'"' => JsonKind::String(self.string()?),
+                b'-' | b'0'..=b'9' => JsonKind::Number(self.number()?),
+                b'[' => {
+                    self.pos += 1;
+                    if !self.eat(b']') {
+                        open.push(Open::Array(offset, Vec::new()));
+                        continue;
+                    }
+                    JsonKind::Array(Vec::new())
+                }
+                b'{' => {
+                    self.pos += 1;
+                    if !self.eat(b'}') {
+                        open.push(Open::Object(offset, Vec::new(), self.key()?));
+                        continue;
+                    }
+                    JsonKind::Object(Vec::new())
+                }
+                _ => return Err(self.error(DecodeErrorKind::Expected("a value"))),
+            };
+            let mut json = Json { kind, offset };
+            // Add the value to the innermost array or object, and close those that end with it.
+            loop {
+                let Some(container) = open.last_mut() else {
+                    return Ok(json);
+                };
+                let close = match container {
+                    Open::Array(_, items) => {
+                        items.push(json);
+                        b']'
+                    }
+                    Open::Object(_, fields, key) => {
+                        fields.push((std::mem::take(key), json));
+                        b'}'
+                    }
+                };
+                if !self.eat(close) {
+                    self.expect(b',', "`,`")?;
+                    if let Open::Object(_, _, key) = container {
+                        *key = self.key()?;
+                    }
+                    break;
+                }
+                json = match open.pop() {
+                    Some(Open::Array(offset, items)) => Json {
+                        kind: JsonKind::Array(items),
+                        offset,
+                    },
+                    Some(Open::Object(offset, fields, _)) => Json {
+                        kind: JsonKind::Object(fields),
+                        offset,
+                    },
+                    None => unreachable!("the value was added to an open container"),
+                };
+            }
+        }
+    }
+
+    /// The key of a field, and the `:` after it.
+    fn key(&mut self) -> Result<String, DecodeError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error(self.expected("a field name")));
+        }
+        let key = self.string()?;
+        self.expect(b':', "`:`")?;
+        Ok(key)
+    }
+
+    fn number(&mut self) -> Result<i64, DecodeError> {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let digits = rest
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += digits;
+        if matches!(self.text.as_bytes().get(self.pos), Some(b'.' | b'e' | b'E')) {
+            return Err(self.error(DecodeErrorKind::Expected("an integer")));
+        }
+        rest[..digits].parse().map_err(|_| DecodeError {
+            kind: DecodeErrorKind::NumberOutOfRange,
+            offset: start,
+        })
+    }
+
+    /// A string, starting at its opening quote.
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        let end = |chars: &std::str::CharIndices| self.pos + chars.offset();
+        loop {
+            let Some((_, c)) = chars.next() else {
+                return Err(DecodeError {
+                    kind: DecodeErrorKind::UnexpectedEnd,
+                    offset: self.text.len(),
+                });
+            };
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escape = end(&chars) - 1;
+                    let c = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex = chars.as_str().get(..4).unwrap_or("");
+                            let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+                            chars.nth(3);
+                            c.ok_or(DecodeError {
+                                kind: DecodeErrorKind::Expected("a valid `\\u` escape"),
+                                offset: escape,
+                            })?
+                        }
+                        _ => {
+                            return Err(DecodeError {
+                                kind: DecodeErrorKind::Expected("a valid escape"),
+                                offset: escape,
+                            })
+                        }
+                    };
+                    out.push(c);
+                }
+                c if c < ' ' => {
+                    return Err(DecodeError {
+                        kind: DecodeErrorKind::Expected("`\"`"),
+                        offset: end(&chars) - 1,
+                    })
+                }
+                c => out.push(c),
+            }
+        }
+        self.pos = end(&chars);
+        Ok(out)
+    }
+}
+
+/// Decode JSON text written by [`to_json`].
+///
+/// Fields may come in any order and whitespace is allowed between tokens, but there must be no
+/// missing or extra fields.
+pub fn from_json(text: &str) -> Result<Expression, DecodeError> {
+    let mut reader = JsonReader { text, pos: 0 };
+    let json = reader.value()?;
+    if reader.peek().is_some() {
+        return Err(reader.error(DecodeErrorKind::TrailingData));
+    }
+    expression(json)
+}
+
+/// The fields of a JSON node, taken out by name.
+struct Fields {
+    fields: Vec<(String, Json)>,
+    offset: usize,
+}
+
+impl Fields {
+    fn take(&mut self, name: &'static str) -> Result<Json, DecodeError> {
+        match self.fields.iter().position(|(key, _)| key == name) {
+            Some(i) => Ok(self.fields.remove(i).1),
+            None => Err(DecodeError {
+                kind: DecodeErrorKind::MissingField(name),
+                offset: self.offset,
+            }),
+        }
+    }
+
+    fn name(&mut self, field: &'static str) -> Result<String, DecodeError> {
+        name(self.take(field)?)
+    }
+
+    /// The operator in `field`, one of `all` which are written as `symbol`.
+    fn operator<T: Copy>(
+        &mut self,
+        field: &'static str,
+        all: &[T],
+        symbol: impl Fn(&T) -> &'static str,
+    ) -> Result<T, DecodeError> {
+        let json = self.take(field)?;
+        let offset = json.offset;
+        match json.into_kind() {
+            JsonKind::String(s) => all.iter().find(|op| symbol(op) == s).copied(),
+            _ => None,
+        }
+        .ok_or(DecodeError {
+            kind: DecodeErrorKind::UnknownOperator,
+            offset,
+        })
+    }
+
+    /// Fail if any field is left over.
+    fn finish(self, e: Expression) -> Result<Expression, DecodeError> {
+        match self.fields.into_iter().next() {
+            None => Ok(e),
+            Some((key, json)) => Err(DecodeError {
+                kind: DecodeErrorKind::UnexpectedField(key),
+                offset: json.offset,
+            }),
+        }
+    }
+}
+
+fn name(json: Json) -> Result<String, DecodeError> {
+    let offset = json.offset;
+    match json.into_kind() {
+        JsonKind::String(name) if is_name(&name) => Ok(name),
+        _ => Err(DecodeError {
+            kind: DecodeErrorKind::InvalidName,
+            offset,
+        }),
+    }
+}
+
+fn array(json: Json) -> Result<Vec<Json>, DecodeError> {
+    let offset = json.offset;
+    match json.into_kind() {
+        JsonKind::Array(items) => Ok(items),
+        _ => Err(DecodeError {
+            kind: DecodeErrorKind::Expected("an array"),
+            offset,
+        }),
+    }
+}
+
+/// What a JSON node decodes to once its subexpressions have been decoded.
+enum Build {
+    Op(Operation),
+    Unary(UnaryOperation),
+    Compare(Comparison),
+    Logic(Connective),
+    Not,
+    If,
+    Let(String),
+    Define(String, Vec<String>),
+    Call(String),
+}
+
+/// A JSON node that [`expression`] is decoding the subexpressions of.
+struct Node {
+    build: Build,
+    fields: Fields,
+    /// The subexpressions still to decode.
+    children: Children,
+    decoded: Vec<Expression>,
+}
+
+/// Where the subexpressions of a [`Node`] are.
+enum Children {
+    /// In these fields, taken in order so that a missing one is reported after any errors in
+    /// those before it.
+    Fields(std::slice::Iter<'static, &'static str>),
+    /// In the arguments of a call.
+    Args(std::vec::IntoIter<Json>),
+}
+
+impl Node {
+    /// Start decoding `json`, which is a leaf if it has no subexpressions.
+    fn start(json: Json) -> Result<Result<Expression, Node>, DecodeError> {
+        let offset = json.offset;
+        let fields = match json.into_kind() {
+            JsonKind::Number(val) => return Ok(Ok(Expression::Value(val))),
+            JsonKind::Object(fields) => fields,
+            _ => {
+                return Err(DecodeError {
+                    kind: DecodeErrorKind::UnknownNode,
+                    offset,
+                })
+            }
+        };
+        const KINDS: [&str; 10] = [
+            "op", "unary", "compare", "logic", "not", "if", "let", "define", "call", "var",
+        ];
+        let kind = KINDS
+            .into_iter()
+            .find(|kind| fields.iter().any(|(key, _)| key == kind))
+            .ok_or(DecodeError {
+                kind: DecodeErrorKind::UnknownNode,
+                offset,
+            })?;
+        let mut fields = Fields { fields, offset };
+        let (build, names): (_, &'static [&'static str]) = match kind {
+            "op" => (
+                Build::Op(fields.operator("op", &OPERATIONS, Operation::symbol)?),
+                &["left", "right"],
+            ),
+            "unary" => (
+                Build::Unary(fields.operator("unary", &UNARY_OPERATIONS, UnaryOperation::name)?),
+                &["operand"],
+            ),
+            "compare" => (
+                Build::Compare(fields.operator("compare", &COMPARISONS, Comparison::symbol)?),
+                &["left", "right"],
+            ),
+            "logic" => (
+                Build::Logic(fields.operator("logic", &CONNECTIVES, Connective::symbol)?),
+                &["left", "right"],
+            ),
+            "not" => (Build::Not, &["not"]),
+            "if" => (Build::If, &["if", "then", "else"]),
+            "let" => (Build::Let(fields.name("let")?), &["value", "body"]),
+            "define" => {
+                let name = fields.name("define")?;
+                let mut params = Vec::new();
+                for param in array(fields.take("params")?)? {
+                    let offset = param.offset;
+                    let param = self::name(param)?;
+                    if params.contains(&param) {
+                        return Err(duplicate_param(offset));
+                    }
+                    params.push(param);
+                }
+                (Build::Define(name, params), &["function", "body"])
+            }
+            "call" => {
+                let build = Build::Call(fields.name("call")?);
+                let args = array(fields.take("args")?)?;
+                return Ok(Err(Node {
+                    build,
+                    fields,
+                    decoded: Vec::with_capacity(args.len()),
+                    children: Children::Args(args.into_iter()),
+                }));
+            }
+            _ => {
+                let e = Expression::Var(fields.name("var")?);
+                return fields.finish(e).map(Ok);
+            }
+        };
+        Ok(Err(Node {
+            build,
+            fields,
+            decoded: Vec::with_capacity(names.len()),
+            children: Children::Fields(names.iter()),
+        }))
+    }
+
+    /// The next subexpression to decode, if any are left.
+    fn next_child(&mut self) -> Result<Option<Json>, DecodeError> {
+        match &mut self.children {
+            Children::Fields(names) => names.next().map(|name| self.fields.take(name)).transpose(),
+            Children::Args(args) => Ok(args.next()),
+        }
+    }
+
+    /// Build the expression from the decoded subexpressions.
+    fn finish(self) -> Result<Expression, DecodeError> {
+        let mut decoded = self.decoded.into_iter();
+        let mut next = || Box::new(decoded.next().expect("missing subexpression"));
+        let e = match self.build {
+            Build::Op(op) => Expression::Op {
+                op,
+                left: next(),
+                right: next(),
+            },
+            Build::Unary(op) => Expression::Unary {
+                op,
+                operand: next(),
+            },
+            Build::Compare(op) => Expression::Compare {
+                op,
+                left: next(),
+                right: next(),
+            },
+            Build::Logic(op) => Expression::Logic {
+                op,
+                left: next(),
+                right: next(),
+            },
+            Build::Not => Expression::Not(next()),
+            Build::If => Expression::If {
+                cond: next(),
+                then: next(),
+                otherwise: next(),
+            },
+            Build::Let(name) => Expression::Let {
+                name,
+                value: next(),
+                body: next(),
+            },
+            Build::Define(name, params) => Expression::Define {
+                name,
+                function: Box::new(Function {
+                    params,
+                    body: *next(),
+                }),
+                body: next(),
+            },
+            Build::Call(name) => Expression::Call {
+                name,
+                args: decoded.collect(),
+            },
+        };
+        self.fields.finish(e)
+    }
+}
+
+/// Decode the expression in `json`, keeping the nodes it is inside on a stack rather than
+/// recursing.
+fn expression(json: Json) -> Result<Expression, DecodeError> {
+    let mut stack: Vec<Node> = Vec::new();
+    let mut json = json;
+    loop {
+        let mut decoded = match Node::start(json)? {
+            Ok(e) => Some(e),
+            Err(node) => {
+                stack.push(node);
+                None
+            }
+        };
+        // Hand the decoded expression to its parent, finishing the nodes it completes.
+        json = loop {
+            let Some(node) = stack.last_mut() else {
+                return Ok(decoded.expect("the root is decoded last"));
+            };
+            node.decoded.extend(decoded.take());
+            match node.next_child()? {
+                Some(child) => break child,
+                None => decoded = Some(stack.pop().expect("a node is open").finish()?),
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{deep_sum, eval_with, parse::parse, Env};
+
+    const EXPRESSIONS: [&str; 8] = [
+        "0",
+        "-9223372036854775808 + 9223372036854775807 * x_1",
+        "-(a - b) / abs(c % 3) ^ 2 ^ -1",
+        "min(x, max(y, 0))",
+        "x < 1 && !(y >= 2) || x == y != (1 > 2)",
+        "if a then 1 else if b then 2 else -3",
+        "let x = 2 in let sq(a) = a * a in sq(x) + k()",
+        "let f(a, b, c) = a + b * c in f(1, 2, f(3, 4, 5))",
+    ];
+
+    fn err(kind: DecodeErrorKind, offset: usize) -> Result<Expression, DecodeError> {
+        Err(DecodeError { kind, offset })
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        for text in EXPRESSIONS {
+            let e = parse(text).unwrap();
+            assert_eq!(decode(&encode(&e)), Ok(e), "{text}");
+        }
+        let bytes = encode(&parse("x * 2 + -1").unwrap());
+        assert_eq!(
+            bytes,
+            [b'E', b'X', b'P', b'R', VERSION, 5, VAR, 1, b'x', VALUE, 4, OP, 2, VALUE, 1, OP, 0]
+        );
+    }
+
+    #[test]
+    fn binary_errors() {
+        let bytes = encode(&parse("let f(a) = a in f(1) + 1").unwrap());
+        // Every prefix is rejected, rather than decoding to a smaller expression.
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "{len}");
+        }
+        assert_eq!(decode(b"EXP"), err(DecodeErrorKind::NotAnExpression, 0));
+        assert_eq!(
+            decode(b"EXPR\x02\x01\x00\x00"),
+            err(DecodeErrorKind::UnsupportedVersion(2), 4)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01"),
+            err(DecodeErrorKind::UnexpectedEnd, 6)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x00\x00\x00"),
+            err(DecodeErrorKind::TrailingData, 8)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x02\x00\x00\x00\x00"),
+            err(DecodeErrorKind::ExtraOperand, 10)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x02\x00\x00\x02\x00"),
+            err(DecodeErrorKind::MissingOperand, 8)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x0b"),
+            err(DecodeErrorKind::UnknownNode, 6)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x02\x00\x00\x03\x02"),
+            err(DecodeErrorKind::UnknownOperator, 9)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x01\x021x"),
+            err(DecodeErrorKind::InvalidName, 7)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x01\x02\xff\xfe"),
+            err(DecodeErrorKind::InvalidName, 7)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x00\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f"),
+            err(DecodeErrorKind::NumberOutOfRange, 7)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x01\x0a\x01f\xff\x01"),
+            err(DecodeErrorKind::MissingOperand, 6)
+        );
+        assert_eq!(
+            decode(b"EXPR\x01\x03\x00\x00\x00\x00\x09\x01f\x02\x01a\x01a"),
+            err(DecodeErrorKind::InvalidName, 16)
+        );
+    }
+
+    #[test]
+    fn deep_binary() {
+        for left_deep in [true, false] {
+            let e = deep_sum(200_000, left_deep);
+            let decoded = decode(&encode(&e)).unwrap();
+            assert_eq!(decoded.depth(), e.depth());
+            assert_eq!(eval_with(&decoded, &Env::new()), eval_with(&e, &Env::new()));
+        }
+    }
+
+    #[test]
+    fn deep_json() {
+        let e = deep_sum(200_000, true);
+        let json = to_json(&e);
+        assert!(json.starts_with(r#"{"op":"+","left":{"op":"+","left":"#));
+        assert_eq!(
+            json.len(),
+            200_000 * r#"{"op":"+","left":,"right":1}"#.len() + 1
+        );
+        for left_deep in [true, false] {
+            let e = deep_sum(200_000, left_deep);
+            let decoded = from_json(&to_json(&e)).unwrap();
+            assert_eq!(decoded.depth(), e.depth());
+            assert_eq!(eval_with(&decoded, &Env::new()), eval_with(&e, &Env::new()));
+        }
+        // Errors with deep values still to decode, or still being read.
+        let json = to_json(&deep_sum(200_000, false));
+        assert_eq!(
+            from_json(&format!(r#"{{"op":"?","left":{json},"right":1}}"#)),
+            err(DecodeErrorKind::UnknownOperator, 6)
+        );
+        assert_eq!(
+            from_json(&format!("[{json} 1]")),
+            err(DecodeErrorKind::Expected("`,`"), json.len() + 2)
+        );
+        assert_eq!(
+            from_json(&"[".repeat(200_000)),
+            err(DecodeErrorKind::UnexpectedEnd, 200_000)
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for text in EXPRESSIONS {
+            let e = parse(text).unwrap();
+            assert_eq!(from_json(&to_json(&e)), Ok(e), "{text}");
+        }
+        let e = parse("let f(a) = -a in if f(x) < 1 then 2 else f(3)").unwrap();
+        assert_eq!(
+            to_json(&e),
+            concat!(
+                r#"{"define":"f","params":["a"],"function":{"unary":"neg","operand":{"var":"a"}},"#,
+                r#""body":{"if":{"compare":"<","left":{"call":"f","args":[{"var":"x"}]},"right":1},"#,
+                r#""then":2,"else":{"call":"f","args":[3]}}}"#
+            )
+        );
+        assert_eq!(
+            from_json(" { \"right\" : 2 , \"left\" : {\"var\":\"x\"}, \"op\" : \"^\" } "),
+            Ok(parse("x ^ 2").unwrap())
+        );
+        assert_eq!(
+            from_json(r#"{"var": "x"}"#),
+            Ok(Expression::Var(String::from("x")))
+        );
+    }
+
+    #[test]
+    fn json_errors() {
+        assert_eq!(from_json(""), err(DecodeErrorKind::UnexpectedEnd, 0));
+        assert_eq!(from_json("1 2"), err(DecodeErrorKind::TrailingData, 2));
+        assert_eq!(
+            from_json("1.5"),
+            err(DecodeErrorKind::Expected("an integer"), 1)
+        );
+        assert_eq!(
+            from_json("99999999999999999999"),
+            err(DecodeErrorKind::NumberOutOfRange, 0)
+        );
+        assert_eq!(
+            from_json("true"),
+            err(DecodeErrorKind::Expected("a value"), 0)
+        );
+        assert_eq!(from_json("\"x\""), err(DecodeErrorKind::UnknownNode, 0));
+        assert_eq!(from_json("{\"x\":1}"), err(DecodeErrorKind::UnknownNode, 0));
+        assert_eq!(
+            from_json("{\"op\":\"+\",\"left\":1}"),
+            err(DecodeErrorKind::MissingField("right"), 0)
+        );
+        assert_eq!(
+            from_json("{\"op\":\"+\",\"left\":1,\"right\":2,\"extra\":3}"),
+            err(DecodeErrorKind::UnexpectedField(String::from("extra")), 37)
+        );
+        assert_eq!(
+            from_json("{\"op\":\"&&\",\"left\":1,\"right\":2}"),
+            err(DecodeErrorKind::UnknownOperator, 6)
+        );
+        assert_eq!(
+            from_json("{\"var\":\"2x\"}"),
+            err(DecodeErrorKind::InvalidName, 7)
+        );
+        assert_eq!(
+            from_json("{\"define\":\"f\",\"params\":[\"a\",\"a\"],\"function\":1,\"body\":2}"),
+            err(DecodeErrorKind::InvalidName, 28)
+        );
+        assert_eq!(
+            from_json("{\"not\":1"),
+            err(DecodeErrorKind::UnexpectedEnd, 8)
+        );
+        assert_eq!(
+            from_json("{\"not\" 1}"),
+            err(DecodeErrorKind::Expected("`:`"), 7)
+        );
+        assert_eq!(
+            from_json("{\"var\":\"x\\q\"}"),
+            err(DecodeErrorKind::Expected("a valid escape"), 9)
+        );
+        assert_eq!(
+            DecodeError {
+                kind: DecodeErrorKind::MissingField("left"),
+                offset: 3
+            }
+            .to_string(),
+            "missing field `left` at byte 3"
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Rendering an [`Expression`] for other tools: a Graphviz DOT graph of the tree, or LaTeX math.
+
+use super::{
+    print::precedence,
+    visit::{self, Next, Visitor},
+    Comparison, Connective, Expression, Operation, Step, UnaryOperation,
+};
+use std::{
+    convert::Infallible,
+    fmt::{self, Display, Formatter},
+};
+
+/// Displays an expression as a Graphviz `digraph` with one node per subexpression.
+///
+/// Children are drawn left to right in the order they are written, and the edges into the parts
+/// of an `if` or a binding are labelled with the keyword in front of them.
+pub struct Dot<'a>(pub &'a Expression);
+
+impl Display for Dot<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "digraph {{")?;
+        writeln!(f, "    ordering=out;")?;
+        let mut writer = DotWriter {
+            f,
+            next: 0,
+            step: None,
+            open: Vec::new(),
+        };
+        visit::visit(self.0, &mut writer).map_err(|(err, _)| err)?;
+        writeln!(f, "}}")
+    }
+}
+
+/// Writes the node for each expression on entering it, numbering them in that order, and the
+/// edge from its parent on leaving it.
+struct DotWriter<'f, 'a> {
+    f: &'f mut Formatter<'a>,
+    /// The number of the next node.
+    next: usize,
+    /// The step to the next expression entered.
+    step: Option<Step>,
+    /// The number of each expression being written, and the step to it from its parent.
+    open: Vec<(usize, Option<Step>)>,
+}
+
+impl<'e> Visitor<'e> for DotWriter<'_, '_> {
+    type Error = fmt::Error;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> fmt::Result {
+        let id = self.next;
+        self.next += 1;
+        self.open.push((id, self.step.take()));
+        let label = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => name.clone(),
+            Expression::Op { op, .. } => op.symbol().to_string(),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                ..
+            } => String::from("-"),
+            Expression::Unary { op, .. } => op.name().to_string(),
+            Expression::Compare { op, .. } => op.symbol().to_string(),
+            Expression::Logic { op, .. } => op.symbol().to_string(),
+            Expression::Not(_) => String::from("!"),
+            Expression::If { .. } => String::from("if"),
+            Expression::Let { name, .. } => format!("let {name}"),
+            Expression::Define { name, function, .. } => {
+                format!("let {name}({})", function.params.join(", "))
+            }
+            Expression::Call { name, .. } => format!("{name}()"),
+        };
+        let shape = match e {
+            Expression::Value(_) | Expression::Var(_) => "box",
+            _ => "ellipse",
+        };
+        writeln!(
+            self.f,
+            "    n{id} [label=\"{}\", shape={shape}];",
+            dot_escape(&label)
+        )
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, fmt::Error> {
+        Ok(match e.child(visited) {
+            Some((step, child)) => {
+                self.step = Some(step);
+                Next::Child(step, child)
+            }
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, _: &'e Expression, _: usize) -> fmt::Result {
+        let (child, step) = self.open.pop().expect("left an expression not entered");
+        let Some(&(id, _)) = self.open.last() else {
+            return Ok(());
+        };
+        let label = match step {
+            Some(Step::Then) => "then",
+            Some(Step::Else) => "else",
+            Some(Step::Bound) => "=",
+            Some(Step::Body) => "in",
+            _ => "",
+        };
+        if label.is_empty() {
+            writeln!(self.f, "    n{id} -> n{child};")
+        } else {
+            writeln!(self.f, "    n{id} -> n{child} [label=\"{label}\"];")
+        }
+    }
+}
+
+/// Escape `label` for a quoted DOT string, since names built by hand may contain anything.
+fn dot_escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Displays an expression as LaTeX math, like `\frac{1}{2} \cdot x^{2}`.
+///
+/// Variables and functions with longer names than one letter are set upright. The result is meant
+/// for a math environment such as `$...$`.
+pub struct Latex<'a>(pub &'a Expression);
+
+impl Display for Latex<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut writer = LatexWriter {
+            out: String::new(),
+            min_prec: 0,
+            parens: Vec::new(),
+        };
+        let Ok(()) = visit::visit(self.0, &mut writer);
+        f.write_str(&writer.out)
+    }
+}
+
+/// Binding strength of `e` in LaTeX, which is its infix one except that fractions are delimited
+/// like a single symbol.
+fn latex_precedence(e: &Expression) -> u8 {
+    match e {
+        Expression::Op {
+            op: Operation::Div, ..
+        } => u8::MAX,
+        _ => precedence(e),
+    }
+}
+
+/// Whether `base` can be raised to a power without parentheses around it. The exponent is
+/// raised and braced, so it never needs them.
+fn bare_base(base: &Expression) -> bool {
+    match base {
+        Expression::Value(val) => *val >= 0,
+        Expression::Var(_) | Expression::Call { .. } => true,
+        _ => false,
+    }
+}
+
+/// Writes each node as LaTeX around its subexpressions, like [`print`](super::print) does for
+/// infix notation.
+struct LatexWriter {
+    out: String,
+    /// How tightly the next expression entered must bind to go without parentheses.
+    min_prec: u8,
+    /// Whether each expression being written is parenthesized.
+    parens: Vec<bool>,
+}
+
+impl<'e> Visitor<'e> for LatexWriter {
+    type Error = Infallible;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let parens = latex_precedence(e) < self.min_prec;
+        self.parens.push(parens);
+        if parens {
+            self.out.push_str("\\left(");
+        }
+        let start = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => latex_name(name),
+            Expression::Op {
+                op: Operation::Div, ..
+            } => String::from("\\frac{"),
+            Expression::Op {
+                op: Operation::Pow,
+                left,
+                ..
+            } if !bare_base(left) => String::from("\\left("),
+            Expression::Op {
+                op: op @ (Operation::Min | Operation::Max),
+                ..
+            } => format!("\\{op}\\left("),
+            Expression::Unary {
+                op: UnaryOperation::Abs,
+                ..
+            } => String::from("\\left|"),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                ..
+            } => String::from("-"),
+            Expression::Not(_) => String::from("\\lnot "),
+            Expression::If { .. } => String::from("\\begin{cases} "),
+            Expression::Let { name, .. } => format!("\\text{{let }} {} = ", latex_name(name)),
+            Expression::Define { name, function, .. } => {
+                let params: Vec<_> = function.params.iter().map(|p| latex_name(p)).collect();
+                format!(
+                    "\\text{{let }} {}\\left({}\\right) = ",
+                    latex_name(name),
+                    params.join(", ")
+                )
+            }
+            Expression::Call { name, .. } => format!("{}\\left(", latex_name(name)),
+            _ => String::new(),
+        };
+        self.out.push_str(&start);
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        // The branch taken comes before the condition in a case distinction.
+        let i = match (e, visited) {
+            (Expression::If { .. }, 0) => 1,
+            (Expression::If { .. }, 1) => 0,
+            _ => visited,
+        };
+        let Some((step, child)) = e.child(i) else {
+            return Ok(Next::Leave);
+        };
+        let prec = latex_precedence(e);
+        // As in infix, an equally binding operand on the side the operator does not associate
+        // to needs parentheses.
+        let binary = |symbol: &str| {
+            let min_prec = if visited == 0 { prec } else { prec + 1 };
+            (format!(" {symbol} "), min_prec)
+        };
+        let (between, min_prec) = match e {
+            Expression::Op {
+                op: Operation::Div, ..
+            } => (String::from("}{"), 0),
+            Expression::Op {
+                op: Operation::Pow,
+                left,
+                ..
+            } if !bare_base(left) => (String::from("\\right)^{"), 0),
+            Expression::Op {
+                op: Operation::Pow, ..
+            } => (String::from("^{"), 0),
+            Expression::Op {
+                op: Operation::Min | Operation::Max,
+                ..
+            }
+            | Expression::Call { .. } => (String::from(", "), 0),
+            Expression::Op { op, .. } => binary(match op {
+                Operation::Mul => "\\cdot",
+                Operation::Rem => "\\bmod",
+                _ => op.symbol(),
+            }),
+            Expression::Compare { op, .. } => binary(match op {
+                Comparison::Eq => "=",
+                Comparison::Ne => "\\neq",
+                Comparison::Le => "\\leq",
+                Comparison::Ge => "\\geq",
+                _ => op.symbol(),
+            }),
+            Expression::Logic { op, .. } => binary(match op {
+                Connective::And => "\\land",
+                Connective::Or => "\\lor",
+            }),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                ..
+            }
+            | Expression::Not(_) => (String::new(), prec),
+            Expression::If { .. } if visited == 1 => (String::from(" & \\text{if } "), 0),
+            Expression::If { .. } => (String::from(" \\\\ "), 0),
+            Expression::Let { .. } | Expression::Define { .. } => {
+                (String::from(" \\text{ in } "), 0)
+            }
+            _ => (String::new(), 0),
+        };
+        if visited > 0 {
+            self.out.push_str(&between);
+        }
+        self.min_prec = min_prec;
+        Ok(Next::Child(step, child))
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let end = match e {
+            Expression::Op {
+                op: Operation::Div | Operation::Pow,
+                ..
+            } => "}",
+            Expression::Op {
+                op: Operation::Min | Operation::Max,
+                ..
+            }
+            | Expression::Call { .. } => "\\right)",
+            Expression::Unary {
+                op: UnaryOperation::Abs,
+                ..
+            } => "\\right|",
+            Expression::If { .. } => " & \\text{otherwise} \\end{cases}",
+            _ => "",
+        };
+        self.out.push_str(end);
+        if self.parens.pop().expect("left an expression not entered") {
+            self.out.push_str("\\right)");
+        }
+        Ok(())
+    }
+}
+
+/// A variable or function name, upright unless it is a single character. Characters that are
+/// special to LaTeX, such as the `$` of REPL results, are escaped for math mode.
+fn latex_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        match c {
+            '$' | '{' | '}' | '#' | '%' | '&' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\backslash "),
+            '^' => escaped.push_str("\\text{\\textasciicircum}"),
+            _ => escaped.push(c),
+        }
+    }
+    if name.chars().count() == 1 {
+        escaped
+    } else {
+        format!("\\mathrm{{{escaped}}}")
+    }
+}
+
+impl Expression {
+    /// Render as a Graphviz DOT graph.
+    pub fn dot(&self) -> Dot<'_> {
+        Dot(self)
+    }
+
+    /// Render as LaTeX math.
+    pub fn latex(&self) -> Latex<'_> {
+        Latex(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day2::{deep_sum, parse::parse, Expression};
+
+    fn latex(text: &str) -> String {
+        parse(text).unwrap().latex().to_string()
+    }
+
+    #[test]
+    fn dot() {
+        let e = parse("if x < 1 then -x else f(x, 2)").unwrap();
+        assert_eq!(
+            e.dot().to_string().lines().collect::<Vec<_>>(),
+            [
+                "digraph {",
+                "    ordering=out;",
+                "    n0 [label=\"if\", shape=ellipse];",
+                "    n1 [label=\"<\", shape=ellipse];",
+                "    n2 [label=\"x\", shape=box];",
+                "    n1 -> n2;",
+                "    n3 [label=\"1\", shape=box];",
+                "    n1 -> n3;",
+                "    n0 -> n1;",
+                "    n4 [label=\"-\", shape=ellipse];",
+                "    n5 [label=\"x\", shape=box];",
+                "    n4 -> n5;",
+                "    n0 -> n4 [label=\"then\"];",
+                "    n6 [label=\"f()\", shape=ellipse];",
+                "    n7 [label=\"x\", shape=box];",
+                "    n6 -> n7;",
+                "    n8 [label=\"2\", shape=box];",
+                "    n6 -> n8;",
+                "    n0 -> n6 [label=\"else\"];",
+                "}",
+            ]
+        );
+        let e = parse("let sq(a) = a * a in let y = 3 in sq(y)").unwrap();
+        let dot = e.dot().to_string();
+        assert!(dot.contains("    n0 [label=\"let sq(a)\", shape=ellipse];\n"));
+        assert!(dot.contains("    n0 -> n1 [label=\"=\"];\n"));
+        assert!(dot.contains("    n4 -> n6 [label=\"in\"];\n    n0 -> n4 [label=\"in\"];\n}\n"));
+
+        let e = Expression::Call {
+            name: String::from(r#"say "hi" \n"#),
+            args: vec![Expression::Var(String::from(r"\"))],
+        };
+        let dot = e.dot().to_string();
+        assert!(dot.contains(r#"    n0 [label="say \"hi\" \\n()", shape=ellipse];"#));
+        assert!(dot.contains(r#"    n1 [label="\\", shape=box];"#));
+    }
+
+    #[test]
+    fn deep() {
+        let e = deep_sum(100_000, true);
+        let dot = e.dot().to_string();
+        assert!(dot.ends_with("    n0 -> n200000;\n}\n"));
+        assert_eq!(dot.lines().count(), 2 * 200_001 + 2);
+        let latex = e.latex().to_string();
+        assert_eq!(latex.len(), 1 + 100_000 * " + 1".len());
+    }
+
+    #[test]
+    fn latex_math() {
+        assert_eq!(latex("1 / 2 * x ^ 2"), r"\frac{1}{2} \cdot x^{2}");
+        assert_eq!(
+            latex("(a + b) / (c - 1) / 2"),
+            r"\frac{\frac{a + b}{c - 1}}{2}"
+        );
+        assert_eq!(
+            latex("(x + 1) ^ (y / 2) + -2 ^ 2 + (1 / x) ^ 3"),
+            r"\left(x + 1\right)^{\frac{y}{2}} + \left(-2\right)^{2} + \left(\frac{1}{x}\right)^{3}"
+        );
+        assert_eq!(
+            latex("2 ^ 3 ^ 4 + (2 ^ 3) ^ 4 + (-x) ^ 2"),
+            r"2^{3^{4}} + \left(2^{3}\right)^{4} + \left(-x\right)^{2}"
+        );
+        assert_eq!(
+            latex("a - (b - c) * (d % 3)"),
+            r"a - \left(b - c\right) \cdot \left(d \bmod 3\right)"
+        );
+        assert_eq!(
+            latex("-abs(x) + min(x, max(y, 0))"),
+            r"-\left|x\right| + \min\left(x, \max\left(y, 0\right)\right)"
+        );
+        assert_eq!(
+            latex("x <= 1 && !(y != 2) || rate >= 0"),
+            r"x \leq 1 \land \lnot \left(y \neq 2\right) \lor \mathrm{rate} \geq 0"
+        );
+        assert_eq!(
+            latex("-(a + b) * (if a then 1 else 2)"),
+            r"-\left(a + b\right) \cdot \left(\begin{cases} 1 & \text{if } a \\ 2 & \text{otherwise} \end{cases}\right)"
+        );
+        assert_eq!(
+            latex("let d_y = 2 in let f(t) = t * d_y in 1 + f(3)"),
+            r"\text{let } \mathrm{d\_y} = 2 \text{ in } \text{let } f\left(t\right) = t \cdot \mathrm{d\_y} \text{ in } 1 + f\left(3\right)"
+        );
+    }
+
+    #[test]
+    fn latex_names() {
+        assert_eq!(
+            latex("$1 * x + $12 - max_rate"),
+            r"\mathrm{\$1} \cdot x + \mathrm{\$12} - \mathrm{max\_rate}"
+        );
+        let name = |name: &str| Expression::Var(String::from(name)).latex().to_string();
+        assert_eq!(name("$"), r"\$");
+        assert_eq!(name("a{b}#%&"), r"\mathrm{a\{b\}\#\%\&}");
+        assert_eq!(
+            name(r"a\b^c"),
+            r"\mathrm{a\backslash b\text{\textasciicircum}c}"
+        );
+    }
+}
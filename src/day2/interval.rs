@@ -0,0 +1,799 @@
+//! Interval evaluation: bounds on what an [`Expression`] can evaluate to when each variable is
+//! only known to lie in a range.
+
+use super::{
+    visit::{self, Next, Visitor},
+    Arithmetic, Call, Comparison, Env, EvalError, EvalErrorKind, Expression, Local, Locals,
+    Operation, Step, Type, UnaryOperation,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// The integers from `lo` to `hi`, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    lo: i64,
+    hi: i64,
+}
+
+impl From<i64> for Interval {
+    fn from(val: i64) -> Self {
+        Interval { lo: val, hi: val }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+/// The interval from `lo` to `hi` if it fits in `i64`, failing with an overflow if it lies
+/// completely outside and a possible overflow if it lies partly outside.
+fn fit(lo: i128, hi: i128) -> Result<Interval, EvalErrorKind> {
+    let range = i128::from(i64::MIN)..=i128::from(i64::MAX);
+    match (range.contains(&lo), range.contains(&hi)) {
+        (true, true) => Ok(Interval {
+            lo: lo as i64,
+            hi: hi as i64,
+        }),
+        _ if hi < *range.start() || lo > *range.end() => Err(EvalErrorKind::Overflow),
+        _ => Err(EvalErrorKind::Possibly(Box::new(EvalErrorKind::Overflow))),
+    }
+}
+
+/// The smallest interval holding all of `vals`, which must not be empty.
+fn hull(vals: impl IntoIterator<Item = i128>) -> Result<Interval, EvalErrorKind> {
+    let (lo, hi) = vals
+        .into_iter()
+        .fold((i128::MAX, i128::MIN), |(lo, hi), val| {
+            (lo.min(val), hi.max(val))
+        });
+    fit(lo, hi)
+}
+
+/// `base` to the power `exp`, saturating far outside the `i64` range when it overflows.
+fn power(base: i64, exp: i64) -> i128 {
+    match base {
+        0 => i128::from(exp == 0),
+        1 => 1,
+        -1 if exp % 2 == 0 => 1,
+        -1 => -1,
+        _ => u32::try_from(exp)
+            .ok()
+            .and_then(|exp| i128::from(base).checked_pow(exp))
+            .unwrap_or(if base < 0 && exp % 2 == 1 {
+                i128::MIN
+            } else {
+                i128::MAX
+            }),
+    }
+}
+
+impl Interval {
+    /// The interval from `lo` to `hi`, or `None` if that is empty.
+    pub fn new(lo: i64, hi: i64) -> Option<Self> {
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+
+    pub fn lo(&self) -> i64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> i64 {
+        self.hi
+    }
+
+    pub fn contains(&self, val: i64) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    /// The smallest interval containing both.
+    pub fn join(self, other: Self) -> Self {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    fn is_point(&self) -> bool {
+        self.lo == self.hi
+    }
+
+    /// The endpoints, and the values between them where a function of one argument can peak.
+    fn corners(&self) -> Vec<i64> {
+        let mut vals = vec![self.lo, self.hi];
+        if self.contains(0) {
+            vals.push(0);
+        }
+        vals
+    }
+
+    /// Fail with `kind` if `self` is zero, or possibly if it contains zero.
+    fn nonzero(&self, kind: EvalErrorKind) -> Result<(), EvalErrorKind> {
+        match (self.lo, self.hi) {
+            (0, 0) => Err(kind),
+            _ if self.contains(0) => Err(EvalErrorKind::Possibly(Box::new(kind))),
+            _ => Ok(()),
+        }
+    }
+
+    /// An interval containing the result of `op` for every pair of values from `self` and
+    /// `other`, with the semantics of [`Arithmetic::Checked`].
+    ///
+    /// Fails with the error of the operation if it fails for every pair, and with
+    /// [`EvalErrorKind::Possibly`] that error if it may fail for only some of them.
+    pub fn apply(self, op: Operation, other: Interval) -> Result<Interval, EvalErrorKind> {
+        if self.is_point() && other.is_point() {
+            return Arithmetic::Checked
+                .apply(op, self.lo, other.lo)
+                .map(Interval::from);
+        }
+        let (a, b) = (self, other);
+        let corners = |f: fn(i128, i128) -> i128| {
+            let (a, b) = ([a.lo, a.hi], [b.lo, b.hi]);
+            hull(a.into_iter().flat_map(|x| b.map(|y| f(x.into(), y.into()))))
+        };
+        match op {
+            Operation::Add => fit(
+                i128::from(a.lo) + i128::from(b.lo),
+                i128::from(a.hi) + i128::from(b.hi),
+            ),
+            Operation::Sub => fit(
+                i128::from(a.lo) - i128::from(b.hi),
+                i128::from(a.hi) - i128::from(b.lo),
+            ),
+            Operation::Mul => corners(|x, y| x * y),
+            // Truncating division is monotonic in each operand while the divisor keeps its sign.
+            Operation::Div => {
+                b.nonzero(EvalErrorKind::DivisionByZero)?;
+                corners(|x, y| x / y)
+            }
+            Operation::Rem => {
+                b.nonzero(EvalErrorKind::RemainderByZero)?;
+                if a.lo == i64::MIN && b.contains(-1) {
+                    return Err(EvalErrorKind::Possibly(Box::new(EvalErrorKind::Overflow)));
+                }
+                // The remainder has the sign of the dividend and is smaller than the divisor.
+                let (small, large) = (b.lo.unsigned_abs(), b.hi.unsigned_abs());
+                let (small, large) = (small.min(large), small.max(large));
+                if a.lo.unsigned_abs() < small && a.hi.unsigned_abs() < small {
+                    return Ok(a);
+                }
+                let largest = i128::from(large) - 1;
+                fit(
+                    i128::from(a.lo.min(0)).max(-largest),
+                    i128::from(a.hi.max(0)).min(largest),
+                )
+            }
+            Operation::Pow => {
+                if b.hi < 0 {
+                    return Err(EvalErrorKind::NegativeExponent);
+                }
+                if b.lo < 0 {
+                    return Err(EvalErrorKind::Possibly(Box::new(
+                        EvalErrorKind::NegativeExponent,
+                    )));
+                }
+                // For a fixed base the extremes are at the smallest and largest exponents of
+                // each parity, and for a fixed exponent at the ends of the range or at zero.
+                let exps = [b.lo, b.lo.saturating_add(1), b.hi - 1, b.hi].into_iter();
+                let exps: Vec<_> = exps.filter(|exp| b.contains(*exp)).collect();
+                hull(
+                    a.corners()
+                        .into_iter()
+                        .flat_map(|x| exps.iter().map(move |&exp| power(x, exp))),
+                )
+            }
+            Operation::Min => Ok(Interval {
+                lo: a.lo.min(b.lo),
+                hi: a.hi.min(b.hi),
+            }),
+            Operation::Max => Ok(Interval {
+                lo: a.lo.max(b.lo),
+                hi: a.hi.max(b.hi),
+            }),
+        }
+    }
+
+    /// An interval containing the result of `op` for every value in `self`, failing like
+    /// [`Interval::apply`].
+    pub fn apply_unary(self, op: UnaryOperation) -> Result<Interval, EvalErrorKind> {
+        if self.is_point() {
+            return Arithmetic::Checked
+                .apply_unary(op, self.lo)
+                .map(Interval::from);
+        }
+        let (lo, hi) = (i128::from(self.lo), i128::from(self.hi));
+        match op {
+            UnaryOperation::Neg => fit(-hi, -lo),
+            UnaryOperation::Abs if lo >= 0 => Ok(self),
+            UnaryOperation::Abs if hi <= 0 => fit(-hi, -lo),
+            UnaryOperation::Abs => fit(0, hi.max(-lo)),
+        }
+    }
+}
+
+/// What an expression can evaluate to, over all values of its variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bounds {
+    Num(Interval),
+    /// Always the given boolean, or either if `None`.
+    Bool(Option<bool>),
+}
+
+impl Display for Bounds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Bounds::Num(interval) => write!(f, "{interval}"),
+            Bounds::Bool(Some(val)) => write!(f, "{val}"),
+            Bounds::Bool(None) => write!(f, "true or false"),
+        }
+    }
+}
+
+impl Bounds {
+    fn ty(&self) -> Type {
+        match self {
+            Bounds::Num(_) => Type::Number,
+            Bounds::Bool(_) => Type::Boolean,
+        }
+    }
+
+    fn number(self) -> Result<Interval, EvalErrorKind> {
+        match self {
+            Bounds::Num(interval) => Ok(interval),
+            Bounds::Bool(_) => Err(EvalErrorKind::TypeMismatch {
+                expected: Type::Number,
+                found: Type::Boolean,
+            }),
+        }
+    }
+
+    fn boolean(self) -> Result<Option<bool>, EvalErrorKind> {
+        match self {
+            Bounds::Bool(val) => Ok(val),
+            Bounds::Num(_) => Err(EvalErrorKind::TypeMismatch {
+                expected: Type::Boolean,
+                found: Type::Number,
+            }),
+        }
+    }
+
+    /// Bounds covering both, which must have the same type.
+    fn join(self, other: Self) -> Result<Bounds, EvalErrorKind> {
+        match (self, other) {
+            (Bounds::Num(a), Bounds::Num(b)) => Ok(Bounds::Num(a.join(b))),
+            (Bounds::Bool(a), Bounds::Bool(b)) => Ok(Bounds::Bool(if a == b { a } else { None })),
+            (a, b) => Err(EvalErrorKind::TypeMismatch {
+                expected: a.ty(),
+                found: b.ty(),
+            }),
+        }
+    }
+
+    /// Whether `op` holds, like [`Value::compare`](super::Value::compare).
+    fn compare(self, op: Comparison, other: Self) -> Result<Option<bool>, EvalErrorKind> {
+        let (a, b) = match (self, other) {
+            (Bounds::Num(a), Bounds::Num(b)) => (a, b),
+            (Bounds::Bool(Some(a)), Bounds::Bool(Some(b))) if op.is_equality() => {
+                return Ok(Some(op.test(&a, &b)))
+            }
+            (Bounds::Bool(_), Bounds::Bool(_)) if op.is_equality() => return Ok(None),
+            (Bounds::Bool(_), _) | (_, Bounds::Bool(_)) if !op.is_equality() => {
+                return Err(EvalErrorKind::TypeMismatch {
+                    expected: Type::Number,
+                    found: Type::Boolean,
+                })
+            }
+            (a, b) => {
+                return Err(EvalErrorKind::TypeMismatch {
+                    expected: a.ty(),
+                    found: b.ty(),
+                })
+            }
+        };
+        // Whether `a < b`, or `a <= b` if `or_equal`, for all values, no values or some.
+        let less = |a: Interval, b: Interval, or_equal: bool| match or_equal {
+            false if a.hi < b.lo => Some(true),
+            false if a.lo >= b.hi => Some(false),
+            true if a.hi <= b.lo => Some(true),
+            true if a.lo > b.hi => Some(false),
+            _ => None,
+        };
+        let equal = if a.is_point() && a == b {
+            Some(true)
+        } else if a.hi < b.lo || b.hi < a.lo {
+            Some(false)
+        } else {
+            None
+        };
+        Ok(match op {
+            Comparison::Eq => equal,
+            Comparison::Ne => equal.map(|equal| !equal),
+            Comparison::Lt => less(a, b, false),
+            Comparison::Le => less(a, b, true),
+            Comparison::Gt => less(b, a, false),
+            Comparison::Ge => less(b, a, true),
+        })
+    }
+}
+
+/// Mark an error as one that may not happen, because it is on a path that may not be taken.
+fn possibly(mut err: EvalError) -> EvalError {
+    if !matches!(err.kind, EvalErrorKind::Possibly(_)) {
+        err.kind = EvalErrorKind::Possibly(Box::new(err.kind));
+    }
+    err
+}
+
+/// Bounds on the result of `e` over all values of its variables, each of which lies in the
+/// interval bound to it in `env`.
+///
+/// The bounds are sound but not always tight: each operation is bounded separately, so `x - x`
+/// is not known to be zero. When a condition can go either way both branches are evaluated and
+/// their bounds joined, so a recursive function whose condition stays undecided is only bounded
+/// by the call limit. Failures that depend on the values of the variables are reported as
+/// [`EvalErrorKind::Possibly`], and a definite one is certain to happen for every value.
+///
+/// Traverses `e` with [`visit::visit`], so arbitrarily deep trees and calls are fine.
+pub fn bounds(e: &Expression, env: &Env<Interval>) -> Result<Bounds, EvalError> {
+    let mut evaluator = Evaluator {
+        env,
+        values: Vec::new(),
+        locals: Locals::default(),
+        scopes: Vec::new(),
+        calls: Vec::new(),
+        active: 0,
+        call_limit: env.call_limit(),
+        undecided: 0,
+    };
+    match visit::visit(e, &mut evaluator) {
+        Ok(()) => Ok(evaluator.values.pop().expect("missing result")),
+        // The traversal stops where it fails, so the count is of the branches around the failure.
+        Err((kind, path)) if evaluator.undecided > 0 => Err(possibly(EvalError { kind, path })),
+        Err((kind, path)) => Err(EvalError { kind, path }),
+    }
+}
+
+/// The [`Visitor`] behind [`bounds`], which pushes the bounds of each expression it is done
+/// with. Scopes and calls are kept as in [`walk`](super::walk).
+struct Evaluator<'e, 'a> {
+    env: &'e Env<'a, Interval>,
+    values: Vec<Bounds>,
+    locals: Locals<'e, Bounds>,
+    /// The innermost binding outside each `let` and function definition being evaluated.
+    scopes: Vec<Option<usize>>,
+    calls: Vec<Call<'e>>,
+    /// How many function bodies are being evaluated.
+    active: usize,
+    call_limit: usize,
+    /// How many of the expressions being evaluated may not be evaluated at all, because they
+    /// are behind a condition that can go either way.
+    undecided: usize,
+}
+
+impl Evaluator<'_, '_> {
+    fn pop(&mut self) -> Bounds {
+        self.values.pop().expect("missing operand")
+    }
+}
+
+impl<'e> Visitor<'e> for Evaluator<'e, '_> {
+    type Error = EvalErrorKind;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), EvalErrorKind> {
+        match e {
+            Expression::Value(val) => self.values.push(Bounds::Num(Interval::from(*val))),
+            Expression::Var(name) => {
+                let val = match self.locals.var(name) {
+                    Some(val) => *val,
+                    None => self
+                        .env
+                        .get(name)
+                        .map(Bounds::Num)
+                        .ok_or_else(|| EvalErrorKind::UnboundVariable(name.clone()))?,
+                };
+                self.values.push(val);
+            }
+            Expression::Define { name, function, .. } => {
+                let outer = self.locals.push(Local::Function(name, function));
+                self.scopes.push(outer);
+            }
+            Expression::Call { name, args } => {
+                let env = self.env;
+                let found = self
+                    .locals
+                    .function(name)
+                    .or_else(|| env.function(name).map(|function| (function, None)));
+                match found {
+                    None => return Err(EvalErrorKind::UnknownFunction(name.clone())),
+                    Some((function, _)) if function.params.len() != args.len() => {
+                        return Err(EvalErrorKind::ArgumentCount {
+                            name: name.clone(),
+                            expected: function.params.len(),
+                            found: args.len(),
+                        })
+                    }
+                    Some(_) if self.active == self.call_limit => {
+                        return Err(EvalErrorKind::CallDepth(self.call_limit))
+                    }
+                    Some((function, scope)) => self.calls.push(Call {
+                        function,
+                        scope,
+                        caller: None,
+                        len: 0,
+                    }),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, EvalErrorKind> {
+        let val = match (e, visited) {
+            (Expression::Unary { op, .. }, 1) => {
+                Bounds::Num(self.pop().number()?.apply_unary(*op)?)
+            }
+            (Expression::Op { op, .. }, 2) => {
+                let right = self.pop();
+                let left = self.pop().number()?;
+                Bounds::Num(left.apply(*op, right.number()?)?)
+            }
+            (Expression::Compare { op, .. }, 2) => {
+                let right = self.pop();
+                Bounds::Bool(self.pop().compare(*op, right)?)
+            }
+            (Expression::Not(_), 1) => Bounds::Bool(self.pop().boolean()?.map(|val| !val)),
+            (Expression::Logic { op, right, .. }, 1) => match self.pop().boolean()? {
+                Some(left) if left == op.short_circuit() => Bounds::Bool(Some(left)),
+                left => {
+                    // Keep the left operand to combine with the right one.
+                    if left.is_none() {
+                        self.undecided += 1;
+                    }
+                    self.values.push(Bounds::Bool(left));
+                    return Ok(Next::Child(Step::Right, right));
+                }
+            },
+            (Expression::Logic { op, .. }, 2) => {
+                let right = self.pop().boolean();
+                let left = self.pop().boolean()?;
+                let decided = op.short_circuit();
+                match (left, right) {
+                    (Some(_), right) => Bounds::Bool(right?),
+                    (None, right) => {
+                        // When the left operand may decide, the right one may not be evaluated.
+                        self.undecided -= 1;
+                        match right {
+                            Ok(Some(right)) if right == decided => Bounds::Bool(Some(right)),
+                            Ok(_) => Bounds::Bool(None),
+                            Err(kind) => return Err(EvalErrorKind::Possibly(Box::new(kind))),
+                        }
+                    }
+                }
+            }
+            (
+                Expression::If {
+                    then, otherwise, ..
+                },
+                1,
+            ) => {
+                let cond = self.pop().boolean()?;
+                // Keep the condition to tell whether the other branch is needed too.
+                self.values.push(Bounds::Bool(cond));
+                return Ok(match cond {
+                    Some(true) => Next::Child(Step::Then, then),
+                    Some(false) => Next::Child(Step::Else, otherwise),
+                    None => {
+                        self.undecided += 1;
+                        Next::Child(Step::Then, then)
+                    }
+                });
+            }
+            (Expression::If { otherwise, .. }, 2) => {
+                let branch = self.pop();
+                match self.pop() {
+                    Bounds::Bool(None) => {
+                        self.values.extend([Bounds::Bool(None), branch]);
+                        return Ok(Next::Child(Step::Else, otherwise));
+                    }
+                    _ => branch,
+                }
+            }
+            (Expression::If { .. }, 3) => {
+                let otherwise = self.pop();
+                let then = self.pop();
+                self.pop();
+                self.undecided -= 1;
+                then.join(otherwise)?
+            }
+            (Expression::Let { name, body, .. }, 1) => {
+                let value = self.pop();
+                let outer = self.locals.push(Local::Var(name, value));
+                self.scopes.push(outer);
+                return Ok(Next::Child(Step::Body, body));
+            }
+            // The body of the function is only evaluated when it is called.
+            (Expression::Define { body, .. }, 0) => return Ok(Next::Child(Step::Body, body)),
+            (Expression::Call { args, .. }, i) if i < args.len() => {
+                return Ok(Next::Child(Step::Arg(i), &args[i]))
+            }
+            (Expression::Call { args, .. }, i) if i == args.len() => {
+                let args = self.values.split_off(self.values.len() - args.len());
+                let call = self.calls.last_mut().expect("missing call");
+                (call.caller, call.len) = (self.locals.innermost, self.locals.bindings.len());
+                self.locals.innermost = call.scope;
+                let function = call.function;
+                for (param, arg) in function.params.iter().zip(args) {
+                    self.locals.push(Local::Var(param, arg));
+                }
+                self.active += 1;
+                return Ok(Next::Outside(&function.body));
+            }
+            (Expression::Call { .. }, _) => {
+                let call = self.calls.pop().expect("missing call");
+                self.locals.bindings.truncate(call.len);
+                self.locals.innermost = call.caller;
+                self.active -= 1;
+                return Ok(Next::Leave);
+            }
+            // The value of the body is the value of the whole.
+            (Expression::Define { .. }, 1) => return Ok(Next::Leave),
+            (e, i) => {
+                return Ok(match e.child(i) {
+                    Some((step, child)) => Next::Child(step, child),
+                    None => Next::Leave,
+                })
+            }
+        };
+        self.values.push(val);
+        Ok(Next::Leave)
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), EvalErrorKind> {
+        if let Expression::Let { .. } | Expression::Define { .. } = e {
+            let outer = self.scopes.pop().expect("missing scope");
+            self.locals.pop(outer);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{
+        deep_sum, eval_with, parse::parse, property::check_expressions, random::Generator,
+        random::Rng, Value, DEFAULT_CALL_LIMIT,
+    };
+
+    fn interval(lo: i64, hi: i64) -> Interval {
+        Interval::new(lo, hi).unwrap()
+    }
+
+    fn bounded(text: &str, x: Interval) -> Result<Bounds, EvalError> {
+        let mut env = Env::new();
+        env.bind("x", x);
+        bounds(&parse(text).unwrap(), &env)
+    }
+
+    fn possibly(kind: EvalErrorKind) -> EvalErrorKind {
+        EvalErrorKind::Possibly(Box::new(kind))
+    }
+
+    /// Check that `result` soundly bounds `op` over the values in `a` and `b`, trying the ends,
+    /// the values around zero and some random ones.
+    fn check_sound(rng: &mut Rng, op: Option<Operation>, a: Interval, b: Interval) {
+        let unary = |op: UnaryOperation| a.apply_unary(op);
+        let samples = |rng: &mut Rng, i: Interval| {
+            let mut vals = vec![i.lo, i.hi, -1, 0, 1];
+            vals.extend((0..10).map(|_| rng.range(i.lo..=i.hi)));
+            vals.retain(|&val| i.contains(val));
+            vals
+        };
+        let (xs, ys) = (samples(rng, a), samples(rng, b));
+        for (x, y) in xs.iter().flat_map(|&x| ys.iter().map(move |&y| (x, y))) {
+            let (bound, exact) = match op {
+                Some(op) => (a.apply(op, b), Arithmetic::Checked.apply(op, x, y)),
+                None => (
+                    unary(UnaryOperation::Abs).and_then(|_| unary(UnaryOperation::Neg)),
+                    Arithmetic::Checked
+                        .apply_unary(UnaryOperation::Abs, x)
+                        .and_then(|_| Arithmetic::Checked.apply_unary(UnaryOperation::Neg, x)),
+                ),
+            };
+            match (&bound, exact) {
+                (Ok(bound), Ok(val)) => assert!(bound.contains(val), "{op:?} {a} {b}: {val}"),
+                (Ok(_), Err(err)) => panic!("{op:?} {a} {b}: {x}, {y} fails with {err}"),
+                (Err(EvalErrorKind::Possibly(_)), _) | (Err(_), Err(_)) => {}
+                (Err(err), Ok(val)) => panic!("{op:?} {a} {b}: {err}, but {x}, {y} gives {val}"),
+            }
+        }
+    }
+
+    #[test]
+    fn operations() {
+        let mut rng = Rng::new(3);
+        let ops = [
+            Operation::Add,
+            Operation::Sub,
+            Operation::Mul,
+            Operation::Div,
+            Operation::Rem,
+            Operation::Pow,
+            Operation::Min,
+            Operation::Max,
+        ];
+        let extremes = [
+            i64::MIN,
+            i64::MIN + 1,
+            -1 << 32,
+            1 << 32,
+            i64::MAX - 1,
+            i64::MAX,
+        ];
+        for _ in 0..2000 {
+            let mut random = || {
+                let [a, b] = [(); 2].map(|_| match rng.below(4) {
+                    0 => *rng.choose(&extremes).unwrap(),
+                    _ => rng.range(-20..=20),
+                });
+                interval(a.min(b), a.max(b))
+            };
+            let (a, b) = (random(), random());
+            check_sound(&mut rng, None, a, b);
+            for op in ops {
+                check_sound(&mut rng, Some(op), a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn examples() {
+        let x = interval(-1, 3);
+        let num = |lo, hi| Ok(Bounds::Num(interval(lo, hi)));
+        assert_eq!(bounded("x * x - 2 * x", x), num(-9, 11));
+        assert_eq!(bounded("x - x", x), num(-4, 4));
+        assert_eq!(bounded("abs(x) + 1", x), num(1, 4));
+        assert_eq!(bounded("-x ^ 2", x), num(-9, 0));
+        assert_eq!(bounded("x ^ 3", x), num(-1, 27));
+        assert_eq!(bounded("min(x, 2) % 2 + 10 / (x + 2)", x), num(1, 11));
+        assert_eq!(bounded("if x > 0 then x else -x", x), num(-3, 3));
+        assert_eq!(bounded("if x > 5 then 1 / 0 else 7", x), num(7, 7));
+        assert_eq!(bounded("x >= -1", x), Ok(Bounds::Bool(Some(true))));
+        assert_eq!(bounded("x < 2 || x == 1", x), Ok(Bounds::Bool(None)));
+        assert_eq!(
+            bounded("x > 3 && 1 / 0 == 1", x),
+            Ok(Bounds::Bool(Some(false)))
+        );
+        assert_eq!(bounded("let f(a) = a * a in f(x) + 1", x), num(-2, 10));
+        let fact = "let fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x)";
+        assert_eq!(bounded(fact, interval(4, 4)), num(24, 24));
+        assert_eq!(bounded(fact, interval(1, 4)), num(-12, 24));
+        assert_eq!(
+            bounded(fact, interval(1, 4)).unwrap().to_string(),
+            "[-12, 24]"
+        );
+        assert_eq!(bounded("x < 1", x).unwrap().to_string(), "true or false");
+    }
+
+    #[test]
+    fn errors() {
+        let x = interval(-1, 3);
+        let err = bounded("10 + 1 / (x - 2)", x).unwrap_err();
+        assert_eq!(err.kind, possibly(EvalErrorKind::DivisionByZero));
+        assert_eq!(err.path, [Step::Right]);
+        assert_eq!(err.to_string(), "possible division by zero");
+        assert_eq!(
+            bounded("1 / (x - x)", x).unwrap_err().kind,
+            possibly(EvalErrorKind::DivisionByZero)
+        );
+        assert_eq!(
+            bounded("1 / (x * 0)", x).unwrap_err().kind,
+            EvalErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            bounded("x % (x + 1)", x).unwrap_err().kind,
+            possibly(EvalErrorKind::RemainderByZero)
+        );
+        assert_eq!(
+            bounded("2 ^ (x - 1)", x).unwrap_err().kind,
+            possibly(EvalErrorKind::NegativeExponent)
+        );
+        assert_eq!(
+            bounded("x * 4611686018427387904", x).unwrap_err().kind,
+            possibly(EvalErrorKind::Overflow)
+        );
+        assert_eq!(
+            bounded("(x + 2) * 9223372036854775807", x)
+                .unwrap_err()
+                .kind,
+            possibly(EvalErrorKind::Overflow)
+        );
+        assert_eq!(
+            bounded("(x + 4) ^ 80", x).unwrap_err().kind,
+            EvalErrorKind::Overflow
+        );
+        assert_eq!(
+            bounded("if x < 1 then y else 0", x).unwrap_err(),
+            EvalError {
+                kind: possibly(EvalErrorKind::UnboundVariable(String::from("y"))),
+                path: vec![Step::Then]
+            }
+        );
+        assert_eq!(
+            bounded("x < 1 && x", x).unwrap_err().kind,
+            possibly(EvalErrorKind::TypeMismatch {
+                expected: Type::Boolean,
+                found: Type::Number
+            })
+        );
+        let mut env = Env::new();
+        env.bind("x", x).limit_calls(30);
+        let e = parse("let f(n) = if n == 0 then 0 else f(n - 1) in f(x)").unwrap();
+        assert_eq!(
+            bounds(&e, &env).unwrap_err(),
+            EvalError {
+                kind: possibly(EvalErrorKind::CallDepth(30)),
+                path: vec![Step::Body]
+            }
+        );
+    }
+
+    #[test]
+    fn deep() {
+        let x = interval(-1, 3);
+        assert_eq!(
+            bounded("let f(n) = if n == 0 then 0 else f(n - 1) in f(x)", x).unwrap_err(),
+            EvalError {
+                kind: possibly(EvalErrorKind::CallDepth(DEFAULT_CALL_LIMIT)),
+                path: vec![Step::Body]
+            }
+        );
+        for left_deep in [true, false] {
+            let e = deep_sum(200_000, left_deep);
+            assert_eq!(
+                bounds(&e, &Env::new()),
+                Ok(Bounds::Num(Interval::from(200_001)))
+            );
+        }
+    }
+
+    #[test]
+    fn random_sound() {
+        let (xs, ys) = (interval(-3, 4), interval(-5, -1));
+        let mut env = Env::new();
+        env.bind("x", xs).bind("y", ys);
+        let property = |e: &Expression| {
+            let bounds = bounds(e, &env);
+            for x in xs.lo..=xs.hi {
+                for y in ys.lo..=ys.hi {
+                    let mut point = Env::new();
+                    point.bind("x", x).bind("y", y);
+                    let val = eval_with(e, &point);
+                    let sound = match (&bounds, &val) {
+                        (Ok(Bounds::Num(bound)), Ok(Value::Num(val))) => bound.contains(*val),
+                        (Ok(Bounds::Bool(None)), Ok(Value::Bool(_))) => true,
+                        (Ok(Bounds::Bool(Some(bound))), Ok(Value::Bool(val))) => bound == val,
+                        (Err(err), _) if matches!(err.kind, EvalErrorKind::Possibly(_)) => true,
+                        (Err(_), Err(_)) => true,
+                        _ => false,
+                    };
+                    if !sound {
+                        return Err(format!("{bounds:?} at x = {x}, y = {y}: {val:?}"));
+                    }
+                }
+            }
+            Ok(())
+        };
+        if let Err(failure) = check_expressions(&Generator::new(), 500, 0, property) {
+            panic!("{failure}");
+        }
+    }
+}
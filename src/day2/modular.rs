@@ -0,0 +1,362 @@
+//! Integers modulo a fixed `M`, so that evaluating `1 / 3` modulo 7 gives `5`, the number that
+//! gives one when multiplied by three.
+
+use super::{power, EvalErrorKind};
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// An integer modulo `M`, kept as its representative from `0` to `M - 1`, which is also what
+/// orders and prints it.
+///
+/// With a prime `M` every number but zero has an inverse to divide by.
+///
+/// It also remembers the integer it was computed from, see [`Exact`], because a power depends on
+/// the whole exponent rather than on the exponent modulo `M`.
+#[derive(Debug, Clone, Copy)]
+pub struct Modular<const M: u64> {
+    val: u64,
+    exact: Exact,
+}
+
+/// What is known of the integer a [`Modular`] was computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exact {
+    Integer(i64),
+    /// An integer that does not fit in an `i64`.
+    Overflow,
+    /// Possibly not an integer, after an inverse or a division with a remainder.
+    Inexact,
+}
+
+impl Exact {
+    /// Apply `op` to the integer, which fails if the result overflows.
+    fn map(self, op: impl FnOnce(i64) -> Option<i64>) -> Exact {
+        match self {
+            Exact::Integer(a) => op(a).map_or(Exact::Overflow, Exact::Integer),
+            _ => self,
+        }
+    }
+
+    /// Combine with `other` by `op`, which fails if the result overflows. Once either is
+    /// inexact, so is the result.
+    fn zip_with(self, other: Exact, op: impl FnOnce(i64, i64) -> Option<i64>) -> Exact {
+        match (self, other) {
+            (Exact::Integer(a), Exact::Integer(b)) => {
+                op(a, b).map_or(Exact::Overflow, Exact::Integer)
+            }
+            (Exact::Inexact, _) | (_, Exact::Inexact) => Exact::Inexact,
+            _ => Exact::Overflow,
+        }
+    }
+}
+
+impl<const M: u64> Modular<M> {
+    pub fn value(&self) -> u64 {
+        self.val
+    }
+
+    /// The integer this was computed from, as far as it is known.
+    pub fn exact(&self) -> Exact {
+        self.exact
+    }
+
+    pub fn modulus(&self) -> u64 {
+        M
+    }
+
+    /// The number that gives one when multiplied by `self`, failing for zero and for numbers
+    /// sharing a factor with `M`.
+    pub fn inverse(self) -> Result<Self, EvalErrorKind> {
+        if self.val == 0 {
+            return Err(EvalErrorKind::DivisionByZero);
+        }
+        // The extended Euclidean algorithm, keeping only the coefficients of `self`.
+        let (mut r0, mut r1) = (i128::from(M), i128::from(self.val));
+        let (mut t0, mut t1) = (0i128, 1i128);
+        while r1 != 0 {
+            let quot = r0 / r1;
+            (r0, r1) = (r1, r0 - quot * r1);
+            (t0, t1) = (t1, t0 - quot * t1);
+        }
+        if r0 != 1 {
+            return Err(EvalErrorKind::NoInverse {
+                value: self.val,
+                modulus: M,
+            });
+        }
+        Ok(Modular {
+            val: t0.rem_euclid(i128::from(M)) as u64,
+            exact: Exact::Inexact,
+        })
+    }
+
+    /// `self` multiplied by the inverse of `other`.
+    pub fn checked_div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        let quotient = self * other.inverse()?;
+        // Only a division without remainder still gives an integer.
+        let exact = match (self.exact, other.exact) {
+            (Exact::Integer(a), Exact::Integer(b)) if a.checked_rem(b).is_some_and(|r| r != 0) => {
+                Exact::Inexact
+            }
+            (a, b) => a.zip_with(b, i64::checked_div),
+        };
+        Ok(Modular { exact, ..quotient })
+    }
+
+    /// `self` to the power `exp`, by repeated squaring.
+    pub fn pow(self, exp: u64) -> Self {
+        let one = Modular::from(1);
+        let result = power(self, exp, one, |a, b| Ok(a * b)).expect("multiplication cannot fail");
+        let exact = (self.exact).map(|base| base.checked_pow(u32::try_from(exp).ok()?));
+        Modular { exact, ..result }
+    }
+
+    /// `self` to the power of the integer `exp`, which for a negative `exp` is the inverse to the
+    /// power `-exp`.
+    pub fn checked_pow(self, exp: i64) -> Result<Self, EvalErrorKind> {
+        match exp {
+            0.. => Ok(self.pow(exp.unsigned_abs())),
+            _ => Ok(self.inverse()?.pow(exp.unsigned_abs())),
+        }
+    }
+}
+
+/// Only the representatives are compared, whatever integers they came from.
+impl<const M: u64> PartialEq for Modular<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.val == other.val
+    }
+}
+
+impl<const M: u64> Eq for Modular<M> {}
+
+impl<const M: u64> PartialOrd for Modular<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const M: u64> Ord for Modular<M> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.val.cmp(&other.val)
+    }
+}
+
+impl<const M: u64> Hash for Modular<M> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.val.hash(state);
+    }
+}
+
+impl<const M: u64> From<i64> for Modular<M> {
+    fn from(val: i64) -> Self {
+        const { assert!(M > 0, "modulus must be positive") };
+        Modular {
+            val: i128::from(val).rem_euclid(i128::from(M)) as u64,
+            exact: Exact::Integer(val),
+        }
+    }
+}
+
+impl<const M: u64> Add for Modular<M> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let sum = (u128::from(self.val) + u128::from(other.val)) % u128::from(M);
+        let exact = self.exact.zip_with(other.exact, i64::checked_add);
+        Modular {
+            val: sum as u64,
+            exact,
+        }
+    }
+}
+
+impl<const M: u64> Sub for Modular<M> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<const M: u64> Neg for Modular<M> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        let exact = self.exact.map(i64::checked_neg);
+        match self.val {
+            0 => Modular { exact, ..self },
+            val => Modular {
+                val: M - val,
+                exact,
+            },
+        }
+    }
+}
+
+impl<const M: u64> Mul for Modular<M> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let product = u128::from(self.val) * u128::from(other.val) % u128::from(M);
+        let exact = self.exact.zip_with(other.exact, i64::checked_mul);
+        Modular {
+            val: product as u64,
+            exact,
+        }
+    }
+}
+
+impl<const M: u64> fmt::Display for Modular<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.val)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{number::eval_number, Env, Value};
+
+    const PRIME: u64 = 1_000_000_007;
+
+    fn modular<const M: u64>(text: &str, x: i64) -> Result<u64, EvalErrorKind> {
+        let mut env = Env::new();
+        env.bind("x", Modular::<M>::from(x));
+        eval_number(&text.parse().unwrap(), &env)
+            .map_err(|e| e.kind)
+            .and_then(Value::number)
+            .map(|val| val.value())
+    }
+
+    #[test]
+    fn arithmetic() {
+        let m = Modular::<7>::from;
+        assert_eq!(m(-1).value(), 6);
+        assert_eq!(m(12).to_string(), "5");
+        assert_eq!(m(5) + m(4), m(2));
+        assert_eq!(m(2) - m(5), m(4));
+        assert_eq!(-m(0), m(0));
+        assert_eq!(m(3) * m(5), m(1));
+        assert_eq!(m(3).inverse(), Ok(m(5)));
+        assert_eq!(m(4).checked_div(m(2)), Ok(m(2)));
+        assert_eq!(m(1).checked_div(m(0)), Err(EvalErrorKind::DivisionByZero));
+        assert_eq!(m(3).pow(0), m(1));
+        assert_eq!(m(3).pow(6), m(1));
+
+        let big = Modular::<{ u64::MAX }>::from;
+        assert_eq!(big(-1) * big(-1), big(1));
+        assert_eq!(big(-1) + big(-1), big(-2));
+        assert_eq!(big(2).inverse().map(|inv| inv * big(2)), Ok(big(1)));
+        assert_eq!(Modular::<1>::from(5).value(), 0);
+
+        assert_eq!((m(6) * m(-7)).exact(), Exact::Integer(-42));
+        assert_eq!((m(i64::MAX) + m(1)).exact(), Exact::Overflow);
+        assert_eq!((-m(i64::MIN)).exact(), Exact::Overflow);
+        assert_eq!(m(2).pow(64).exact(), Exact::Overflow);
+        assert_eq!(
+            m(6).checked_div(m(-3)).map(|q| q.exact()),
+            Ok(Exact::Integer(-2))
+        );
+        assert_eq!(
+            m(7).checked_div(m(2)).map(|q| q.exact()),
+            Ok(Exact::Inexact)
+        );
+        let overflow = m(i64::MAX) * m(2);
+        assert_eq!(
+            overflow.checked_div(m(2)).map(|q| q.exact()),
+            Ok(Exact::Overflow)
+        );
+        assert_eq!((overflow + m(3).inverse().unwrap()).exact(), Exact::Inexact);
+    }
+
+    #[test]
+    fn inverses() {
+        for val in 1..100 {
+            let m = Modular::<PRIME>::from(val);
+            assert_eq!(m.inverse().map(|inv| inv * m), Ok(Modular::from(1)));
+        }
+        let m = Modular::<12>::from;
+        assert_eq!(m(5).inverse(), Ok(m(5)));
+        assert_eq!(
+            m(8).inverse(),
+            Err(EvalErrorKind::NoInverse {
+                value: 8,
+                modulus: 12
+            })
+        );
+        assert_eq!(
+            m(8).inverse().unwrap_err().to_string(),
+            "8 has no inverse modulo 12"
+        );
+    }
+
+    #[test]
+    fn eval() {
+        assert_eq!(modular::<PRIME>("(x + 1) / 2 * 4", 6), Ok(14));
+        assert_eq!(modular::<PRIME>("1 / 2 + 1 / 2", 0), Ok(1));
+        assert_eq!(modular::<PRIME>("x * (1 / x)", 123_456), Ok(1));
+        assert_eq!(modular::<PRIME>("-x", 1), Ok(PRIME - 1));
+        // Fermat's little theorem.
+        assert_eq!(modular::<PRIME>("x ^ 1000000006", 5), Ok(1));
+        assert_eq!(modular::<PRIME>("x ^ 1000000005 * x", 5), Ok(1));
+        assert_eq!(modular::<PRIME>("2 ^ 10", 0), Ok(1024));
+        // Exponents are integers, not numbers modulo `M`.
+        assert_eq!(modular::<PRIME>("x ^ 1000000007", 5), Ok(5));
+        assert_eq!(modular::<PRIME>("x ^ (1000000007 + 1)", 5), Ok(25));
+        assert_eq!(modular::<7>("x ^ 7", 2), Ok(2));
+        assert_eq!(modular::<7>("x ^ (7 + 1)", 3), Ok(2));
+        assert_eq!(modular::<7>("x ^ (x * 5)", 3), Ok(6));
+        assert_eq!(modular::<7>("x ^ -1", 3), Ok(5));
+        assert_eq!(modular::<7>("x ^ -2 * x ^ 2", 3), Ok(1));
+        assert_eq!(
+            modular::<7>("x ^ -1", 7),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+        assert_eq!(modular::<7>("x ^ (6 / 3)", 3), Ok(2));
+        assert_eq!(
+            modular::<7>("x ^ (1 / 2)", 3),
+            Err(EvalErrorKind::NonIntegerExponent)
+        );
+        // An exponent that overflows is too large to be known, rather than not an integer.
+        assert_eq!(
+            modular::<PRIME>("2 ^ (3 ^ 50)", 0),
+            Err(EvalErrorKind::Overflow)
+        );
+        assert_eq!(
+            modular::<7>("x ^ (9223372036854775807 + x)", 1),
+            Err(EvalErrorKind::Overflow)
+        );
+        assert_eq!(
+            modular::<7>("x ^ (3 ^ 50 / 2)", 3),
+            Err(EvalErrorKind::Overflow)
+        );
+        assert_eq!(
+            modular::<7>("x ^ (3 ^ 50 + 1 / 2)", 3),
+            Err(EvalErrorKind::NonIntegerExponent)
+        );
+        assert_eq!(modular::<PRIME>("x % 10", 1234), Ok(4));
+        assert_eq!(
+            modular::<PRIME>("x / (x - 3)", 3),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+        assert_eq!(
+            modular::<PRIME>("x % (x - 3)", 3),
+            Err(EvalErrorKind::RemainderByZero)
+        );
+        assert_eq!(
+            modular::<12>("1 / x", 10),
+            Err(EvalErrorKind::NoInverse {
+                value: 10,
+                modulus: 12
+            })
+        );
+        let fact = "let fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x)";
+        assert_eq!(modular::<PRIME>(fact, 30), Ok(109_361_473));
+        assert_eq!(modular::<101>(fact, 100), Ok(100));
+    }
+}
@@ -0,0 +1,455 @@
+//! Evaluating an [`Expression`] in any numeric type, not just `i64`.
+
+use super::{
+    modular::{Exact, Modular},
+    power,
+    rational::Rational,
+    walk, Arithmetic, Env, EvalError, EvalErrorKind, Expression, Operation, UnaryOperation, Value,
+};
+use crate::bigint::BigInt;
+
+/// A numeric type that expressions can be evaluated in.
+///
+/// Each type decides for itself when an operation fails, for example on division by zero or when
+/// the result is not representable.
+pub trait Number: Clone + PartialOrd {
+    /// The value of an integer literal.
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind>;
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind>;
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind>;
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind>;
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind>;
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind>;
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind>;
+
+    /// Negate, by default by subtracting from zero.
+    fn neg(self) -> Result<Self, EvalErrorKind> {
+        Self::from_i64(0)?.sub(self)
+    }
+
+    fn abs(self) -> Result<Self, EvalErrorKind> {
+        if self < Self::from_i64(0)? {
+            self.neg()
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn min(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(if other < self { other } else { self })
+    }
+
+    fn max(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(if other > self { other } else { self })
+    }
+
+    /// Apply a binary operation.
+    fn apply(self, op: Operation, other: Self) -> Result<Self, EvalErrorKind> {
+        match op {
+            Operation::Add => self.add(other),
+            Operation::Sub => self.sub(other),
+            Operation::Mul => self.mul(other),
+            Operation::Div => self.div(other),
+            Operation::Rem => self.rem(other),
+            Operation::Pow => self.pow(other),
+            Operation::Min => self.min(other),
+            Operation::Max => self.max(other),
+        }
+    }
+
+    /// Apply a unary operation.
+    fn apply_unary(self, op: UnaryOperation) -> Result<Self, EvalErrorKind> {
+        match op {
+            UnaryOperation::Neg => self.neg(),
+            UnaryOperation::Abs => self.abs(),
+        }
+    }
+}
+
+/// Evaluate `e` in the numeric type `N`, looking up variables in `env`.
+pub fn eval_number<N: Number>(e: &Expression, env: &Env<N>) -> Result<Value<N>, EvalError> {
+    walk(
+        e,
+        env,
+        N::from_i64,
+        |op, val| val.apply_unary(op),
+        |op, left, right| left.apply(op, right),
+        None,
+    )
+}
+
+/// Checked arithmetic, like [`Arithmetic::Checked`](super::Arithmetic::Checked).
+impl Number for i64 {
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind> {
+        Ok(val)
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind> {
+        self.checked_add(other).ok_or(EvalErrorKind::Overflow)
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind> {
+        self.checked_sub(other).ok_or(EvalErrorKind::Overflow)
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind> {
+        self.checked_mul(other).ok_or(EvalErrorKind::Overflow)
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        if other == 0 {
+            return Err(EvalErrorKind::DivisionByZero);
+        }
+        self.checked_div(other).ok_or(EvalErrorKind::Overflow)
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Arithmetic::Checked.apply(Operation::Rem, self, other)
+    }
+
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind> {
+        Arithmetic::Checked.apply(Operation::Pow, self, exp)
+    }
+}
+
+/// Floating point, where dividing by zero or leaving the finite range is an error rather than
+/// an infinity or NaN.
+impl Number for f64 {
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind> {
+        Ok(val as f64)
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind> {
+        finite(self + other)
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind> {
+        finite(self - other)
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind> {
+        finite(self * other)
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        if other == 0.0 {
+            return Err(EvalErrorKind::DivisionByZero);
+        }
+        finite(self / other)
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind> {
+        if other == 0.0 {
+            return Err(EvalErrorKind::RemainderByZero);
+        }
+        finite(self % other)
+    }
+
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind> {
+        if self == 0.0 && exp < 0.0 {
+            return Err(EvalErrorKind::DivisionByZero);
+        }
+        match self.powf(exp) {
+            // Only a negative base with a fractional exponent gives NaN.
+            val if val.is_nan() => Err(EvalErrorKind::NonIntegerExponent),
+            val => finite(val),
+        }
+    }
+}
+
+fn finite(val: f64) -> Result<f64, EvalErrorKind> {
+    if val.is_finite() {
+        Ok(val)
+    } else {
+        Err(EvalErrorKind::Overflow)
+    }
+}
+
+/// Exact fractions, which fail only when a numerator or denominator outgrows an `i64`.
+impl Number for Rational {
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind> {
+        Ok(Rational::from(val))
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Rational::apply(self, Operation::Add, other)
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Rational::apply(self, Operation::Sub, other)
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Rational::apply(self, Operation::Mul, other)
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Rational::apply(self, Operation::Div, other)
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Rational::apply(self, Operation::Rem, other)
+    }
+
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind> {
+        Rational::pow(self, exp)
+    }
+}
+
+/// Integers modulo `M`, where dividing multiplies by the inverse and fails if there is none.
+///
+/// The remainder, minimum and maximum act on the representatives from `0` to `M - 1`. A power
+/// needs its exponent as an integer that fits in an `i64`, see [`Modular::exact`], and a
+/// negative one raises the inverse of the base.
+impl<const M: u64> Number for Modular<M> {
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind> {
+        Ok(Modular::from(val))
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self + other)
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self - other)
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self * other)
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        self.checked_div(other)
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind> {
+        match other.value() {
+            0 => Err(EvalErrorKind::RemainderByZero),
+            divisor => Ok(Modular::from((self.value() % divisor) as i64)),
+        }
+    }
+
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind> {
+        match exp.exact() {
+            Exact::Integer(exp) => self.checked_pow(exp),
+            Exact::Overflow => Err(EvalErrorKind::Overflow),
+            Exact::Inexact => Err(EvalErrorKind::NonIntegerExponent),
+        }
+    }
+
+    fn neg(self) -> Result<Self, EvalErrorKind> {
+        Ok(-self)
+    }
+}
+
+/// The largest power of a [`BigInt`] worth computing, in bits.
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+/// Arbitrary precision integers, which only overflow on powers of more than
+/// [`MAX_POWER_BITS`] bits. Division truncates like `i64`.
+impl Number for BigInt {
+    fn from_i64(val: i64) -> Result<Self, EvalErrorKind> {
+        Ok(BigInt::from(val))
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self + other)
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self - other)
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalErrorKind> {
+        Ok(self * other)
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalErrorKind> {
+        match self.div_rem(&other) {
+            Some((quot, _)) => Ok(quot),
+            None => Err(EvalErrorKind::DivisionByZero),
+        }
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalErrorKind> {
+        match self.div_rem(&other) {
+            Some((_, rem)) => Ok(rem),
+            None => Err(EvalErrorKind::RemainderByZero),
+        }
+    }
+
+    fn pow(self, exp: Self) -> Result<Self, EvalErrorKind> {
+        if exp.is_negative() {
+            return Err(EvalErrorKind::NegativeExponent);
+        }
+        if self.bits() < 2 {
+            // Powers of -1, 0 and 1 only depend on whether the exponent is zero, odd or even.
+            let (_, parity) = exp.div_rem(&BigInt::from(2i64)).expect("nonzero divisor");
+            let exp = match (exp.is_zero(), parity.is_zero()) {
+                (true, _) => 0,
+                (false, false) => 1,
+                (false, true) => 2,
+            };
+            return power(self, exp, BigInt::from(1i64), |a, b| Ok(a * b));
+        }
+        match exp.to_i64() {
+            Some(exp) if self.bits().saturating_mul(exp as u64) <= MAX_POWER_BITS => {
+                power(self, exp as u64, BigInt::from(1i64), |a, b| Ok(a * b))
+            }
+            _ => Err(EvalErrorKind::Overflow),
+        }
+    }
+
+    fn neg(self) -> Result<Self, EvalErrorKind> {
+        Ok(-self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{eval_with, Step};
+
+    #[test]
+    fn same_tree_many_types() {
+        let e: Expression = "(x + 1) / 2 * y".parse().unwrap();
+
+        let mut env = Env::new();
+        env.bind("x", 6).bind("y", 4);
+        assert_eq!(eval_number::<i64>(&e, &env), Ok(Value::Num(12)));
+        assert_eq!(eval_number(&e, &env), eval_with(&e, &env));
+
+        let mut env = Env::new();
+        env.bind("x", 6.0).bind("y", 0.5);
+        assert_eq!(eval_number(&e, &env), Ok(Value::Num(1.75)));
+
+        let mut env = Env::new();
+        env.bind("x", Rational::from(6))
+            .bind("y", Rational::new(1, 3).unwrap());
+        assert_eq!(
+            eval_number(&e, &env),
+            Ok(Value::Num(Rational::new(7, 6).unwrap()))
+        );
+
+        let mut env = Env::new();
+        env.bind("x", BigInt::from(6i64))
+            .bind("y", BigInt::from(4i64));
+        assert_eq!(eval_number(&e, &env), Ok(Value::Num(BigInt::from(12i64))));
+
+        let mut env = Env::new();
+        env.bind("x", Modular::<101>::from(6))
+            .bind("y", Modular::from(4));
+        assert_eq!(eval_number(&e, &env), Ok(Value::Num(Modular::from(14))));
+    }
+
+    #[test]
+    fn failure_modes() {
+        let kind = |text: &str, x: f64| {
+            let mut env = Env::new();
+            env.bind("x", x);
+            eval_number(&text.parse().unwrap(), &env)
+                .map_err(|e| e.kind)
+                .and_then(Value::number)
+        };
+        assert_eq!(kind("1 / x", 0.0), Err(EvalErrorKind::DivisionByZero));
+        assert_eq!(kind("x * x", 1e200), Err(EvalErrorKind::Overflow));
+        assert_eq!(kind("x * x / 2", 1e100), Ok(5e199));
+
+        let e: Expression = "x * x * x * x - x / 7".parse().unwrap();
+        let mut env = Env::new();
+        env.bind("x", i64::MAX);
+        assert_eq!(
+            eval_number(&e, &env).unwrap_err().kind,
+            EvalErrorKind::Overflow
+        );
+        let mut env = Env::new();
+        env.bind("x", BigInt::from(i64::MAX));
+        assert_eq!(
+            eval_number(&e, &env).unwrap().to_string(),
+            "7237005577332262210834635695349653859421902880380109739571772076686093020600"
+        );
+
+        let e: Expression = "x * x / (x - x)".parse().unwrap();
+        assert_eq!(
+            eval_number(&e, &env),
+            Err(EvalError {
+                kind: EvalErrorKind::DivisionByZero,
+                path: vec![],
+            })
+        );
+        let e: Expression = "1 + x / (x - x)".parse().unwrap();
+        assert_eq!(eval_number(&e, &env).unwrap_err().path, [Step::Right]);
+    }
+
+    #[test]
+    fn more_operators() {
+        fn check<N: Number>(text: &str, x: N) -> Result<N, EvalErrorKind> {
+            let mut env = Env::new();
+            env.bind("x", x);
+            eval_number(&text.parse().unwrap(), &env)
+                .map_err(|e| e.kind)
+                .and_then(Value::number)
+        }
+        let text = "-min(x, 3) + abs(x - 10) * max(x % 4, 2) ^ 2";
+        assert_eq!(check(text, 6), Ok(13));
+        assert_eq!(check(text, 6.0), Ok(13.0));
+        assert_eq!(check(text, Rational::from(6)), Ok(Rational::from(13)));
+        assert_eq!(check(text, BigInt::from(6i64)), Ok(BigInt::from(13i64)));
+
+        assert_eq!(check("x ^ -1", 2), Err(EvalErrorKind::NegativeExponent));
+        assert_eq!(check("x ^ -1", 2.0), Ok(0.5));
+        assert_eq!(check("x ^ (1 / 2)", 9.0), Ok(3.0));
+        assert_eq!(
+            check("x ^ (1 / 3)", -8.0),
+            Err(EvalErrorKind::NonIntegerExponent)
+        );
+        assert_eq!(check("x ^ -1", 0.0), Err(EvalErrorKind::DivisionByZero));
+        assert_eq!(check("1 % x", 0.0), Err(EvalErrorKind::RemainderByZero));
+        assert_eq!(check("x % 2", -7.5), Ok(-1.5));
+
+        let half = Rational::new(7, 2).unwrap();
+        assert_eq!(check("x ^ -2", half).unwrap().to_string(), "4/49");
+        assert_eq!(check("x % 1", half).unwrap().to_string(), "1/2");
+        assert_eq!(check("-x % (1 / 3)", half).unwrap().to_string(), "-1/6");
+        assert_eq!(
+            check("x % (x - x)", half),
+            Err(EvalErrorKind::RemainderByZero)
+        );
+        assert_eq!(check("2 ^ x", half), Err(EvalErrorKind::NonIntegerExponent));
+        assert_eq!(
+            check("0 ^ -x", half),
+            Err(EvalErrorKind::NonIntegerExponent)
+        );
+        assert_eq!(
+            check("(x - x) ^ -1", half),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+
+        let big = |x: i64| BigInt::from(x);
+        assert_eq!(
+            check("x ^ 100", big(2)).unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(check("x % -3", big(-7)), Ok(big(-1)));
+        assert_eq!(
+            check("x ^ -1", big(2)),
+            Err(EvalErrorKind::NegativeExponent)
+        );
+        assert_eq!(check("x ^ 100000000", big(3)), Err(EvalErrorKind::Overflow));
+        let huge = check("x ^ 100", big(10)).unwrap();
+        assert_eq!(check("-1 ^ x", huge.clone()), Ok(big(1)));
+        assert_eq!(check("-1 ^ (x + 1)", huge.clone()), Ok(big(-1)));
+        assert_eq!(check("0 ^ x", huge), Ok(big(0)));
+
+        let fact = "let fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x)";
+        assert_eq!(check(fact, 20), Ok(2432902008176640000));
+        assert_eq!(check(fact, 21), Err(EvalErrorKind::Overflow));
+        assert_eq!(
+            check(fact, big(30)).unwrap().to_string(),
+            "265252859812191058636308480000000"
+        );
+        let avg = "let avg(a, b) = (a + b) / 2 in avg(x, avg(x, 2))";
+        assert_eq!(check(avg, 1.0), Ok(1.25));
+        assert_eq!(check(avg, Rational::from(1)).unwrap().to_string(), "5/4");
+    }
+}
@@ -0,0 +1,1477 @@
+//! Parsing infix text like `10 * 9 + (3 - 4) * 5` into an [`Expression`].
+
+use super::{Comparison, Connective, Expression, Function, Operation, UnaryOperation};
+use std::{
+    fmt,
+    iter::Peekable,
+    ops::Range,
+    str::{CharIndices, FromStr},
+};
+
+/// Why the input could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A character that does not start any token.
+    InvalidCharacter(char),
+    /// An integer literal that does not fit in an `i64`.
+    NumberOutOfRange,
+    /// A literal, variable or parenthesized expression was expected.
+    ExpectedOperand,
+    /// An operator or the end of input was expected.
+    ExpectedOperator,
+    /// A `(` without a matching `)`.
+    UnclosedParen,
+    /// A function call has too few arguments.
+    ExpectedComma,
+    /// A conditional is missing its `then` or `else`, or a binding its `in`.
+    ExpectedKeyword(&'static str),
+    /// A variable or function name was expected after `let`, or as a parameter.
+    ExpectedName,
+    /// A binding is missing the `=` before its value.
+    ExpectedEquals,
+    /// A function has two parameters with the same name.
+    DuplicateParameter(String),
+    /// A call or function definition in reverse Polish notation is missing its argument count.
+    ExpectedCount,
+    /// A function is defined with the name of a built-in operation like `min`, which calls
+    /// would never reach.
+    BuiltinName(String),
+}
+
+/// A parse failure, pointing at the offending byte range of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidCharacter(c) => write!(f, "invalid character {c:?}"),
+            ParseErrorKind::NumberOutOfRange => write!(f, "number out of range"),
+            ParseErrorKind::ExpectedOperand => write!(f, "expected an operand"),
+            ParseErrorKind::ExpectedOperator => write!(f, "expected an operator"),
+            ParseErrorKind::UnclosedParen => write!(f, "unclosed parenthesis"),
+            ParseErrorKind::ExpectedComma => write!(f, "expected `,`"),
+            ParseErrorKind::ExpectedKeyword(word) => write!(f, "expected `{word}`"),
+            ParseErrorKind::ExpectedName => write!(f, "expected a name"),
+            ParseErrorKind::ExpectedEquals => write!(f, "expected `=`"),
+            ParseErrorKind::DuplicateParameter(name) => write!(f, "duplicate parameter `{name}`"),
+            ParseErrorKind::ExpectedCount => write!(f, "expected an argument count"),
+            ParseErrorKind::BuiltinName(name) => write!(f, "`{name}` is a built-in operation"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind<'a> {
+    Number(&'a str),
+    Ident(&'a str),
+    Op(BinaryOp),
+    Bang,
+    Equals,
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token<'a> {
+    kind: TokenKind<'a>,
+    span: Range<usize>,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' => {
+                while let Some(&(i, '0'..='9')) = chars.peek() {
+                    end = i + 1;
+                    chars.next();
+                }
+                TokenKind::Number(&input[start..end])
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + 1;
+                    chars.next();
+                }
+                TokenKind::Ident(&input[start..end])
+            }
+            // Numbered results in the calculator, like `$2`, are plain variables.
+            '$' if matches!(chars.peek(), Some((_, '0'..='9'))) => {
+                while let Some(&(i, '0'..='9')) = chars.peek() {
+                    end = i + 1;
+                    chars.next();
+                }
+                TokenKind::Ident(&input[start..end])
+            }
+            '+' => TokenKind::Op(BinaryOp::Arith(Operation::Add)),
+            '-' => TokenKind::Op(BinaryOp::Arith(Operation::Sub)),
+            '*' => TokenKind::Op(BinaryOp::Arith(Operation::Mul)),
+            '/' => TokenKind::Op(BinaryOp::Arith(Operation::Div)),
+            '%' => TokenKind::Op(BinaryOp::Arith(Operation::Rem)),
+            '^' => TokenKind::Op(BinaryOp::Arith(Operation::Pow)),
+            '=' if followed_by(&mut chars, &mut end, '=') => {
+                TokenKind::Op(BinaryOp::Compare(Comparison::Eq))
+            }
+            '=' => TokenKind::Equals,
+            '!' if followed_by(&mut chars, &mut end, '=') => {
+                TokenKind::Op(BinaryOp::Compare(Comparison::Ne))
+            }
+            '!' => TokenKind::Bang,
+            '<' if followed_by(&mut chars, &mut end, '=') => {
+                TokenKind::Op(BinaryOp::Compare(Comparison::Le))
+            }
+            '<' => TokenKind::Op(BinaryOp::Compare(Comparison::Lt)),
+            '>' if followed_by(&mut chars, &mut end, '=') => {
+                TokenKind::Op(BinaryOp::Compare(Comparison::Ge))
+            }
+            '>' => TokenKind::Op(BinaryOp::Compare(Comparison::Gt)),
+            '&' if followed_by(&mut chars, &mut end, '&') => {
+                TokenKind::Op(BinaryOp::Logic(Connective::And))
+            }
+            '|' if followed_by(&mut chars, &mut end, '|') => {
+                TokenKind::Op(BinaryOp::Logic(Connective::Or))
+            }
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            ',' => TokenKind::Comma,
+            c => {
+                return Err(ParseError {
+                    kind: ParseErrorKind::InvalidCharacter(c),
+                    span: start..end,
+                })
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Consume the next character if it is `next`, extending the token to `end`.
+fn followed_by(chars: &mut Peekable<CharIndices>, end: &mut usize, next: char) -> bool {
+    match chars.next_if(|&(_, c)| c == next) {
+        Some((i, c)) => {
+            *end = i + c.len_utf8();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Words that cannot be used as variable or function names.
+const KEYWORDS: [&str; 7] = ["if", "then", "else", "let", "in", "fn", "call"];
+
+/// Any operator that can be written between its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Arith(Operation),
+    Compare(Comparison),
+    Logic(Connective),
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Arith(op) => op.precedence(),
+            BinaryOp::Compare(op) => op.precedence(),
+            BinaryOp::Logic(op) => op.precedence(),
+        }
+    }
+
+    fn build(self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = (Box::new(left), Box::new(right));
+        match self {
+            BinaryOp::Arith(op) => Expression::Op { op, left, right },
+            BinaryOp::Compare(op) => Expression::Compare { op, left, right },
+            BinaryOp::Logic(op) => Expression::Logic { op, left, right },
+        }
+    }
+}
+
+/// An operation written by name, or by a symbol at the start of an S-expression.
+enum Named {
+    Unary(UnaryOperation),
+    Binary(BinaryOp),
+    Not,
+    If,
+}
+
+/// The operation called `name`: `abs`, `neg`, `min`, `max` or `if`.
+fn named(name: &str) -> Option<Named> {
+    match name {
+        "neg" => Some(Named::Unary(UnaryOperation::Neg)),
+        "abs" => Some(Named::Unary(UnaryOperation::Abs)),
+        "min" => Some(Named::Binary(BinaryOp::Arith(Operation::Min))),
+        "max" => Some(Named::Binary(BinaryOp::Arith(Operation::Max))),
+        "if" => Some(Named::If),
+        _ => None,
+    }
+}
+
+impl Named {
+    /// How many operands this operation takes.
+    fn arity(&self) -> usize {
+        match self {
+            Named::Unary(_) | Named::Not => 1,
+            Named::Binary(_) => 2,
+            Named::If => 3,
+        }
+    }
+
+    /// Apply to operands, given in order.
+    fn build(self, operands: Vec<Expression>) -> Expression {
+        let mut operands = operands.into_iter();
+        let mut next = || operands.next().expect("too few operands");
+        match self {
+            Named::Unary(op) => Expression::Unary {
+                op,
+                operand: Box::new(next()),
+            },
+            Named::Binary(op) => {
+                let left = next();
+                op.build(left, next())
+            }
+            Named::Not => Expression::Not(Box::new(next())),
+            Named::If => Expression::If {
+                cond: Box::new(next()),
+                then: Box::new(next()),
+                otherwise: Box::new(next()),
+            },
+        }
+    }
+}
+
+/// What a parser does next, while it keeps the constructs it is inside on a stack of its own.
+enum Step {
+    /// Start parsing the next subexpression.
+    Operand,
+    /// Hand a finished subexpression to the innermost pending construct.
+    Done(Expression),
+}
+
+/// An infix construct waiting for its next subexpression.
+enum Pending {
+    /// Operators binding at least as tight as `min_prec`, with the left operand of an operator
+    /// whose right operand is being parsed.
+    Infix {
+        min_prec: u8,
+        left: Option<(Expression, BinaryOp)>,
+    },
+    /// The inside of a `(` at the given span.
+    Paren(Range<usize>),
+    /// The operand of `-` or `!`.
+    Prefix(Named),
+    /// The condition and branches of an `if` parsed so far.
+    If(Vec<Expression>),
+    /// The value of a `let`, then its body.
+    Let {
+        name: String,
+        params: Option<Vec<String>>,
+        value: Option<Expression>,
+    },
+    /// The arguments of a call to a built-in operation like `min(a, b)`.
+    Builtin {
+        function: Named,
+        open: Range<usize>,
+        args: Vec<Expression>,
+    },
+    /// The arguments of a call to a function.
+    Call {
+        name: String,
+        open: Range<usize>,
+        args: Vec<Expression>,
+    },
+}
+
+/// A list in an S-expression, opened at `open`, waiting for its next operand.
+enum PendingList {
+    Let {
+        open: Range<usize>,
+        name: String,
+        params: Option<Vec<String>>,
+        value: Option<Expression>,
+    },
+    Call {
+        open: Range<usize>,
+        name: String,
+        args: Vec<Expression>,
+    },
+    Named {
+        open: Range<usize>,
+        function: Named,
+        operands: Vec<Expression>,
+    },
+}
+
+/// Push `construct` and start on its next subexpression, made of operators binding at least as
+/// tight as `min_prec`.
+fn awaiting(pending: &mut Vec<Pending>, construct: Pending, min_prec: u8) -> Step {
+    pending.push(construct);
+    pending.push(Pending::Infix {
+        min_prec,
+        left: None,
+    });
+    Step::Operand
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    len: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, ParseError> {
+        Ok(Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            len: input.len(),
+        })
+    }
+
+    fn peek(&self) -> Option<TokenKind<'a>> {
+        self.lookahead(0)
+    }
+
+    /// The kind of the token `n` places after the next one.
+    fn lookahead(&self, n: usize) -> Option<TokenKind<'a>> {
+        self.tokens.get(self.pos + n).map(|t| t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// The span of the next token, or an empty span at the end of input.
+    fn here(&self) -> Range<usize> {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.len..self.len, |t| t.span.clone())
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind,
+            span: self.here(),
+        }
+    }
+
+    /// Consume a `)` closing the `(` at `open`.
+    fn close(&mut self, open: Range<usize>) -> Result<(), ParseError> {
+        self.expect(TokenKind::RParen, ParseErrorKind::ExpectedOperator, open)
+    }
+
+    /// Consume `token` inside the `(` at `open`, failing with `kind` if something else is next.
+    fn expect(
+        &mut self,
+        token: TokenKind<'a>,
+        kind: ParseErrorKind,
+        open: Range<usize>,
+    ) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(next) if next == token => {
+                self.next();
+                Ok(())
+            }
+            Some(_) => Err(self.error(kind)),
+            None => Err(ParseError {
+                kind: ParseErrorKind::UnclosedParen,
+                span: open,
+            }),
+        }
+    }
+
+    /// Check that all input was consumed.
+    fn finish(&self, expr: Expression) -> Result<Expression, ParseError> {
+        match self.peek() {
+            None => Ok(expr),
+            Some(_) => Err(self.error(ParseErrorKind::ExpectedOperator)),
+        }
+    }
+
+    /// Parse until the outermost construct in `pending` is complete, keeping pending
+    /// constructs on the heap rather than the call stack, so nesting is limited only by memory.
+    ///
+    /// `start` begins a subexpression, which it either finishes or opens with a construct
+    /// pushed to `pending`, and `resume` hands a finished subexpression to the innermost one.
+    fn nested<P>(
+        &mut self,
+        mut pending: Vec<P>,
+        start: fn(&mut Self, &mut Vec<P>) -> Result<Step, ParseError>,
+        resume: fn(&mut Self, P, Expression, &mut Vec<P>) -> Result<Step, ParseError>,
+    ) -> Result<Expression, ParseError> {
+        let mut step = Step::Operand;
+        loop {
+            step = match step {
+                Step::Operand => start(self, &mut pending)?,
+                Step::Done(expr) => match pending.pop() {
+                    Some(construct) => resume(self, construct, expr, &mut pending)?,
+                    None => return Ok(expr),
+                },
+            };
+        }
+    }
+
+    /// An infix expression, with operators parsed by precedence climbing.
+    fn infix(&mut self) -> Result<Expression, ParseError> {
+        let pending = vec![Pending::Infix {
+            min_prec: 0,
+            left: None,
+        }];
+        self.nested(pending, Parser::operand, Parser::resume)
+    }
+
+    fn operand(&mut self, pending: &mut Vec<Pending>) -> Result<Step, ParseError> {
+        let construct = match (self.peek(), self.lookahead(1)) {
+            (Some(TokenKind::LParen), _) => Pending::Paren(self.next().unwrap().span),
+            // A minus before a number is part of the literal, anything else is negated.
+            (Some(TokenKind::Op(BinaryOp::Arith(Operation::Sub))), next)
+                if !matches!(next, Some(TokenKind::Number(_))) =>
+            {
+                self.next();
+                Pending::Prefix(Named::Unary(UnaryOperation::Neg))
+            }
+            // Boolean negation binds like arithmetic negation.
+            (Some(TokenKind::Bang), _) => {
+                self.next();
+                Pending::Prefix(Named::Not)
+            }
+            (Some(TokenKind::Ident("if")), _) => {
+                self.next();
+                Pending::If(Vec::with_capacity(3))
+            }
+            (Some(TokenKind::Ident("let")), _) => {
+                self.next();
+                let span = self.here();
+                let name = self.name()?;
+                let params = match self.peek() {
+                    Some(TokenKind::LParen) => {
+                        function_name(&name, span)?;
+                        Some(self.params()?)
+                    }
+                    _ => None,
+                };
+                if self.peek() != Some(TokenKind::Equals) {
+                    return Err(self.error(ParseErrorKind::ExpectedEquals));
+                }
+                self.next();
+                Pending::Let {
+                    name,
+                    params,
+                    value: None,
+                }
+            }
+            (Some(TokenKind::Ident(name)), Some(TokenKind::LParen)) => match named(name) {
+                Some(function @ (Named::Unary(_) | Named::Binary(_))) => {
+                    self.next();
+                    let open = self.next().unwrap().span;
+                    let args = Vec::with_capacity(function.arity());
+                    Pending::Builtin {
+                        function,
+                        open,
+                        args,
+                    }
+                }
+                _ if !KEYWORDS.contains(&name) => {
+                    self.next();
+                    let open = self.next().unwrap().span;
+                    let name = name.to_string();
+                    if self.peek() == Some(TokenKind::RParen) {
+                        self.close(open)?;
+                        return Ok(Step::Done(Expression::Call { name, args: vec![] }));
+                    }
+                    Pending::Call {
+                        name,
+                        open,
+                        args: Vec::new(),
+                    }
+                }
+                _ => return self.leaf().map(Step::Done),
+            },
+            _ => return self.leaf().map(Step::Done),
+        };
+        let min_prec = match construct {
+            Pending::Prefix(_) => UnaryOperation::Neg.precedence(),
+            _ => 0,
+        };
+        Ok(awaiting(pending, construct, min_prec))
+    }
+
+    /// Continue `construct` now that `expr`, its latest subexpression, has been parsed.
+    fn resume(
+        &mut self,
+        construct: Pending,
+        expr: Expression,
+        pending: &mut Vec<Pending>,
+    ) -> Result<Step, ParseError> {
+        let (construct, min_prec) = match construct {
+            Pending::Infix { min_prec, left } => {
+                let left = match left {
+                    Some((left, op)) => op.build(left, expr),
+                    None => expr,
+                };
+                let op = match self.peek() {
+                    Some(TokenKind::Op(op)) if op.precedence() >= min_prec => op,
+                    _ => return Ok(Step::Done(left)),
+                };
+                self.next();
+                // For left associative operators the right side must bind tighter.
+                let right_prec = if op == BinaryOp::Arith(Operation::Pow) {
+                    op.precedence()
+                } else {
+                    op.precedence() + 1
+                };
+                let left = Some((left, op));
+                (Pending::Infix { min_prec, left }, right_prec)
+            }
+            Pending::Paren(open) => {
+                self.close(open)?;
+                return Ok(Step::Done(expr));
+            }
+            Pending::Prefix(function) => return Ok(Step::Done(function.build(vec![expr]))),
+            Pending::If(mut operands) => {
+                operands.push(expr);
+                match operands.len() {
+                    1 => self.keyword("then")?,
+                    2 => self.keyword("else")?,
+                    _ => return Ok(Step::Done(Named::If.build(operands))),
+                }
+                (Pending::If(operands), 0)
+            }
+            Pending::Let {
+                name,
+                params,
+                value: None,
+            } => {
+                self.keyword("in")?;
+                let value = Some(expr);
+                (
+                    Pending::Let {
+                        name,
+                        params,
+                        value,
+                    },
+                    0,
+                )
+            }
+            Pending::Let {
+                name,
+                params,
+                value: Some(value),
+            } => return Ok(Step::Done(binding(name, params, value, expr))),
+            Pending::Builtin {
+                function,
+                open,
+                mut args,
+            } => {
+                args.push(expr);
+                if args.len() == function.arity() {
+                    self.close(open)?;
+                    return Ok(Step::Done(function.build(args)));
+                }
+                self.expect(
+                    TokenKind::Comma,
+                    ParseErrorKind::ExpectedComma,
+                    open.clone(),
+                )?;
+                (
+                    Pending::Builtin {
+                        function,
+                        open,
+                        args,
+                    },
+                    0,
+                )
+            }
+            Pending::Call {
+                name,
+                open,
+                mut args,
+            } => {
+                args.push(expr);
+                if self.peek() != Some(TokenKind::Comma) {
+                    self.close(open)?;
+                    return Ok(Step::Done(Expression::Call { name, args }));
+                }
+                self.next();
+                (Pending::Call { name, open, args }, 0)
+            }
+        };
+        Ok(awaiting(pending, construct, min_prec))
+    }
+
+    /// A variable or function name being bound.
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) if !KEYWORDS.contains(&name) => {
+                self.next();
+                Ok(name.to_string())
+            }
+            _ => Err(self.error(ParseErrorKind::ExpectedName)),
+        }
+    }
+
+    /// Parenthesized parameter names, like `(x, y)`.
+    fn params(&mut self) -> Result<Vec<String>, ParseError> {
+        let open = self.next().unwrap().span;
+        let mut params: Vec<String> = Vec::new();
+        while self.peek() != Some(TokenKind::RParen) {
+            if !params.is_empty() {
+                self.expect(
+                    TokenKind::Comma,
+                    ParseErrorKind::ExpectedComma,
+                    open.clone(),
+                )?;
+            }
+            params.push(self.param(&params)?);
+        }
+        self.close(open)?;
+        Ok(params)
+    }
+
+    /// A parameter name that is not already in `params`.
+    fn param(&mut self, params: &[String]) -> Result<String, ParseError> {
+        let span = self.here();
+        let param = self.name()?;
+        if params.contains(&param) {
+            return Err(ParseError {
+                kind: ParseErrorKind::DuplicateParameter(param),
+                span,
+            });
+        }
+        Ok(param)
+    }
+
+    /// Consume the keyword `word`.
+    fn keyword(&mut self, word: &'static str) -> Result<(), ParseError> {
+        if self.peek() != Some(TokenKind::Ident(word)) {
+            return Err(self.error(ParseErrorKind::ExpectedKeyword(word)));
+        }
+        self.next();
+        Ok(())
+    }
+
+    /// A variable or literal.
+    fn leaf(&mut self) -> Result<Expression, ParseError> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) if !KEYWORDS.contains(&name) => {
+                self.next();
+                Ok(Expression::Var(name.to_string()))
+            }
+            _ => self.literal(),
+        }
+    }
+
+    /// An integer literal, optionally negated by a leading minus.
+    fn literal(&mut self) -> Result<Expression, ParseError> {
+        let start = self.here().start;
+        let negative = self.peek() == Some(TokenKind::Op(BinaryOp::Arith(Operation::Sub)));
+        if negative {
+            self.next();
+        }
+        match self.peek() {
+            Some(TokenKind::Number(digits)) => {
+                let span = start..self.next().unwrap().span.end;
+                if negative {
+                    number(&format!("-{digits}"), span)
+                } else {
+                    number(digits, span)
+                }
+            }
+            _ => Err(self.error(ParseErrorKind::ExpectedOperand)),
+        }
+    }
+
+    fn sexpr(&mut self) -> Result<Expression, ParseError> {
+        self.nested(Vec::new(), Parser::list, Parser::resume_list)
+    }
+
+    /// An S-expression, which is a leaf unless it starts with `(`.
+    fn list(&mut self, pending: &mut Vec<PendingList>) -> Result<Step, ParseError> {
+        if self.peek() != Some(TokenKind::LParen) {
+            return self.leaf().map(Step::Done);
+        }
+        let open = self.next().unwrap().span;
+        let function = match self.peek() {
+            Some(TokenKind::Op(op)) => Some(Named::Binary(op)),
+            Some(TokenKind::Bang) => Some(Named::Not),
+            Some(TokenKind::Ident("let")) => {
+                self.next();
+                let (name, params) = match self.peek() {
+                    Some(TokenKind::LParen) => {
+                        let open = self.next().unwrap().span;
+                        let span = self.here();
+                        let name = self.name()?;
+                        function_name(&name, span)?;
+                        let mut params: Vec<String> = Vec::new();
+                        while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                            params.push(self.param(&params)?);
+                        }
+                        self.close(open)?;
+                        (name, Some(params))
+                    }
+                    _ => (self.name()?, None),
+                };
+                pending.push(PendingList::Let {
+                    open,
+                    name,
+                    params,
+                    value: None,
+                });
+                return Ok(Step::Operand);
+            }
+            Some(TokenKind::Ident(name)) if named(name).is_none() && !KEYWORDS.contains(&name) => {
+                self.next();
+                let name = name.to_string();
+                if matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                    self.close(open)?;
+                    return Ok(Step::Done(Expression::Call { name, args: vec![] }));
+                }
+                pending.push(PendingList::Call {
+                    open,
+                    name,
+                    args: Vec::new(),
+                });
+                return Ok(Step::Operand);
+            }
+            Some(TokenKind::Ident(name)) => named(name),
+            _ => None,
+        };
+        let Some(function) = function else {
+            return Err(self.error(ParseErrorKind::ExpectedOperator));
+        };
+        self.next();
+        let operands = Vec::with_capacity(function.arity());
+        pending.push(PendingList::Named {
+            open,
+            function,
+            operands,
+        });
+        Ok(Step::Operand)
+    }
+
+    /// Continue the innermost list `construct` now that `expr`, its latest operand, has been
+    /// parsed.
+    fn resume_list(
+        &mut self,
+        construct: PendingList,
+        expr: Expression,
+        pending: &mut Vec<PendingList>,
+    ) -> Result<Step, ParseError> {
+        match construct {
+            PendingList::Let {
+                open,
+                name,
+                params,
+                value: None,
+            } => pending.push(PendingList::Let {
+                open,
+                name,
+                params,
+                value: Some(expr),
+            }),
+            PendingList::Let {
+                open,
+                name,
+                params,
+                value: Some(value),
+            } => {
+                self.close(open)?;
+                return Ok(Step::Done(binding(name, params, value, expr)));
+            }
+            PendingList::Call {
+                open,
+                name,
+                mut args,
+            } => {
+                args.push(expr);
+                if matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                    self.close(open)?;
+                    return Ok(Step::Done(Expression::Call { name, args }));
+                }
+                pending.push(PendingList::Call { open, name, args });
+            }
+            PendingList::Named {
+                open,
+                function,
+                mut operands,
+            } => {
+                operands.push(expr);
+                if operands.len() == function.arity() {
+                    self.close(open)?;
+                    return Ok(Step::Done(function.build(operands)));
+                }
+                pending.push(PendingList::Named {
+                    open,
+                    function,
+                    operands,
+                });
+            }
+        }
+        Ok(Step::Operand)
+    }
+}
+
+/// Check that a function being defined as `name` at `span` would not be shadowed by a built-in
+/// operation, since calls to it are parsed as the operation.
+fn function_name(name: &str, span: Range<usize>) -> Result<(), ParseError> {
+    match named(name) {
+        Some(Named::Unary(_) | Named::Binary(_)) => Err(ParseError {
+            kind: ParseErrorKind::BuiltinName(name.to_string()),
+            span,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// A `let` of a variable, or of a function if there are parameters.
+fn binding(
+    name: String,
+    params: Option<Vec<String>>,
+    value: Expression,
+    body: Expression,
+) -> Expression {
+    let body = Box::new(body);
+    match params {
+        None => Expression::Let {
+            name,
+            value: Box::new(value),
+            body,
+        },
+        Some(params) => Expression::Define {
+            name,
+            function: Box::new(Function {
+                params,
+                body: value,
+            }),
+            body,
+        },
+    }
+}
+
+fn number(text: &str, span: Range<usize>) -> Result<Expression, ParseError> {
+    text.parse().map(Expression::Value).map_err(|_| ParseError {
+        kind: ParseErrorKind::NumberOutOfRange,
+        span,
+    })
+}
+
+/// Parse an infix expression, honoring the usual precedence and associativity.
+///
+/// Powers (`^`) bind tightest and group to the right, then negation (`-` and `!`), then `*`, `/`
+/// and `%`, then `+` and `-`, then comparisons, `&&` and finally `||`. A minus sign directly
+/// before a number is part of the literal, so `-2 ^ 2` is 4 while `-x ^ 2` is `-(x ^ 2)`.
+/// `abs(x)`, `min(a, b)` and `max(a, b)` are written as calls, like other functions.
+///
+/// `if c then a else b`, `let x = value in body` and `let f(x, y) = definition in body` extend as
+/// far to the right as possible.
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let expr = parser.infix()?;
+    parser.finish(expr)
+}
+
+/// Parse an S-expression such as `(+ 1 (* 2 3))`, where named operations like `(neg x)`,
+/// `(min a b)` and `(if c a b)` take one, two and three operands.
+///
+/// Bindings are written `(let x value body)` and `(let (f x y) definition body)`, and any other
+/// name calls a function with the operands that follow, like `(f 1 2)`.
+pub fn parse_sexpr(input: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let expr = parser.sexpr()?;
+    parser.finish(expr)
+}
+
+/// Parse reverse Polish notation such as `1 2 3 * +`.
+///
+/// A minus sign directly followed by digits is a negative literal, otherwise it is subtraction.
+/// Negation and the other named operations are written as words, like `x neg`, `a b max` or
+/// `cond then else if`. Bindings and calls end in the name and a keyword, as described for
+/// [`Rpn`](super::print::Rpn).
+pub fn parse_rpn(input: &str) -> Result<Expression, ParseError> {
+    let mut stack = Vec::new();
+    let mut tokens = tokenize(input)?.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let function = match (token.kind, tokens.peek()) {
+            (TokenKind::Number(digits), _) => {
+                stack.push(number(digits, token.span)?);
+                continue;
+            }
+            (TokenKind::Ident(word @ ("let" | "fn" | "call")), _) => {
+                let e = rpn_binding(&mut stack, word, token.span)?;
+                stack.push(e);
+                continue;
+            }
+            (TokenKind::Ident(name), _) => match named(name) {
+                Some(function) => function,
+                None if KEYWORDS.contains(&name) => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::ExpectedOperand,
+                        span: token.span,
+                    })
+                }
+                None => {
+                    stack.push(Expression::Var(name.to_string()));
+                    continue;
+                }
+            },
+            (
+                TokenKind::Op(BinaryOp::Arith(Operation::Sub)),
+                Some(Token {
+                    kind: TokenKind::Number(digits),
+                    span,
+                }),
+            ) if span.start == token.span.end => {
+                stack.push(number(&format!("-{digits}"), token.span.start..span.end)?);
+                tokens.next();
+                continue;
+            }
+            (TokenKind::Op(op), _) => Named::Binary(op),
+            (TokenKind::Bang, _) => Named::Not,
+            (TokenKind::LParen | TokenKind::RParen | TokenKind::Comma | TokenKind::Equals, _) => {
+                let c = input[token.span.clone()].chars().next().unwrap();
+                return Err(ParseError {
+                    kind: ParseErrorKind::InvalidCharacter(c),
+                    span: token.span,
+                });
+            }
+        };
+        let Some(first) = stack.len().checked_sub(function.arity()) else {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedOperand,
+                span: token.span,
+            });
+        };
+        let operands = stack.split_off(first);
+        stack.push(function.build(operands));
+    }
+    let end = input.len()..input.len();
+    match (stack.pop(), stack.is_empty()) {
+        (Some(expr), true) => Ok(expr),
+        (Some(_), false) => Err(ParseError {
+            kind: ParseErrorKind::ExpectedOperator,
+            span: end,
+        }),
+        (None, _) => Err(ParseError {
+            kind: ParseErrorKind::ExpectedOperand,
+            span: end,
+        }),
+    }
+}
+
+/// Build a `let`, `fn` or `call` from the top of an RPN stack, which holds the name on top of
+/// the argument count, if there is one, and the operands.
+fn rpn_binding(
+    stack: &mut Vec<Expression>,
+    word: &str,
+    span: Range<usize>,
+) -> Result<Expression, ParseError> {
+    let error = |kind| ParseError {
+        kind,
+        span: span.clone(),
+    };
+    let pop = |stack: &mut Vec<Expression>| {
+        stack
+            .pop()
+            .ok_or_else(|| error(ParseErrorKind::ExpectedOperand))
+    };
+    let name = match &pop(stack)? {
+        Expression::Var(name) => name.clone(),
+        _ => return Err(error(ParseErrorKind::ExpectedName)),
+    };
+    if word == "let" {
+        let body = pop(stack)?;
+        return Ok(binding(name, None, pop(stack)?, body));
+    }
+    let count = match pop(stack)? {
+        Expression::Value(count) => usize::try_from(count).ok(),
+        _ => None,
+    };
+    let count = count.ok_or_else(|| error(ParseErrorKind::ExpectedCount))?;
+    let first = stack
+        .len()
+        .checked_sub(count)
+        .ok_or_else(|| error(ParseErrorKind::ExpectedOperand))?;
+    let operands = stack.split_off(first);
+    if word == "call" {
+        return Ok(Expression::Call {
+            name,
+            args: operands,
+        });
+    }
+    let mut params: Vec<String> = Vec::new();
+    for operand in &operands {
+        match operand {
+            Expression::Var(param) if params.contains(param) => {
+                return Err(error(ParseErrorKind::DuplicateParameter(param.clone())))
+            }
+            Expression::Var(param) => params.push(param.clone()),
+            _ => return Err(error(ParseErrorKind::ExpectedName)),
+        }
+    }
+    let body = pop(stack)?;
+    Ok(binding(name, Some(params), pop(stack)?, body))
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::eval;
+
+    fn op(op: Operation, left: Expression, right: Expression) -> Expression {
+        Expression::Op {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn unary(op: UnaryOperation, operand: Expression) -> Expression {
+        Expression::Unary {
+            op,
+            operand: Box::new(operand),
+        }
+    }
+
+    fn err(kind: ParseErrorKind, span: Range<usize>) -> Result<Expression, ParseError> {
+        Err(ParseError { kind, span })
+    }
+
+    #[test]
+    fn precedence() {
+        use Expression::Value;
+        assert_eq!(
+            parse("10 * 9 + (3 - 4) * 5"),
+            Ok(op(
+                Operation::Add,
+                op(Operation::Mul, Value(10), Value(9)),
+                op(
+                    Operation::Mul,
+                    op(Operation::Sub, Value(3), Value(4)),
+                    Value(5)
+                ),
+            ))
+        );
+        assert_eq!(eval(parse("10 * 9 + (3 - 4) * 5").unwrap()), Ok(85.into()));
+        assert_eq!(eval(parse("2 + 3 * 4").unwrap()), Ok(14.into()));
+        assert_eq!(eval(parse("(2 + 3) * 4").unwrap()), Ok(20.into()));
+    }
+
+    #[test]
+    fn associativity() {
+        use Expression::Value;
+        assert_eq!(
+            parse("8 - 4 - 2"),
+            Ok(op(
+                Operation::Sub,
+                op(Operation::Sub, Value(8), Value(4)),
+                Value(2)
+            ))
+        );
+        assert_eq!(eval(parse("100 / 10 / 5").unwrap()), Ok(2.into()));
+        assert_eq!(eval(parse("8 - (4 - 2)").unwrap()), Ok(6.into()));
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(parse("42"), Ok(Expression::Value(42)));
+        assert_eq!(parse(" ((-7)) "), Ok(Expression::Value(-7)));
+        assert_eq!(eval(parse("3 - -4").unwrap()), Ok(7.into()));
+        assert_eq!(
+            "1+2".parse(),
+            Ok(op(
+                Operation::Add,
+                Expression::Value(1),
+                Expression::Value(2)
+            ))
+        );
+        assert_eq!(
+            parse("-9223372036854775808"),
+            Ok(Expression::Value(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn variables() {
+        use Expression::{Value, Var};
+        assert_eq!(
+            parse("rate_2 * (x - 1)"),
+            Ok(op(
+                Operation::Mul,
+                Var(String::from("rate_2")),
+                op(Operation::Sub, Var(String::from("x")), Value(1))
+            ))
+        );
+        assert_eq!(
+            parse_rpn("x 2 /"),
+            Ok(op(Operation::Div, Var(String::from("x")), Value(2)))
+        );
+        assert_eq!(
+            parse("-x"),
+            Ok(Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand: Box::new(Var(String::from("x"))),
+            })
+        );
+        assert_eq!(parse("$12"), Ok(Var(String::from("$12"))));
+        assert_eq!(
+            parse("$x"),
+            err(ParseErrorKind::InvalidCharacter('$'), 0..1)
+        );
+    }
+
+    #[test]
+    fn operators() {
+        use Expression::{Value, Var};
+        let neg = |e| unary(UnaryOperation::Neg, e);
+        let x = || Var(String::from("x"));
+        assert_eq!(
+            parse("2 ^ 3 ^ x"),
+            Ok(op(
+                Operation::Pow,
+                Value(2),
+                op(Operation::Pow, Value(3), x())
+            ))
+        );
+        assert_eq!(parse("-x ^ 2"), Ok(neg(op(Operation::Pow, x(), Value(2)))));
+        assert_eq!(parse("-2 ^ 2"), Ok(op(Operation::Pow, Value(-2), Value(2))));
+        assert_eq!(
+            parse("-x * 2 % 3"),
+            Ok(op(
+                Operation::Rem,
+                op(Operation::Mul, neg(x()), Value(2)),
+                Value(3)
+            ))
+        );
+        assert_eq!(parse("--x"), Ok(neg(neg(x()))));
+        assert_eq!(
+            parse("max(abs(x), -(1 + 2))"),
+            Ok(op(
+                Operation::Max,
+                unary(UnaryOperation::Abs, x()),
+                neg(op(Operation::Add, Value(1), Value(2)))
+            ))
+        );
+        assert_eq!(
+            parse("abs * 2"),
+            Ok(op(Operation::Mul, Var(String::from("abs")), Value(2)))
+        );
+        assert_eq!(parse("min(1)"), err(ParseErrorKind::ExpectedComma, 5..6));
+        assert_eq!(parse("min(1, 2"), err(ParseErrorKind::UnclosedParen, 3..4));
+        assert_eq!(
+            parse("abs(1, 2)"),
+            err(ParseErrorKind::ExpectedOperator, 5..6)
+        );
+        assert_eq!(parse("then(1)"), err(ParseErrorKind::ExpectedOperand, 0..4));
+        assert_eq!(
+            parse_sexpr("(min (neg x) (^ 2 3))"),
+            parse("min(-x, 2 ^ 3)")
+        );
+        assert_eq!(parse_rpn("x neg 2 3 ^ min"), parse("min(-x, 2 ^ 3)"));
+        assert_eq!(parse_rpn("abs"), err(ParseErrorKind::ExpectedOperand, 0..3));
+        assert_eq!(
+            parse_rpn("1, 2"),
+            err(ParseErrorKind::InvalidCharacter(','), 1..2)
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        use Expression::{Value, Var};
+        let var = |name: &str| Var(String::from(name));
+        let compare = |op, left, right| Expression::Compare {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        let logic = |op, left, right| Expression::Logic {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        assert_eq!(
+            parse("!a || b && x + 1 <= 2"),
+            Ok(logic(
+                Connective::Or,
+                Expression::Not(Box::new(var("a"))),
+                logic(
+                    Connective::And,
+                    var("b"),
+                    compare(
+                        Comparison::Le,
+                        op(Operation::Add, var("x"), Value(1)),
+                        Value(2)
+                    )
+                )
+            ))
+        );
+        assert_eq!(
+            parse("if x != 0 then 1 else 2 + 3"),
+            Ok(Expression::If {
+                cond: Box::new(compare(Comparison::Ne, var("x"), Value(0))),
+                then: Box::new(Value(1)),
+                otherwise: Box::new(op(Operation::Add, Value(2), Value(3))),
+            })
+        );
+        assert_eq!(
+            parse("(if a then 1 else 2) * 3"),
+            parse_sexpr("(* (if a 1 2) 3)")
+        );
+        assert_eq!(parse("a>=b==c<d"), parse_rpn("a b >= c == d <"));
+
+        assert_eq!(
+            parse("if x 1 else 2"),
+            err(ParseErrorKind::ExpectedKeyword("then"), 5..6)
+        );
+        assert_eq!(
+            parse("if x then 1"),
+            err(ParseErrorKind::ExpectedKeyword("else"), 11..11)
+        );
+        assert_eq!(
+            parse("then + 1"),
+            err(ParseErrorKind::ExpectedOperand, 0..4)
+        );
+        assert_eq!(parse("x = 1"), err(ParseErrorKind::ExpectedOperator, 2..3));
+        assert_eq!(
+            parse("a & b"),
+            err(ParseErrorKind::InvalidCharacter('&'), 2..3)
+        );
+        assert_eq!(
+            parse_rpn("a b else"),
+            err(ParseErrorKind::ExpectedOperand, 4..8)
+        );
+        assert_eq!(
+            ParseErrorKind::ExpectedKeyword("else").to_string(),
+            "expected `else`"
+        );
+    }
+
+    #[test]
+    fn functions() {
+        use Expression::{Value, Var};
+        let var = |name: &str| Var(String::from(name));
+        assert_eq!(
+            parse("let x = 1 in x + 2"),
+            Ok(Expression::Let {
+                name: String::from("x"),
+                value: Box::new(Value(1)),
+                body: Box::new(op(Operation::Add, var("x"), Value(2))),
+            })
+        );
+        assert_eq!(
+            parse("let f(a, b) = a * b in f(2, g()) + 1"),
+            Ok(Expression::Define {
+                name: String::from("f"),
+                function: Box::new(Function {
+                    params: vec![String::from("a"), String::from("b")],
+                    body: op(Operation::Mul, var("a"), var("b")),
+                }),
+                body: Box::new(op(
+                    Operation::Add,
+                    Expression::Call {
+                        name: String::from("f"),
+                        args: vec![
+                            Value(2),
+                            Expression::Call {
+                                name: String::from("g"),
+                                args: vec![],
+                            }
+                        ],
+                    },
+                    Value(1)
+                )),
+            })
+        );
+        assert_eq!(
+            parse("let f(a, b) = a * b in f(2, g()) + 1"),
+            parse_sexpr("(let (f a b) (* a b) (+ (f 2 (g)) 1))")
+        );
+        assert_eq!(
+            parse("let f(a, b) = a * b in f(2, g()) + 1"),
+            parse_rpn("a b * 2 0 g call 2 f call 1 + a b 2 f fn")
+        );
+        assert_eq!(parse("(let x = 1 in x) * x"), parse_rpn("1 x x let x *"));
+
+        assert_eq!(
+            parse("let 1 = 2 in 3"),
+            err(ParseErrorKind::ExpectedName, 4..5)
+        );
+        assert_eq!(
+            parse("let x 1 in x"),
+            err(ParseErrorKind::ExpectedEquals, 6..7)
+        );
+        assert_eq!(
+            parse("let x = 1 x"),
+            err(ParseErrorKind::ExpectedKeyword("in"), 10..11)
+        );
+        assert_eq!(
+            parse("let f(a, a) = a in 1"),
+            err(ParseErrorKind::DuplicateParameter(String::from("a")), 9..10)
+        );
+        assert_eq!(
+            parse("let f(a b) = a in 1"),
+            err(ParseErrorKind::ExpectedComma, 8..9)
+        );
+        assert_eq!(
+            parse_rpn("1 2 3 let"),
+            err(ParseErrorKind::ExpectedName, 6..9)
+        );
+        assert_eq!(
+            parse_rpn("1 x f call"),
+            err(ParseErrorKind::ExpectedCount, 6..10)
+        );
+        assert_eq!(
+            parse_rpn("1 2 a a 2 f fn"),
+            err(
+                ParseErrorKind::DuplicateParameter(String::from("a")),
+                12..14
+            )
+        );
+        assert_eq!(
+            ParseErrorKind::DuplicateParameter(String::from("a")).to_string(),
+            "duplicate parameter `a`"
+        );
+        assert_eq!(
+            parse("let min(a, b) = a + b in min(1, 2)"),
+            err(ParseErrorKind::BuiltinName(String::from("min")), 4..7)
+        );
+        assert_eq!(
+            parse_sexpr("(let (abs a) a (abs -1))"),
+            err(ParseErrorKind::BuiltinName(String::from("abs")), 6..9)
+        );
+        // Only functions clash with the operations, variables are never called.
+        assert_eq!(
+            parse("let max = 2 in max(max, 1)"),
+            parse_sexpr("(let max 2 (max max 1))")
+        );
+        assert_eq!(
+            ParseErrorKind::BuiltinName(String::from("neg")).to_string(),
+            "`neg` is a built-in operation"
+        );
+    }
+
+    #[test]
+    fn deep() {
+        let depth = 100_000;
+        let nested = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse(&nested), Ok(Expression::Value(1)));
+        let unclosed = format!("{}1{}", "(".repeat(depth), ")".repeat(depth - 1));
+        assert_eq!(parse(&unclosed), err(ParseErrorKind::UnclosedParen, 0..1));
+        for (prefix, leaf) in [("- ", "x"), ("2 ^ ", "2"), ("if x then 1 else ", "x")] {
+            let e = parse(&format!("{}{leaf}", prefix.repeat(depth))).unwrap();
+            assert_eq!(e.depth(), depth);
+        }
+        let calls = format!("{}1{}", "abs(f(".repeat(depth), "))".repeat(depth));
+        assert_eq!(parse(&calls).unwrap().depth(), 2 * depth);
+        let sexpr = format!("{}1{}", "(abs ".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse_sexpr(&sexpr).unwrap().depth(), depth);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), err(ParseErrorKind::ExpectedOperand, 0..0));
+        assert_eq!(parse("1 +"), err(ParseErrorKind::ExpectedOperand, 3..3));
+        assert_eq!(parse("1 + * 2"), err(ParseErrorKind::ExpectedOperand, 4..5));
+        assert_eq!(parse("1 2"), err(ParseErrorKind::ExpectedOperator, 2..3));
+        assert_eq!(parse("(1 + 2"), err(ParseErrorKind::UnclosedParen, 0..1));
+        assert_eq!(parse("(1 2)"), err(ParseErrorKind::ExpectedOperator, 3..4));
+        assert_eq!(parse("1 + 2)"), err(ParseErrorKind::ExpectedOperator, 5..6));
+        assert_eq!(
+            parse("2 $ 3"),
+            err(ParseErrorKind::InvalidCharacter('$'), 2..3)
+        );
+        assert_eq!(
+            parse("1 + 99999999999999999999"),
+            err(ParseErrorKind::NumberOutOfRange, 4..24)
+        );
+        assert_eq!(
+            parse("(1 +").unwrap_err().to_string(),
+            "expected an operand at 4..4"
+        );
+    }
+
+    #[test]
+    fn sexpr() {
+        use Expression::Value;
+        assert_eq!(
+            parse_sexpr("(- (* 2 3) -4)"),
+            Ok(op(
+                Operation::Sub,
+                op(Operation::Mul, Value(2), Value(3)),
+                Value(-4)
+            ))
+        );
+        assert_eq!(
+            parse_sexpr("(+ 1"),
+            err(ParseErrorKind::ExpectedOperand, 4..4)
+        );
+        assert_eq!(
+            parse_sexpr("(+ 1 2"),
+            err(ParseErrorKind::UnclosedParen, 0..1)
+        );
+        assert_eq!(
+            parse_sexpr("(1 2)"),
+            err(ParseErrorKind::ExpectedOperator, 1..2)
+        );
+        assert_eq!(
+            parse_sexpr("(+ 1 2 3)"),
+            err(ParseErrorKind::ExpectedOperator, 7..8)
+        );
+    }
+
+    #[test]
+    fn rpn() {
+        use Expression::Value;
+        assert_eq!(
+            parse_rpn("2 3 * -4 -"),
+            Ok(op(
+                Operation::Sub,
+                op(Operation::Mul, Value(2), Value(3)),
+                Value(-4)
+            ))
+        );
+        assert_eq!(parse_rpn("1 +"), err(ParseErrorKind::ExpectedOperand, 2..3));
+        assert_eq!(
+            parse_rpn("1 2"),
+            err(ParseErrorKind::ExpectedOperator, 3..3)
+        );
+        assert_eq!(parse_rpn(""), err(ParseErrorKind::ExpectedOperand, 0..0));
+        assert_eq!(
+            parse_rpn("1 (2"),
+            err(ParseErrorKind::InvalidCharacter('('), 2..3)
+        );
+    }
+}
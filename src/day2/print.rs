@@ -0,0 +1,536 @@
+//! Rendering an [`Expression`] as infix, S-expression or reverse Polish text.
+
+use super::{
+    visit::{self, Next, Visitor},
+    Comparison, Connective, EvalError, Expression, Operation, Step, UnaryOperation,
+};
+use std::{
+    convert::Infallible,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Display for Connective {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Display for UnaryOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether `e` is written starting with a digit, so that a minus in front would make it a
+/// negative literal.
+fn starts_with_digit(e: &Expression) -> bool {
+    match e {
+        Expression::Value(val) => *val >= 0,
+        // The base of a power is only parenthesized if it is not a leaf.
+        Expression::Op {
+            op: Operation::Pow,
+            left,
+            ..
+        } => matches!(**left, Expression::Value(val) if val >= 0),
+        _ => false,
+    }
+}
+
+/// Binding strength of `e` in infix notation, see [`Operation::precedence`].
+pub(super) fn precedence(e: &Expression) -> u8 {
+    match e {
+        Expression::Value(_) | Expression::Var(_) | Expression::Call { .. } => u8::MAX,
+        Expression::Op { op, .. } => op.precedence(),
+        Expression::Unary { op, .. } => op.precedence(),
+        Expression::Compare { op, .. } => op.precedence(),
+        Expression::Logic { op, .. } => op.precedence(),
+        Expression::Not(_) => UnaryOperation::Neg.precedence(),
+        // The else branch and the body of a binding extend as far as possible, so these always
+        // need parentheses inside another expression.
+        Expression::If { .. } | Expression::Let { .. } | Expression::Define { .. } => 0,
+    }
+}
+
+/// Write `e` in infix notation, along with the byte range of the subexpression at the `target`
+/// path, if there is one.
+fn infix(e: &Expression, target: Option<&[Step]>) -> (String, Range<usize>) {
+    let mut writer = InfixWriter {
+        out: String::new(),
+        min_prec: 0,
+        target,
+        open: Vec::new(),
+        span: 0..0,
+    };
+    let Ok(()) = visit::visit(e, &mut writer);
+    (writer.out, writer.span)
+}
+
+/// Writes each node in infix notation around its subexpressions: up to the first on entering
+/// it, the text between them before going into each of the others, and the rest on leaving.
+struct InfixWriter<'t> {
+    out: String,
+    /// How tightly the next expression entered must bind to go without parentheses.
+    min_prec: u8,
+    /// The rest of the path to the target from the next expression entered.
+    target: Option<&'t [Step]>,
+    /// For each expression being written, where it starts in `out`, whether it is
+    /// parenthesized, and the rest of the path to the target from it.
+    open: Vec<(usize, bool, Option<&'t [Step]>)>,
+    /// The byte range of the target, once it has been written.
+    span: Range<usize>,
+}
+
+impl<'e> Visitor<'e> for InfixWriter<'_> {
+    type Error = Infallible;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let parens = precedence(e) < self.min_prec;
+        self.open.push((self.out.len(), parens, self.target));
+        if parens {
+            self.out.push('(');
+        }
+        let start = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => name.clone(),
+            Expression::Op {
+                op: op @ (Operation::Min | Operation::Max),
+                ..
+            } => format!("{op}("),
+            Expression::Unary {
+                op: UnaryOperation::Abs,
+                ..
+            } => String::from("abs("),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand,
+            } if starts_with_digit(operand) => String::from("-("),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                ..
+            } => String::from("-"),
+            Expression::Not(_) => String::from("!"),
+            Expression::If { .. } => String::from("if "),
+            Expression::Let { name, .. } => format!("let {name} = "),
+            Expression::Define { name, function, .. } => {
+                format!("let {name}({}) = ", function.params.join(", "))
+            }
+            Expression::Call { name, .. } => format!("{name}("),
+            Expression::Op { .. } | Expression::Compare { .. } | Expression::Logic { .. } => {
+                String::new()
+            }
+        };
+        self.out.push_str(&start);
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        let Some((step, child)) = e.child(visited) else {
+            return Ok(Next::Leave);
+        };
+        let prec = precedence(e);
+        // An equally binding operand needs parentheses on the side the operator does not
+        // associate to.
+        let binary = |symbol: &str, right_assoc: bool| {
+            let min_prec = if (visited == 0) == right_assoc {
+                prec + 1
+            } else {
+                prec
+            };
+            (format!(" {symbol} "), min_prec)
+        };
+        let (between, min_prec) = match e {
+            Expression::Op {
+                op: Operation::Min | Operation::Max,
+                ..
+            }
+            | Expression::Call { .. } => (String::from(", "), 0),
+            Expression::Op { op, .. } => binary(op.symbol(), op.right_associative()),
+            Expression::Compare { op, .. } => binary(op.symbol(), false),
+            Expression::Logic { op, .. } => binary(op.symbol(), false),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand,
+            } if !starts_with_digit(operand) => (String::new(), prec),
+            Expression::Not(_) => (String::new(), prec),
+            Expression::If { .. } if visited == 1 => (String::from(" then "), 0),
+            Expression::If { .. } => (String::from(" else "), 0),
+            Expression::Let { .. } | Expression::Define { .. } => (String::from(" in "), 0),
+            _ => (String::new(), 0),
+        };
+        if visited > 0 {
+            self.out.push_str(&between);
+        }
+        self.min_prec = min_prec;
+        let target = self.open.last().and_then(|&(_, _, target)| target);
+        self.target = match target.and_then(|target| target.split_first()) {
+            Some((first, rest)) if *first == step => Some(rest),
+            _ => None,
+        };
+        Ok(Next::Child(step, child))
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        match e {
+            Expression::Op {
+                op: Operation::Min | Operation::Max,
+                ..
+            }
+            | Expression::Unary {
+                op: UnaryOperation::Abs,
+                ..
+            }
+            | Expression::Call { .. } => self.out.push(')'),
+            Expression::Unary {
+                op: UnaryOperation::Neg,
+                operand,
+            } if starts_with_digit(operand) => self.out.push(')'),
+            _ => {}
+        }
+        let (start, parens, target) = self.open.pop().expect("left an expression not entered");
+        if parens {
+            self.out.push(')');
+        }
+        if target == Some(&[]) {
+            self.span = start..self.out.len();
+        }
+        Ok(())
+    }
+}
+
+/// Infix notation with only the parentheses needed to parse back to the same tree.
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&infix(self, None).0)
+    }
+}
+
+impl EvalError {
+    /// Describe the error, underlining the failing subexpression of `root`.
+    pub fn report(&self, root: &Expression) -> String {
+        let (out, span) = infix(root, Some(&self.path));
+        format!(
+            "{self}\n{out}\n{}{}\n",
+            " ".repeat(span.start),
+            "^".repeat(span.len())
+        )
+    }
+}
+
+/// Displays an expression as an S-expression, like `(+ 1 (* 2 3))`.
+pub struct SExpr<'a>(pub &'a Expression);
+
+impl Display for SExpr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut writer = SExprWriter(String::new());
+        let Ok(()) = visit::visit(self.0, &mut writer);
+        f.write_str(&writer.0)
+    }
+}
+
+/// Writes each list with its operation or name on entering it, a space before going into each
+/// operand, and the `)` on leaving.
+struct SExprWriter(String);
+
+impl<'e> Visitor<'e> for SExprWriter {
+    type Error = Infallible;
+
+    fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let start = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => name.clone(),
+            Expression::Op { op, .. } => format!("({op}"),
+            Expression::Compare { op, .. } => format!("({op}"),
+            Expression::Logic { op, .. } => format!("({op}"),
+            Expression::Unary { op, .. } => format!("({op}"),
+            Expression::Not(_) => String::from("(!"),
+            Expression::If { .. } => String::from("(if"),
+            Expression::Let { name, .. } => format!("(let {name}"),
+            Expression::Define { name, function, .. } => {
+                let params: String = function.params.iter().map(|p| format!(" {p}")).collect();
+                format!("(let ({name}{params})")
+            }
+            Expression::Call { name, .. } => format!("({name}"),
+        };
+        self.0.push_str(&start);
+        Ok(())
+    }
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        Ok(match e.child(visited) {
+            Some((step, child)) => {
+                self.0.push(' ');
+                Next::Child(step, child)
+            }
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        if !matches!(e, Expression::Value(_) | Expression::Var(_)) {
+            self.0.push(')');
+        }
+        Ok(())
+    }
+}
+
+/// Displays an expression in reverse Polish notation, like `1 2 3 * +`.
+///
+/// Bindings and calls name their variable or function last, after the argument count if there is
+/// one: `let x = 1 in x + 2` is `1 x 2 + x let`, `f(a, b)` is `a b 2 f call`, and
+/// `let f(x) = x in f(1)` is `x 1 1 f call x 1 f fn`.
+pub struct Rpn<'a>(pub &'a Expression);
+
+impl Display for Rpn<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut writer = RpnWriter(String::new());
+        let Ok(()) = visit::visit(self.0, &mut writer);
+        f.write_str(&writer.0)
+    }
+}
+
+/// Writes a space between the operands of each node, then the node itself on leaving it.
+struct RpnWriter(String);
+
+impl<'e> Visitor<'e> for RpnWriter {
+    type Error = Infallible;
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        Ok(match e.child(visited) {
+            Some((step, child)) => {
+                if visited > 0 {
+                    self.0.push(' ');
+                }
+                Next::Child(step, child)
+            }
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        if e.child(0).is_some() {
+            self.0.push(' ');
+        }
+        let end = match e {
+            Expression::Value(val) => val.to_string(),
+            Expression::Var(name) => name.clone(),
+            Expression::Op { op, .. } => op.to_string(),
+            Expression::Compare { op, .. } => op.to_string(),
+            Expression::Logic { op, .. } => op.to_string(),
+            Expression::Unary { op, .. } => op.to_string(),
+            Expression::Not(_) => String::from("!"),
+            Expression::If { .. } => String::from("if"),
+            Expression::Let { name, .. } => format!("{name} let"),
+            Expression::Define { name, function, .. } => {
+                let params: String = function.params.iter().map(|p| format!("{p} ")).collect();
+                format!("{params}{} {name} fn", function.params.len())
+            }
+            Expression::Call { name, args } => format!("{} {name} call", args.len()),
+        };
+        self.0.push_str(&end);
+        Ok(())
+    }
+}
+
+impl Expression {
+    /// Render as an S-expression.
+    pub fn sexpr(&self) -> SExpr<'_> {
+        SExpr(self)
+    }
+
+    /// Render in reverse Polish notation.
+    pub fn rpn(&self) -> Rpn<'_> {
+        Rpn(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day2::{
+        deep_sum, eval_with,
+        parse::{parse, parse_rpn, parse_sexpr},
+        property::check_expressions,
+        random::Generator,
+        Env, Expression, Operation,
+    };
+
+    const CASES: [(&str, &str, &str); 23] = [
+        ("42", "42", "42"),
+        ("-7", "-7", "-7"),
+        ("1 + 2 * 3", "(+ 1 (* 2 3))", "1 2 3 * +"),
+        ("(1 + 2) * 3", "(* (+ 1 2) 3)", "1 2 + 3 *"),
+        ("x * x + y", "(+ (* x x) y)", "x x * y +"),
+        ("8 - 4 - 2", "(- (- 8 4) 2)", "8 4 - 2 -"),
+        ("8 - (4 - 2)", "(- 8 (- 4 2))", "8 4 2 - -"),
+        (
+            "10 * 9 + (3 - -4) / (5 * 2)",
+            "(+ (* 10 9) (/ (- 3 -4) (* 5 2)))",
+            "10 9 * 3 -4 - 5 2 * / +",
+        ),
+        ("-x ^ 2", "(neg (^ x 2))", "x 2 ^ neg"),
+        ("-2 ^ 2", "(^ -2 2)", "-2 2 ^"),
+        ("(-x) ^ 2", "(^ (neg x) 2)", "x neg 2 ^"),
+        ("2 ^ 3 ^ 2", "(^ 2 (^ 3 2))", "2 3 2 ^ ^"),
+        ("(2 ^ 3) ^ 2", "(^ (^ 2 3) 2)", "2 3 ^ 2 ^"),
+        (
+            "min(x, -y) % abs(-7) * -(2 ^ x)",
+            "(* (% (min x (neg y)) (abs -7)) (neg (^ 2 x)))",
+            "x y neg min -7 abs % 2 x ^ neg *",
+        ),
+        (
+            "x < y + 1 && !(y == 0) || x >= 2",
+            "(|| (&& (< x (+ y 1)) (! (== y 0))) (>= x 2))",
+            "x y 1 + < y 0 == ! && x 2 >= ||",
+        ),
+        ("a && (b || c)", "(&& a (|| b c))", "a b c || &&"),
+        (
+            "if x != 0 then 10 / x else -1",
+            "(if (!= x 0) (/ 10 x) -1)",
+            "x 0 != 10 x / -1 if",
+        ),
+        (
+            "(if a then 1 else 2) + 3",
+            "(+ (if a 1 2) 3)",
+            "a 1 2 if 3 +",
+        ),
+        (
+            "if a then b else if c then 1 else 2",
+            "(if a b (if c 1 2))",
+            "a b c 1 2 if if",
+        ),
+        ("let x = 2 in x * x", "(let x 2 (* x x))", "2 x x * x let"),
+        ("(let x = 1 in x) + x", "(+ (let x 1 x) x)", "1 x x let x +"),
+        ("let k() = 7 in k()", "(let (k) 7 (k))", "7 0 k call 0 k fn"),
+        (
+            "let f(a, b) = a - b in f(1, g()) * 2",
+            "(let (f a b) (- a b) (* (f 1 (g)) 2))",
+            "a b - 1 0 g call 2 f call 2 * a b 2 f fn",
+        ),
+    ];
+
+    #[test]
+    fn renderings() {
+        for (infix, sexpr, rpn) in CASES {
+            let e = parse(infix).unwrap();
+            assert_eq!(e.to_string(), infix);
+            assert_eq!(e.sexpr().to_string(), sexpr);
+            assert_eq!(e.rpn().to_string(), rpn);
+        }
+    }
+
+    #[test]
+    fn minimal_parens() {
+        let e = parse("((1 + 2)) + (3 * (4))").unwrap();
+        assert_eq!(e.to_string(), "1 + 2 + 3 * 4");
+        let e = parse("1 + (2 + 3)").unwrap();
+        assert_eq!(e.to_string(), "1 + (2 + 3)");
+        let e = parse("(-x) * (-(y)) - (-(--z))").unwrap();
+        assert_eq!(e.to_string(), "-x * -y - ---z");
+        let e = parse("-(5 % 2) - -(5) + (-5)").unwrap();
+        assert_eq!(e.to_string(), "-(5 % 2) - -(5) + -5");
+        let e = parse("(!(x < y)) || ((a && b) && c) || (a == (b < c))").unwrap();
+        assert_eq!(e.to_string(), "!(x < y) || a && b && c || a == (b < c)");
+        let e = parse("if (if a then b else c) then (x + 1) else (y > 2)").unwrap();
+        assert_eq!(e.to_string(), "if if a then b else c then x + 1 else y > 2");
+        assert_eq!(parse(&e.to_string()), Ok(e));
+        let e = parse("f((1 + 2)) * (let y = (3) in (y)) - -(g(x))").unwrap();
+        assert_eq!(e.to_string(), "f(1 + 2) * (let y = 3 in y) - -g(x)");
+        assert_eq!(parse(&e.to_string()), Ok(e));
+    }
+
+    #[test]
+    fn round_trip() {
+        for (infix, sexpr, rpn) in CASES {
+            let e = parse(infix).unwrap();
+            assert_eq!(parse(&e.to_string()).unwrap(), e);
+            assert_eq!(parse_sexpr(&e.sexpr().to_string()).unwrap(), e);
+            assert_eq!(parse_sexpr(sexpr).unwrap(), e);
+            assert_eq!(parse_rpn(&e.rpn().to_string()).unwrap(), e);
+            assert_eq!(parse_rpn(rpn).unwrap(), e);
+        }
+    }
+
+    #[test]
+    fn random_round_trip() {
+        let mut generator = Generator::new();
+        generator.values(i64::MIN..=i64::MAX);
+        let property = |e: &Expression| {
+            let renderings = [
+                parse(&e.to_string()),
+                parse_sexpr(&e.sexpr().to_string()),
+                parse_rpn(&e.rpn().to_string()),
+            ];
+            match renderings
+                .into_iter()
+                .find(|parsed| parsed.as_ref() != Ok(e))
+            {
+                Some(parsed) => Err(format!("reads back as {parsed:?}")),
+                None => Ok(()),
+            }
+        };
+        if let Err(failure) = check_expressions(&generator, 1000, 0, property) {
+            panic!("{failure}");
+        }
+    }
+
+    #[test]
+    fn deep() {
+        for left_deep in [true, false] {
+            let e = deep_sum(100_000, left_deep);
+            let text = e.to_string();
+            assert_eq!(parse(&text).unwrap().to_string(), text);
+            let sexpr = e.sexpr().to_string();
+            assert_eq!(parse_sexpr(&sexpr).unwrap().sexpr().to_string(), sexpr);
+            let rpn = e.rpn().to_string();
+            assert_eq!(parse_rpn(&rpn).unwrap().rpn().to_string(), rpn);
+        }
+        let mut e = Expression::Op {
+            op: Operation::Div,
+            left: Box::new(Expression::Value(1)),
+            right: Box::new(Expression::Value(0)),
+        };
+        for _ in 0..100_000 {
+            e = Expression::Op {
+                op: Operation::Add,
+                left: Box::new(Expression::Value(1)),
+                right: Box::new(e),
+            };
+        }
+        let text = e.to_string();
+        let error = eval_with(&e, &Env::new()).unwrap_err();
+        assert_eq!(
+            error.report(&e),
+            format!(
+                "division by zero\n{text}\n{}^^^^^\n",
+                " ".repeat(text.find("1 / 0").unwrap())
+            )
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Checking properties against many random inputs, shrinking any counterexample to a small one.
+
+use super::{
+    random::{Generator, Rng},
+    Expression,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// An input a property failed for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure<T> {
+    /// The seed that generates `original`.
+    pub seed: u64,
+    /// The input as generated.
+    pub original: T,
+    /// The smallest input found by shrinking that still fails.
+    pub shrunk: T,
+    /// Why the property failed for `shrunk`.
+    pub message: String,
+}
+
+impl<T: Display> Display for Failure<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "property failed: {}", self.message)?;
+        writeln!(f, "  input: {}", self.shrunk)?;
+        write!(f, "  shrunk from: {} (seed {})", self.original, self.seed)
+    }
+}
+
+/// Check `property` against `cases` inputs made by `generate`, the first from `seed`.
+///
+/// The first failing input is shrunk by repeatedly moving to the first of its `shrink` candidates
+/// that still fails, until none does. Candidates should be strictly simpler than their input, so
+/// that this ends.
+pub fn check<T: Clone>(
+    cases: u64,
+    seed: u64,
+    mut generate: impl FnMut(&mut Rng) -> T,
+    shrink: impl Fn(&T) -> Vec<T>,
+    property: impl Fn(&T) -> Result<(), String>,
+) -> Result<(), Box<Failure<T>>> {
+    for seed in (0..cases).map(|case| seed.wrapping_add(case)) {
+        let original = generate(&mut Rng::new(seed));
+        let Err(mut message) = property(&original) else {
+            continue;
+        };
+        let mut shrunk = original.clone();
+        'shrink: loop {
+            for candidate in shrink(&shrunk) {
+                if let Err(candidate_message) = property(&candidate) {
+                    (shrunk, message) = (candidate, candidate_message);
+                    continue 'shrink;
+                }
+            }
+            break;
+        }
+        return Err(Box::new(Failure {
+            seed,
+            original,
+            shrunk,
+            message,
+        }));
+    }
+    Ok(())
+}
+
+/// [`check`] `property` against expressions from `generator`, shrinking with [`shrink`].
+pub fn check_expressions(
+    generator: &Generator,
+    cases: u64,
+    seed: u64,
+    property: impl Fn(&Expression) -> Result<(), String>,
+) -> Result<(), Box<Failure<Expression>>> {
+    check(cases, seed, |rng| generator.generate(rng), shrink, property)
+}
+
+/// Simpler variants of `e`: a literal instead of it, each of its subexpressions, and it with one
+/// subexpression replaced by a simpler variant of that. Literals shrink towards zero.
+///
+/// The variants need not be well-formed, a bound name may be left unbound for example.
+pub fn shrink(e: &Expression) -> Vec<Expression> {
+    let mut out = Vec::new();
+    match e {
+        Expression::Value(0) => {}
+        Expression::Value(val) => {
+            out.push(Expression::Value(0));
+            for smaller in [val / 2, val - val.signum()] {
+                if smaller != 0 && !out.contains(&Expression::Value(smaller)) {
+                    out.push(Expression::Value(smaller));
+                }
+            }
+        }
+        Expression::Var(_) => out.push(Expression::Value(0)),
+        _ => {
+            out.push(Expression::Value(0));
+            let parts: Vec<_> = e
+                .children()
+                .into_iter()
+                .map(|(_, child)| child.clone())
+                .collect();
+            out.extend(parts.iter().cloned());
+            for (i, part) in parts.iter().enumerate() {
+                for candidate in shrink(part) {
+                    let mut variant = e.clone();
+                    *variant.children_mut()[i] = candidate;
+                    out.push(variant);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::parse::parse;
+
+    fn has_division(e: &Expression) -> bool {
+        let text = e.to_string();
+        text.contains('/') || text.contains('%')
+    }
+
+    #[test]
+    fn passing() {
+        let generator = Generator::new();
+        let property = |e: &Expression| {
+            if e.to_string().is_empty() {
+                Err(String::from("empty"))
+            } else {
+                Ok(())
+            }
+        };
+        assert_eq!(check_expressions(&generator, 200, 0, property), Ok(()));
+    }
+
+    #[test]
+    fn shrinking() {
+        let mut generator = Generator::new();
+        generator.depth(6);
+        let property = |e: &Expression| {
+            if has_division(e) {
+                Err(format!("divides: {e}"))
+            } else {
+                Ok(())
+            }
+        };
+        let failure = check_expressions(&generator, 100, 0, property).unwrap_err();
+        assert!(
+            ["0 / 0", "0 % 0"].contains(&failure.shrunk.to_string().as_str()),
+            "{failure}"
+        );
+        assert_eq!(failure.message, format!("divides: {}", failure.shrunk));
+        assert_eq!(
+            generator.generate(&mut Rng::new(failure.seed)),
+            failure.original
+        );
+
+        let failure = check(
+            100,
+            5,
+            |rng| rng.range(0..=1000),
+            |&n| vec![n / 2, n - 1],
+            |&n| {
+                if n < 100 {
+                    Ok(())
+                } else {
+                    Err(format!("{n} is too big"))
+                }
+            },
+        )
+        .unwrap_err();
+        assert_eq!(failure.shrunk, 100);
+        assert_eq!(failure.message, "100 is too big");
+        assert_eq!(Rng::new(failure.seed).range(0..=1000), failure.original);
+    }
+
+    #[test]
+    fn candidates() {
+        let e = parse("-(x) + 3").unwrap();
+        let shrunk: Vec<_> = shrink(&e).iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            shrunk,
+            ["0", "-x", "3", "0 + 3", "x + 3", "-(0) + 3", "-x + 0", "-x + 1", "-x + 2"]
+        );
+        assert_eq!(
+            shrink(&Expression::Value(-7)),
+            [0, -3, -6].map(Expression::Value)
+        );
+        assert!(shrink(&Expression::Value(0)).is_empty());
+        let call = parse("let f(a) = a * 2 in f(1)").unwrap();
+        assert!(shrink(&call).contains(&parse("f(1)").unwrap()));
+        assert!(shrink(&call).contains(&parse("a * 2").unwrap()));
+    }
+
+    #[test]
+    fn report() {
+        let failure = Failure {
+            seed: 9,
+            original: parse("x / (1 - 1) + 2").unwrap(),
+            shrunk: parse("0 / 0").unwrap(),
+            message: String::from("division by zero"),
+        };
+        assert_eq!(
+            failure.to_string(),
+            "property failed: division by zero\n  input: 0 / 0\n  shrunk from: x / (1 - 1) + 2 (seed 9)"
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Seeded pseudo-random numbers and random [`Expression`]s, for property tests and benchmarks.
+
+use super::{Comparison, Connective, Expression, Function, Operation, UnaryOperation};
+use std::ops::RangeInclusive;
+
+/// A small, fast pseudo-random number generator (SplitMix64). The same seed always gives the
+/// same sequence, so anything generated from it can be reproduced.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `range`, which must not be empty.
+    pub fn range(&mut self, range: RangeInclusive<i64>) -> i64 {
+        let (low, high) = range.into_inner();
+        assert!(low <= high, "empty range");
+        // Scaling rather than taking a remainder keeps this free of bias for all but huge spans.
+        let span = (high as i128 - low as i128 + 1) as u128;
+        let offset = (u128::from(self.next_u64()) * span) >> 64;
+        (low as i128 + offset as i128) as i64
+    }
+
+    /// An index below `len`, which must not be zero.
+    pub fn below(&mut self, len: usize) -> usize {
+        self.range(0..=len as i64 - 1) as usize
+    }
+
+    /// True once in `n` times on average.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    /// A random element of `items`, if there are any.
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        match items.len() {
+            0 => None,
+            len => Some(&items[self.below(len)]),
+        }
+    }
+}
+
+/// Settings for generating random well-formed expressions: every operand has the type its
+/// operator expects, and every variable and function is bound where it is used.
+///
+/// Evaluating one can still fail, by dividing by zero or overflowing for example.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    depth: usize,
+    operations: Vec<Operation>,
+    unary_operations: Vec<UnaryOperation>,
+    values: RangeInclusive<i64>,
+    vars: Vec<String>,
+    booleans: bool,
+    bindings: bool,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            depth: 5,
+            operations: vec![
+                Operation::Add,
+                Operation::Sub,
+                Operation::Mul,
+                Operation::Div,
+                Operation::Rem,
+                Operation::Pow,
+                Operation::Min,
+                Operation::Max,
+            ],
+            unary_operations: vec![UnaryOperation::Neg, UnaryOperation::Abs],
+            values: -10..=10,
+            vars: vec![String::from("x"), String::from("y")],
+            booleans: true,
+            bindings: true,
+        }
+    }
+}
+
+/// The names visible at the point being generated.
+#[derive(Default)]
+struct Scope {
+    vars: Vec<String>,
+    /// Functions and how many parameters they take.
+    functions: Vec<(String, usize)>,
+    /// How many names have been made up, to keep new ones unique.
+    fresh: usize,
+}
+
+impl Scope {
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.fresh += 1;
+        format!("{prefix}{}", self.fresh)
+    }
+}
+
+impl Generator {
+    /// Expressions up to five levels deep, with every operator, literals from -10 to 10, the
+    /// variables `x` and `y`, booleans, `let`s and functions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Nest at most `depth` levels below the root.
+    pub fn depth(&mut self, depth: usize) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Use only these binary operations. With none, only the other kinds of node are generated.
+    pub fn operations(&mut self, operations: &[Operation]) -> &mut Self {
+        self.operations = operations.to_vec();
+        self
+    }
+
+    /// Use only these unary operations.
+    pub fn unary_operations(&mut self, operations: &[UnaryOperation]) -> &mut Self {
+        self.unary_operations = operations.to_vec();
+        self
+    }
+
+    /// Draw literals from `values`, which must not be empty.
+    pub fn values(&mut self, values: RangeInclusive<i64>) -> &mut Self {
+        assert!(!values.is_empty(), "empty range");
+        self.values = values;
+        self
+    }
+
+    /// Refer to these free variables, which evaluation will need bound.
+    pub fn vars(&mut self, vars: &[&str]) -> &mut Self {
+        self.vars = vars.iter().map(|&var| String::from(var)).collect();
+        self
+    }
+
+    /// Whether to generate comparisons, connectives and `if`s.
+    pub fn booleans(&mut self, booleans: bool) -> &mut Self {
+        self.booleans = booleans;
+        self
+    }
+
+    /// Whether to generate `let`s, function definitions and calls.
+    pub fn bindings(&mut self, bindings: bool) -> &mut Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// A random expression evaluating to a number.
+    pub fn generate(&self, rng: &mut Rng) -> Expression {
+        let mut scope = Scope {
+            vars: self.vars.clone(),
+            ..Scope::default()
+        };
+        self.number(rng, self.depth, &mut scope)
+    }
+
+    fn leaf(&self, rng: &mut Rng, scope: &Scope) -> Expression {
+        match rng.choose(&scope.vars) {
+            Some(var) if rng.one_in(2) => Expression::Var(var.clone()),
+            _ => Expression::Value(rng.range(self.values.clone())),
+        }
+    }
+
+    fn number(&self, rng: &mut Rng, depth: usize, scope: &mut Scope) -> Expression {
+        #[derive(Clone, Copy)]
+        enum Kind {
+            Op,
+            Unary,
+            If,
+            Let,
+            Define,
+            Call,
+        }
+        let mut kinds = Vec::new();
+        if !self.operations.is_empty() {
+            kinds.extend([Kind::Op; 4]);
+        }
+        if !self.unary_operations.is_empty() {
+            kinds.push(Kind::Unary);
+        }
+        // A condition needs a level for itself and one for its operands.
+        if self.booleans && depth >= 2 {
+            kinds.push(Kind::If);
+        }
+        if self.bindings {
+            kinds.extend([Kind::Let, Kind::Define]);
+            if !scope.functions.is_empty() {
+                kinds.extend([Kind::Call; 4]);
+            }
+        }
+        if depth == 0 || rng.one_in(4) {
+            return self.leaf(rng, scope);
+        }
+        let Some(&kind) = rng.choose(&kinds) else {
+            return self.leaf(rng, scope);
+        };
+        let depth = depth - 1;
+        match kind {
+            Kind::Op => Expression::Op {
+                op: *rng.choose(&self.operations).expect("no operations"),
+                left: Box::new(self.number(rng, depth, scope)),
+                right: Box::new(self.number(rng, depth, scope)),
+            },
+            Kind::Unary => Expression::Unary {
+                op: *rng.choose(&self.unary_operations).expect("no operations"),
+                operand: Box::new(self.number(rng, depth, scope)),
+            },
+            Kind::If => Expression::If {
+                cond: Box::new(self.boolean(rng, depth, scope)),
+                then: Box::new(self.number(rng, depth, scope)),
+                otherwise: Box::new(self.number(rng, depth, scope)),
+            },
+            Kind::Let => {
+                let name = scope.fresh("v");
+                let value = self.number(rng, depth, scope);
+                scope.vars.push(name.clone());
+                let body = self.number(rng, depth, scope);
+                scope.vars.pop();
+                Expression::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                }
+            }
+            Kind::Define => {
+                let name = scope.fresh("f");
+                let params: Vec<_> = (0..rng.below(3)).map(|_| scope.fresh("p")).collect();
+                // The body sees the enclosing bindings too. It does not call itself, so that
+                // every call ends.
+                let outer = scope.vars.len();
+                scope.vars.extend(params.iter().cloned());
+                let function = self.number(rng, depth, scope);
+                scope.vars.truncate(outer);
+                scope.functions.push((name.clone(), params.len()));
+                let body = self.number(rng, depth, scope);
+                scope.functions.pop();
+                Expression::Define {
+                    name,
+                    function: Box::new(Function {
+                        params,
+                        body: function,
+                    }),
+                    body: Box::new(body),
+                }
+            }
+            Kind::Call => {
+                let (name, arity) = rng.choose(&scope.functions).expect("no functions").clone();
+                Expression::Call {
+                    name,
+                    args: (0..arity).map(|_| self.number(rng, depth, scope)).collect(),
+                }
+            }
+        }
+    }
+
+    /// A random expression evaluating to a boolean, with room for at least one level below it.
+    fn boolean(&self, rng: &mut Rng, depth: usize, scope: &mut Scope) -> Expression {
+        const COMPARISONS: [Comparison; 6] = [
+            Comparison::Eq,
+            Comparison::Ne,
+            Comparison::Lt,
+            Comparison::Le,
+            Comparison::Gt,
+            Comparison::Ge,
+        ];
+        let depth = depth - 1;
+        match rng.below(5) {
+            0 if depth > 0 => Expression::Logic {
+                op: *rng
+                    .choose(&[Connective::And, Connective::Or])
+                    .expect("no connectives"),
+                left: Box::new(self.boolean(rng, depth, scope)),
+                right: Box::new(self.boolean(rng, depth, scope)),
+            },
+            1 if depth > 0 => Expression::Not(Box::new(self.boolean(rng, depth, scope))),
+            _ => Expression::Compare {
+                op: *rng.choose(&COMPARISONS).expect("no comparisons"),
+                left: Box::new(self.number(rng, depth, scope)),
+                right: Box::new(self.number(rng, depth, scope)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{eval_with, Env, EvalErrorKind};
+
+    #[test]
+    fn rng() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        let numbers: Vec<_> = (0..5).map(|_| a.next_u64()).collect();
+        assert_eq!(numbers, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(
+            numbers,
+            (0..5).map(|_| Rng::new(8).next_u64()).collect::<Vec<_>>()
+        );
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let n = a.range(-3..=3);
+            seen[(n + 3) as usize] = true;
+        }
+        assert_eq!(seen, [true; 7]);
+        a.range(i64::MIN..=i64::MAX);
+        assert_eq!(a.range(5..=5), 5);
+    }
+
+    #[test]
+    fn well_formed() {
+        let mut rng = Rng::new(1);
+        let generator = Generator::new();
+        let mut env = Env::new();
+        env.bind("x", 3).bind("y", -4);
+        let mut lengths = Vec::new();
+        for _ in 0..500 {
+            let e = generator.generate(&mut rng);
+            lengths.push(e.to_string().len());
+            if let Err(err) = eval_with(&e, &env) {
+                assert!(
+                    !matches!(
+                        err.kind,
+                        EvalErrorKind::UnboundVariable(_)
+                            | EvalErrorKind::TypeMismatch { .. }
+                            | EvalErrorKind::UnknownFunction(_)
+                            | EvalErrorKind::ArgumentCount { .. }
+                            | EvalErrorKind::CallDepth(_)
+                    ),
+                    "{e}: {err}"
+                );
+            }
+        }
+        assert!(lengths.iter().any(|&len| len > 40));
+    }
+
+    #[test]
+    fn settings() {
+        let mut rng = Rng::new(2);
+        let mut generator = Generator::new();
+        generator
+            .depth(3)
+            .operations(&[Operation::Add])
+            .unary_operations(&[])
+            .values(1..=2)
+            .vars(&[])
+            .booleans(false)
+            .bindings(false);
+        for _ in 0..100 {
+            let e = generator.generate(&mut rng);
+            let text = e.to_string();
+            assert!(
+                text.chars()
+                    .all(|c| matches!(c, '1' | '2' | ' ' | '+' | '(' | ')')),
+                "{text}"
+            );
+            assert!(text.matches('+').count() < 8, "{text}");
+        }
+        generator.depth(0);
+        assert!(matches!(
+            generator.generate(&mut rng),
+            Expression::Value(1 | 2)
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+//! Exact fractions, so that evaluating `7 / 2` gives `7/2` rather than `3`.
+
+use super::{power, EvalErrorKind, Operation};
+use std::{cmp::Ordering, fmt};
+
+/// A fraction in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numer: i64,
+    denom: i64,
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rational {
+    /// The fraction `numer / denom`, normalized.
+    pub fn new(numer: i64, denom: i64) -> Result<Self, EvalErrorKind> {
+        Self::normalize(numer.into(), denom.into())
+    }
+
+    /// Reduce to lowest terms, failing if the result does not fit in `i64`s.
+    fn normalize(numer: i128, denom: i128) -> Result<Self, EvalErrorKind> {
+        if denom == 0 {
+            return Err(EvalErrorKind::DivisionByZero);
+        }
+        let divisor = gcd(numer.unsigned_abs(), denom.unsigned_abs()) as i128;
+        let sign = denom.signum();
+        let fit = |n: i128| {
+            (n / divisor)
+                .checked_mul(sign)
+                .and_then(|n| i64::try_from(n).ok())
+                .ok_or(EvalErrorKind::Overflow)
+        };
+        Ok(Rational {
+            numer: fit(numer)?,
+            denom: fit(denom)?,
+        })
+    }
+
+    pub fn numer(&self) -> i64 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> i64 {
+        self.denom
+    }
+
+    /// Apply `op` exactly, failing on division by zero or if the result does not fit.
+    ///
+    /// The remainder is what is left after subtracting a whole multiple of `other`, so
+    /// `7/2 % 1` is `1/2`.
+    pub fn apply(self, op: Operation, other: Rational) -> Result<Rational, EvalErrorKind> {
+        let (a, b) = (i128::from(self.numer), i128::from(self.denom));
+        let (c, d) = (i128::from(other.numer), i128::from(other.denom));
+        // Products of two `i64`s always fit in an `i128`, only the sums can overflow.
+        let (numer, denom) = match op {
+            Operation::Add => ((a * d).checked_add(c * b), b * d),
+            Operation::Sub => ((a * d).checked_sub(c * b), b * d),
+            Operation::Mul => (Some(a * c), b * d),
+            Operation::Div => (Some(a * d), b * c),
+            Operation::Rem if c == 0 => return Err(EvalErrorKind::RemainderByZero),
+            Operation::Rem => (Some((a * d) % (b * c)), b * d),
+            Operation::Pow => return self.pow(other),
+            Operation::Min => return Ok(self.min(other)),
+            Operation::Max => return Ok(self.max(other)),
+        };
+        Self::normalize(numer.ok_or(EvalErrorKind::Overflow)?, denom)
+    }
+
+    /// `self` raised to a whole number power, which may be negative.
+    pub fn pow(self, exp: Rational) -> Result<Rational, EvalErrorKind> {
+        if exp.denom != 1 {
+            return Err(EvalErrorKind::NonIntegerExponent);
+        }
+        let one = Rational::from(1);
+        let base = if exp.numer < 0 {
+            one.apply(Operation::Div, self)?
+        } else {
+            self
+        };
+        power(base, exp.numer.unsigned_abs(), one, |a, b| {
+            a.apply(Operation::Mul, b)
+        })
+    }
+
+    /// Decimal digits with `precision` places after the point, rounding half away from zero.
+    pub fn to_decimal(&self, precision: usize) -> String {
+        let denom = u128::from(self.denom.unsigned_abs());
+        let mut whole = u128::from(self.numer.unsigned_abs()) / denom;
+        let mut rem = u128::from(self.numer.unsigned_abs()) % denom;
+        let mut digits = Vec::with_capacity(precision);
+        for _ in 0..precision {
+            rem *= 10;
+            digits.push((rem / denom) as u8);
+            rem %= denom;
+        }
+        if rem >= denom - rem {
+            // Round up, carrying through trailing nines into the whole part.
+            let carry = digits.iter_mut().rev().all(|digit| {
+                *digit = (*digit + 1) % 10;
+                *digit == 0
+            });
+            if carry {
+                whole += 1;
+            }
+        }
+        let negative = self.numer < 0 && (whole != 0 || digits.iter().any(|&d| d != 0));
+        let mut out = format!("{}{whole}", if negative { "-" } else { "" });
+        if precision > 0 {
+            out.push('.');
+            out.extend(digits.iter().map(|&d| char::from(b'0' + d)));
+        }
+        out
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(val: i64) -> Self {
+        Rational {
+            numer: val,
+            denom: 1,
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Denominators are positive, so cross multiplying keeps the order.
+        (i128::from(self.numer) * i128::from(other.denom))
+            .cmp(&(i128::from(other.numer) * i128::from(self.denom)))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denom == 1 {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{number::eval_number, Env, Value};
+
+    fn exact(text: &str) -> Result<Rational, EvalErrorKind> {
+        eval_number(&text.parse().unwrap(), &Env::new())
+            .map_err(|e| e.kind)
+            .and_then(Value::number)
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(Rational::new(6, -4), Ok(Rational::new(-3, 2).unwrap()));
+        assert_eq!(Rational::new(0, -5).unwrap().to_string(), "0");
+        assert_eq!(Rational::new(-10, -5).unwrap().to_string(), "2");
+        assert_eq!(Rational::new(1, 0), Err(EvalErrorKind::DivisionByZero));
+        assert_eq!(Rational::new(i64::MIN, -1), Err(EvalErrorKind::Overflow));
+        assert_eq!(Rational::new(i64::MIN, i64::MIN).unwrap().to_string(), "1");
+    }
+
+    #[test]
+    fn exact_eval() {
+        assert_eq!(exact("7 / 2").unwrap().to_string(), "7/2");
+        assert_eq!(exact("7 / 2 * 2"), Ok(Rational::from(7)));
+        assert_eq!(exact("1 / 3 + 1 / 6").unwrap().to_string(), "1/2");
+        assert_eq!(exact("1 / (1 - 3) - 1").unwrap().to_string(), "-3/2");
+        assert_eq!(exact("1 / (2 - 2)"), Err(EvalErrorKind::DivisionByZero));
+        assert_eq!(
+            exact("9223372036854775807 / 2 + 9223372036854775807 / 2"),
+            Ok(Rational::from(i64::MAX))
+        );
+        assert_eq!(
+            exact("9223372036854775807 + 1 / 2"),
+            Err(EvalErrorKind::Overflow)
+        );
+
+        let e = "x / y".parse().unwrap();
+        let mut env = Env::new();
+        env.bind("x", Rational::from(10))
+            .bind("y", Rational::new(1, 4).unwrap());
+        assert_eq!(eval_number(&e, &env).unwrap().to_string(), "40");
+    }
+
+    #[test]
+    fn decimals() {
+        let r = |n, d| Rational::new(n, d).unwrap();
+        assert_eq!(r(7, 2).to_decimal(3), "3.500");
+        assert_eq!(r(2, 3).to_decimal(5), "0.66667");
+        assert_eq!(r(-1, 3).to_decimal(4), "-0.3333");
+        assert_eq!(r(-7, 2).to_decimal(0), "-4");
+        assert_eq!(r(999, 1000).to_decimal(2), "1.00");
+        assert_eq!(r(-1, 1000).to_decimal(2), "0.00");
+        assert_eq!(r(1, 7).to_decimal(12), "0.142857142857");
+        assert_eq!(r(i64::MAX, i64::MAX - 1).to_decimal(1), "1.0");
+    }
+}
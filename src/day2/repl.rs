@@ -0,0 +1,238 @@
+//! A line-based calculator on top of [`eval_with`].
+//!
+//! Each numeric result is remembered as `$1`, `$2`, ... and `let name = expression` binds a
+//! variable for the following lines. Variables only hold numbers, so boolean results are shown but
+//! not remembered. `let f(x, y) = expression` defines a function, which sees the variables as they
+//! are when it is called.
+
+use super::{eval_with, parse::parse, Env, EvalErrorKind, Expression, Function, Type, Value};
+use std::io::{self, BufRead, Write};
+
+/// Calculator state carried from one line to the next.
+#[derive(Debug, Default)]
+pub struct Repl {
+    env: Env<'static>,
+    results: usize,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle one line of input, returning the text to show for it.
+    pub fn line(&mut self, input: &str) -> String {
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            return String::new();
+        }
+        match trimmed.strip_prefix("let ") {
+            // With `in` it is an expression, without it binds for the following lines.
+            Some(rest) if parse(trimmed).is_err() => self.assign(rest),
+            _ => match self.evaluate(input) {
+                Ok(Value::Bool(val)) => format!("{val}\n"),
+                Ok(Value::Num(val)) => {
+                    self.results += 1;
+                    let name = format!("${}", self.results);
+                    self.env.bind(name.clone(), val);
+                    format!("{name} = {val}\n")
+                }
+                Err(message) => message,
+            },
+        }
+    }
+
+    /// Handle `let name = expression` or `let name(params) = expression`, where `rest` is the
+    /// part after `let`.
+    fn assign(&mut self, rest: &str) -> String {
+        let Some((name, value)) = rest.split_once('=') else {
+            return String::from("error: expected `let <name> = <expression>`\n");
+        };
+        let name = name.trim();
+        if name.starts_with('$') {
+            return format!("error: `{name}` is not a valid variable name\n");
+        }
+        match &parse(name) {
+            Ok(Expression::Var(_)) => {}
+            Ok(Expression::Call {
+                name: function,
+                args,
+            }) => return self.define(name, function, args, value.trim()),
+            _ => return format!("error: `{name}` is not a valid variable name\n"),
+        }
+        match self.evaluate(value.trim()) {
+            Ok(Value::Bool(_)) => {
+                let kind = EvalErrorKind::TypeMismatch {
+                    expected: Type::Number,
+                    found: Type::Boolean,
+                };
+                format!("error: {kind}\n")
+            }
+            Ok(Value::Num(val)) => {
+                self.env.bind(name, val);
+                format!("{name} = {val}\n")
+            }
+            Err(message) => message,
+        }
+    }
+
+    /// Handle `let header = body`, where `header` parsed as a call of `name` with `args`.
+    fn define(&mut self, header: &str, name: &str, args: &[Expression], body: &str) -> String {
+        let mut params: Vec<String> = Vec::new();
+        for arg in args {
+            match arg {
+                Expression::Var(param) if !params.contains(param) => params.push(param.clone()),
+                _ => return format!("error: `{header}` is not a valid function header\n"),
+            }
+        }
+        match read(body) {
+            Ok(body) => {
+                let shown = format!("{name}({}) = {body}\n", params.join(", "));
+                self.env.define(name, Function { params, body });
+                shown
+            }
+            Err(message) => message,
+        }
+    }
+
+    /// Parse and evaluate `input`, or describe what went wrong.
+    fn evaluate(&self, input: &str) -> Result<Value, String> {
+        let e = read(input)?;
+        eval_with(&e, &self.env).map_err(|err| format!("error: {}", err.report(&e)))
+    }
+}
+
+/// Parse `input`, or describe what went wrong.
+fn read(input: &str) -> Result<Expression, String> {
+    parse(input).map_err(|err| {
+        let width = err.span.len().max(1);
+        format!(
+            "error: {}\n{input}\n{}{}\n",
+            err.kind,
+            " ".repeat(err.span.start),
+            "^".repeat(width)
+        )
+    })
+}
+
+/// Run the calculator, reading lines from `input` until it ends or says `quit`.
+pub fn run(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut repl = Repl::new();
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        if matches!(line.trim(), "quit" | "exit") {
+            return Ok(());
+        }
+        write!(output, "{}> ", repl.line(&line))?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn results_and_assignment() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.line("10 * 9 + (3 - 4) * 5"), "$1 = 85\n");
+        assert_eq!(repl.line("  "), "");
+        assert_eq!(repl.line("let x = $1 - 80"), "x = 5\n");
+        assert_eq!(repl.line("x * x + $1"), "$2 = 110\n");
+        assert_eq!(repl.line("$2 / x"), "$3 = 22\n");
+        assert_eq!(repl.line("x < $3 && !(x == 5)"), "false\n");
+        assert_eq!(repl.line("if x >= 5 then $3 else 0"), "$4 = 22\n");
+    }
+
+    #[test]
+    fn functions() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.line("let sq(x) = (x * x)"), "sq(x) = x * x\n");
+        assert_eq!(repl.line("sq(3) + 1"), "$1 = 10\n");
+        assert_eq!(repl.line("let x = 5"), "x = 5\n");
+        assert_eq!(
+            repl.line("let hyp(a, b) = sq(a) + sq(b) + x"),
+            "hyp(a, b) = sq(a) + sq(b) + x\n"
+        );
+        assert_eq!(repl.line("hyp(3, 4)"), "$2 = 30\n");
+        assert_eq!(repl.line("let y = 2 in y * sq(y)"), "$3 = 8\n");
+        assert_eq!(
+            repl.line("let f(a, a) = 1"),
+            "error: `f(a, a)` is not a valid function header\n"
+        );
+        assert_eq!(
+            repl.line("let f(1) = 1"),
+            "error: `f(1)` is not a valid function header\n"
+        );
+        assert_eq!(
+            repl.line("let f(a) = (a"),
+            "error: unclosed parenthesis\n(a\n^\n"
+        );
+        assert_eq!(
+            repl.line("1 + sq(1 < 2)"),
+            "error: type mismatch: expected number, found boolean\n1 + sq(1 < 2)\n    ^^^^^^^^^\n"
+        );
+        assert_eq!(
+            repl.line("sq(1, 2)"),
+            "error: `sq` takes 1 arguments but 2 were given\nsq(1, 2)\n^^^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            repl.line("1 + * 2"),
+            "error: expected an operand\n1 + * 2\n    ^\n"
+        );
+        assert_eq!(
+            repl.line("7 / ((y) - 1)"),
+            "error: unbound variable `y`\n7 / (y - 1)\n     ^\n"
+        );
+        assert_eq!(
+            repl.line("let y = 3 / 0"),
+            "error: division by zero\n3 / 0\n^^^^^\n"
+        );
+        assert_eq!(
+            repl.line("let y = (1"),
+            "error: unclosed parenthesis\n(1\n^\n"
+        );
+        assert_eq!(
+            repl.line("let 2 = 3"),
+            "error: `2` is not a valid variable name\n"
+        );
+        assert_eq!(
+            repl.line("let $1 = 3"),
+            "error: `$1` is not a valid variable name\n"
+        );
+        assert_eq!(
+            repl.line("let y"),
+            "error: expected `let <name> = <expression>`\n"
+        );
+        assert_eq!(
+            repl.line("let b = 1 < 2"),
+            "error: type mismatch: expected number, found boolean\n"
+        );
+        assert_eq!(
+            repl.line("1 + (2 < 3)"),
+            "error: type mismatch: expected number, found boolean\n1 + (2 < 3)\n^^^^^^^^^^^\n"
+        );
+        // Failed lines and booleans do not use up a result number.
+        assert_eq!(repl.line("1 != 2"), "true\n");
+        assert_eq!(repl.line("1"), "$1 = 1\n");
+    }
+
+    #[test]
+    fn session() {
+        let input = "1 + 2\nlet a = $1 * 2\n\na / 0\nquit\n4\n";
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> $1 = 3\n> a = 6\n> > error: division by zero\na / 0\n^^^^^\n> "
+        );
+    }
+}
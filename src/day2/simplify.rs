@@ -0,0 +1,386 @@
+//! Constant folding and algebraic simplification of an [`Expression`].
+//!
+//! Only rewrites that keep [`eval`](super::eval) results unchanged are applied: a subtree that can
+//! fail (dividing by zero, overflowing, or reading an unbound variable) is never dropped or folded.
+
+use super::{
+    eval_with,
+    number::eval_number,
+    rational::Rational,
+    visit::{self, Next, Visitor},
+    Arithmetic, Env, Expression, Function, Operation, UnaryOperation,
+};
+use std::convert::Infallible;
+
+/// Return a simplified copy of `e` that evaluates to the same result or error kind.
+///
+/// Traverses `e` with [`visit::visit`], so arbitrarily deep trees are fine.
+pub fn simplify(e: &Expression) -> Expression {
+    simplify_with(e, false)
+}
+
+/// Like [`simplify`], but also keeping the results the same when evaluated with fractions, as
+/// with [`eval_number`](super::number::eval_number) in `f64` or
+/// [`Rational`](super::rational::Rational): a division is only folded if it leaves no remainder.
+pub fn simplify_real(e: &Expression) -> Expression {
+    simplify_with(e, true)
+}
+
+fn simplify_with(e: &Expression, real: bool) -> Expression {
+    let mut simplifier = Simplifier {
+        real,
+        bound: Vec::new(),
+        done: Vec::new(),
+    };
+    let Ok(()) = visit::visit(e, &mut simplifier);
+    simplifier.done.pop().expect("missing result")
+}
+
+/// The [`Visitor`] behind [`simplify`], which pushes the simplified copy of each expression it
+/// is done with.
+struct Simplifier<'e> {
+    /// Whether to leave alone divisions that truncate.
+    real: bool,
+    /// The names bound by an enclosing `let` or function.
+    bound: Vec<&'e str>,
+    done: Vec<Expression>,
+}
+
+impl Simplifier<'_> {
+    fn pop(&mut self) -> Expression {
+        self.done.pop().expect("missing operand")
+    }
+
+    fn pop_box(&mut self) -> Box<Expression> {
+        Box::new(self.pop())
+    }
+}
+
+impl<'e> Visitor<'e> for Simplifier<'e> {
+    type Error = Infallible;
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        match (e, visited) {
+            // The value of a `let` does not see its name, but the body does.
+            (Expression::Let { name, .. }, 1) => self.bound.push(name),
+            // The parameters are only bound in the body of the function.
+            (Expression::Define { function, .. }, 0) => {
+                self.bound
+                    .extend(function.params.iter().map(String::as_str));
+            }
+            (Expression::Define { function, .. }, 1) => {
+                let len = self.bound.len() - function.params.len();
+                self.bound.truncate(len);
+            }
+            _ => {}
+        }
+        Ok(match e.child(visited) {
+            Some((step, child)) => Next::Child(step, child),
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        let simplified = match e {
+            Expression::Value(val) => Expression::Value(*val),
+            Expression::Var(name) => Expression::Var(name.clone()),
+            Expression::Unary { op, .. } => match self.pop() {
+                Expression::Value(val) => match Arithmetic::Checked.apply_unary(*op, val) {
+                    Ok(val) => Expression::Value(val),
+                    Err(_) => unary(*op, Expression::Value(val)),
+                },
+                operand => unary(*op, operand),
+            },
+            Expression::Op { op, .. } => {
+                let right = self.pop();
+                let left = self.pop();
+                match (op, left, right) {
+                    (Operation::Div, Expression::Value(l), Expression::Value(r))
+                        if self.real && l.checked_rem(r) != Some(0) =>
+                    {
+                        binary(Operation::Div, Expression::Value(l), Expression::Value(r))
+                    }
+                    (op, Expression::Value(l), Expression::Value(r)) => {
+                        // Results agree in every arithmetic mode when the checked one succeeds.
+                        match Arithmetic::Checked.apply(*op, l, r) {
+                            Ok(val) => Expression::Value(val),
+                            Err(_) => binary(*op, Expression::Value(l), Expression::Value(r)),
+                        }
+                    }
+                    // A boolean operand must keep failing with a type mismatch.
+                    (Operation::Add, Expression::Value(0), e)
+                    | (Operation::Add | Operation::Sub, e, Expression::Value(0))
+                    | (Operation::Mul, Expression::Value(1), e)
+                    | (Operation::Mul | Operation::Div | Operation::Pow, e, Expression::Value(1))
+                        if is_number(&e, &self.bound) =>
+                    {
+                        e
+                    }
+                    (op, left, right) => binary(*op, left, right),
+                }
+            }
+            Expression::Compare { op, .. } => {
+                let right = self.pop_box();
+                Expression::Compare {
+                    op: *op,
+                    left: self.pop_box(),
+                    right,
+                }
+            }
+            Expression::Logic { op, .. } => {
+                let right = self.pop_box();
+                Expression::Logic {
+                    op: *op,
+                    left: self.pop_box(),
+                    right,
+                }
+            }
+            Expression::Not(_) => Expression::Not(self.pop_box()),
+            Expression::If { .. } => {
+                let otherwise = self.pop();
+                let then = self.pop();
+                let cond = self.pop();
+                // There are no boolean literals, so a constant condition is resolved by picking
+                // the branch it would take; the other one is never evaluated anyway. Fractions
+                // can take a different branch than truncating division.
+                let decided = if self.real {
+                    eval_number::<Rational>(&cond, &Env::new()).map(|val| val.boolean())
+                } else {
+                    eval_with(&cond, &Env::new()).map(|val| val.boolean())
+                };
+                match decided {
+                    Ok(Ok(true)) => then,
+                    Ok(Ok(false)) => otherwise,
+                    _ => Expression::If {
+                        cond: Box::new(cond),
+                        then: Box::new(then),
+                        otherwise: Box::new(otherwise),
+                    },
+                }
+            }
+            Expression::Let { name, .. } => {
+                self.bound.pop();
+                let body = self.pop_box();
+                Expression::Let {
+                    name: name.clone(),
+                    value: self.pop_box(),
+                    body,
+                }
+            }
+            Expression::Define { name, function, .. } => {
+                let body = self.pop_box();
+                Expression::Define {
+                    name: name.clone(),
+                    function: Box::new(Function {
+                        params: function.params.clone(),
+                        body: self.pop(),
+                    }),
+                    body,
+                }
+            }
+            Expression::Call { name, args } => Expression::Call {
+                name: name.clone(),
+                args: self.done.split_off(self.done.len() - args.len()),
+            },
+        };
+        self.done.push(simplified);
+        Ok(())
+    }
+}
+
+/// Whether `e` can only evaluate to a number, if it succeeds at all.
+///
+/// Variables from the [`Env`] are numbers, but those in `bound` may hold booleans, and so may
+/// function results.
+fn is_number(e: &Expression, bound: &[&str]) -> bool {
+    let mut pending = vec![e];
+    while let Some(e) = pending.pop() {
+        match e {
+            Expression::Value(_) | Expression::Op { .. } | Expression::Unary { .. } => {}
+            Expression::Var(name) if !bound.contains(&name.as_str()) => {}
+            Expression::If {
+                then, otherwise, ..
+            } => pending.extend([&**then, &**otherwise]),
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn unary(op: UnaryOperation, operand: Expression) -> Expression {
+    Expression::Unary {
+        op,
+        operand: Box::new(operand),
+    }
+}
+
+fn binary(op: Operation, left: Expression, right: Expression) -> Expression {
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{deep_sum, property::check_expressions, random::Generator, EvalError, Value};
+
+    fn simplified(text: &str) -> String {
+        simplify(&text.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(simplified("3 - 4"), "-1");
+        assert_eq!(simplified("10 * 9 + (3 - 4) * 5"), "85");
+        assert_eq!(simplified("x * (2 + 3)"), "x * 5");
+        assert_eq!(simplified("(1 + 2) * x + 4 / 2"), "3 * x + 2");
+        assert_eq!(simplified("2 ^ 10 - abs(-3) % max(2, -(1))"), "1023");
+        assert_eq!(simplified("-(2 * 3) * -x"), "-6 * -x");
+        assert_eq!(
+            simplified("x < 2 + 3 && !(y == 0 * 1)"),
+            "x < 5 && !(y == 0)"
+        );
+        assert_eq!(simplified("if 2 - 1 < 3 then x * (2 + 3) else 0"), "x * 5");
+        let real = |text: &str| simplify_real(&text.parse().unwrap()).to_string();
+        assert_eq!(real("x * (7 / 2) + 6 / 3 + -7 % 2"), "x * (7 / 2) + 2 + -1");
+        assert_eq!(real("(1 / 2) ^ 2 + 1 / 0"), "(1 / 2) ^ 2 + 1 / 0");
+        assert_eq!(real("if 1 / 2 > 0 then x else 2 * x"), "x");
+        assert_eq!(simplified("if 1 / 2 > 0 then x else 2 * x"), "2 * x");
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(simplified("x * 1"), "x");
+        assert_eq!(simplified("1 * x"), "x");
+        assert_eq!(simplified("0 + x"), "x");
+        assert_eq!(simplified("x - 0"), "x");
+        assert_eq!(simplified("x / (3 - 2)"), "x");
+        assert_eq!(simplified("x ^ (2 - 1)"), "x");
+        assert_eq!(simplified("(x + (5 - 5)) * (y - 0) / 1"), "x * y");
+        // Not identities: `0 - x` is negation, and `x * 0` still fails if `x` is unbound.
+        assert_eq!(simplified("0 - x"), "0 - x");
+        assert_eq!(simplified("x * 0"), "x * 0");
+        // Local names may hold booleans, so they are left alone.
+        assert_eq!(
+            simplified("let y = 2 - 1 in x * 1 + y"),
+            "let y = 1 in x + y"
+        );
+        assert_eq!(
+            simplified("let b = 1 < 2 in let f(a) = a * 1 + (b - 0) in f(2 + 3)"),
+            "let b = 1 < 2 in let f(a) = a * 1 + (b - 0) in f(5)"
+        );
+        assert_eq!(
+            simplified("(let x = 1 in x) * x * 1"),
+            "(let x = 1 in x) * x"
+        );
+    }
+
+    #[test]
+    fn preserves_errors() {
+        assert_eq!(simplified("1 / 0"), "1 / 0");
+        assert_eq!(simplified("(2 - 2) * (7 / (1 - 1))"), "0 * (7 / 0)");
+        assert_eq!(
+            simplified("9223372036854775807 + 1"),
+            "9223372036854775807 + 1"
+        );
+        assert_eq!(
+            simplified("-9223372036854775808 / -1 * 1"),
+            "-9223372036854775808 / -1"
+        );
+        assert_eq!(simplified("2 ^ (0 - 1)"), "2 ^ -1");
+        assert_eq!(simplified("x % (1 - 1)"), "x % 0");
+        assert_eq!(
+            simplified("abs(-9223372036854775808)"),
+            "abs(-9223372036854775808)"
+        );
+        assert_eq!(simplified("(1 < 2) + 0"), "(1 < 2) + 0");
+        assert_eq!(simplified("if 1 == 2 then 1 else x > 0"), "x > 0");
+        assert_eq!(simplified("if 1 then x else 0"), "if 1 then x else 0");
+    }
+
+    #[test]
+    fn same_eval() {
+        let mut env = Env::new();
+        env.bind("x", 12).bind("y", -5);
+        for text in [
+            "x * 1 + 0 * y + (2 - 2) + y / 1",
+            "x / (y + 5) * 1",
+            "(x - 0) * (1 + 2 * 3) - unbound * 0",
+            "(x + 1) * (y - 1) / (1 * 2)",
+            "-(x % 5) ^ (3 - 2) + min(y, abs(-2 * 4))",
+            "(x < y) * 1",
+            "if 2 > 1 then x + 0 else 1 / 0",
+            "if x > y || unbound then (y < 0) - 0 else 0",
+            "let b = x < y in if b then 1 * 2 else b - 0",
+            "let f(n) = if n <= 1 then 1 * 1 else n * f(n - 1) in f(2 + 3) + 0",
+            "let f(p) = p * 1 in f(x > 0)",
+        ] {
+            let e = text.parse().unwrap();
+            let kind = |r: Result<Value, EvalError>| r.map_err(|e| e.kind);
+            assert_eq!(
+                kind(eval_with(&simplify(&e), &env)),
+                kind(eval_with(&e, &env)),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn random_same_eval() {
+        let mut env = Env::new();
+        env.bind("x", 3).bind("y", -7);
+        let kind = |r: Result<Value, EvalError>| r.map_err(|e| e.kind);
+        let property = |e: &Expression| {
+            let simplified = simplify(e);
+            let (before, after) = (kind(eval_with(e, &env)), kind(eval_with(&simplified, &env)));
+            if before == after {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{simplified} gives {after:?} instead of {before:?}"
+                ))
+            }
+        };
+        if let Err(failure) = check_expressions(&Generator::new(), 1000, 0, property) {
+            panic!("{failure}");
+        }
+    }
+
+    #[test]
+    fn deep() {
+        for left_deep in [true, false] {
+            assert_eq!(
+                simplify(&deep_sum(200_000, left_deep)),
+                Expression::Value(200_001)
+            );
+        }
+        let mut e = Expression::Var(String::from("x"));
+        for _ in 0..200_000 {
+            e = binary(
+                Operation::Mul,
+                binary(Operation::Add, e, Expression::Value(0)),
+                Expression::Value(1),
+            );
+        }
+        let simplified = simplify(&e);
+        assert_eq!(simplified, Expression::Var(String::from("x")));
+        // Nothing folds here, so the result is as deep, and is checked by evaluating it.
+        let mut e = Expression::Var(String::from("x"));
+        for _ in 0..200_000 {
+            e = binary(Operation::Sub, e, Expression::Value(1));
+        }
+        let mut env = Env::new();
+        env.bind("x", 7);
+        let simplified = simplify(&e);
+        assert_eq!(simplified.depth(), 200_000);
+        assert_eq!(eval_with(&simplified, &env), Ok(Value::Num(-199_993)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Step-by-step evaluation, recording each reduction to show how a result was reached.
+
+use super::{
+    number::Number, walk, Comparison, Connective, Env, EvalError, Expression, Operation,
+    UnaryOperation, Value,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// One thing evaluation worked out along the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reduction<T = i64> {
+    /// A variable was looked up.
+    Var { name: String, value: Value<T> },
+    /// An operation was applied to two numbers.
+    Op {
+        op: Operation,
+        left: T,
+        right: T,
+        result: T,
+    },
+    /// An operation was applied to one number.
+    Unary {
+        op: UnaryOperation,
+        operand: T,
+        result: T,
+    },
+    /// Two values were compared.
+    Compare {
+        op: Comparison,
+        left: Value<T>,
+        right: Value<T>,
+        result: bool,
+    },
+    /// A boolean was negated.
+    Not { operand: bool },
+    /// A connective was decided, by the left operand alone if there is no right one.
+    Logic {
+        op: Connective,
+        left: bool,
+        right: Option<bool>,
+    },
+    /// A condition picked a branch.
+    If { cond: bool },
+    /// A `let` bound a value.
+    Let { name: String, value: Value<T> },
+    /// A function was called with evaluated arguments. The reductions of its body follow, one
+    /// level deeper.
+    Call { name: String, args: Vec<Value<T>> },
+    /// A function call finished.
+    Return { name: String, value: Value<T> },
+}
+
+impl<T: Display> Display for Reduction<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reduction::Var { name, value } => write!(f, "{name} = {value}"),
+            Reduction::Op {
+                op: op @ (Operation::Min | Operation::Max),
+                left,
+                right,
+                result,
+            } => write!(f, "{op}({left}, {right}) = {result}"),
+            Reduction::Op {
+                op,
+                left,
+                right,
+                result,
+            } => write!(f, "{left} {op} {right} = {result}"),
+            Reduction::Unary {
+                op: UnaryOperation::Neg,
+                operand,
+                result,
+            } => write!(f, "-({operand}) = {result}"),
+            Reduction::Unary {
+                op,
+                operand,
+                result,
+            } => write!(f, "{op}({operand}) = {result}"),
+            Reduction::Compare {
+                op,
+                left,
+                right,
+                result,
+            } => write!(f, "{left} {op} {right} is {result}"),
+            Reduction::Not { operand } => write!(f, "!{operand} is {}", !operand),
+            Reduction::Logic {
+                op,
+                left,
+                right: None,
+            } => write!(f, "{left} {op} ... is {left}"),
+            Reduction::Logic {
+                op,
+                left,
+                right: Some(right),
+            } => write!(f, "{left} {op} {right} is {right}"),
+            Reduction::If { cond: true } => write!(f, "condition is true, taking `then`"),
+            Reduction::If { cond: false } => write!(f, "condition is false, taking `else`"),
+            Reduction::Let { name, value } => write!(f, "let {name} = {value}"),
+            Reduction::Call { name, args } => {
+                write!(f, "call {name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Reduction::Return { name, value } => write!(f, "{name} returns {value}"),
+        }
+    }
+}
+
+/// A [`Reduction`] and how deeply the expression it reduced is nested in the evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep<T = i64> {
+    pub depth: usize,
+    pub reduction: Reduction<T>,
+}
+
+/// Everything an evaluation did, in order, and what it came to.
+///
+/// Displays as one reduction per line, indented by depth, so that the steps feeding into a
+/// result are indented further than it and come just before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace<T = i64> {
+    pub steps: Vec<TraceStep<T>>,
+    pub result: Result<Value<T>, EvalError>,
+}
+
+impl<T: Display> Display for Trace<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}{}", "  ".repeat(step.depth), step.reduction)?;
+        }
+        if let Err(err) = &self.result {
+            writeln!(f, "error: {err}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate `e` like [`eval_number`](super::number::eval_number), recording every reduction.
+pub fn trace<N: Number>(e: &Expression, env: &Env<N>) -> Trace<N> {
+    let mut steps = Vec::new();
+    let result = walk(
+        e,
+        env,
+        N::from_i64,
+        |op, val| val.apply_unary(op),
+        |op, left, right| left.apply(op, right),
+        Some(&mut steps),
+    );
+    Trace { steps, result }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{eval_with, rational::Rational, EvalErrorKind};
+
+    fn explain(text: &str) -> String {
+        let mut env = Env::new();
+        env.bind("x", 7);
+        trace(&text.parse().unwrap(), &env).to_string()
+    }
+
+    #[test]
+    fn steps() {
+        let e = "(1 + x) * -abs(2 - x)".parse().unwrap();
+        let mut env = Env::new();
+        env.bind("x", 7);
+        let trace = trace(&e, &env);
+        assert_eq!(trace.result, eval_with(&e, &env));
+        let step = |depth, reduction| TraceStep { depth, reduction };
+        assert_eq!(
+            trace.steps,
+            [
+                step(
+                    2,
+                    Reduction::Var {
+                        name: String::from("x"),
+                        value: Value::Num(7)
+                    }
+                ),
+                step(
+                    1,
+                    Reduction::Op {
+                        op: Operation::Add,
+                        left: 1,
+                        right: 7,
+                        result: 8
+                    }
+                ),
+                step(
+                    4,
+                    Reduction::Var {
+                        name: String::from("x"),
+                        value: Value::Num(7)
+                    }
+                ),
+                step(
+                    3,
+                    Reduction::Op {
+                        op: Operation::Sub,
+                        left: 2,
+                        right: 7,
+                        result: -5
+                    }
+                ),
+                step(
+                    2,
+                    Reduction::Unary {
+                        op: UnaryOperation::Abs,
+                        operand: -5,
+                        result: 5
+                    }
+                ),
+                step(
+                    1,
+                    Reduction::Unary {
+                        op: UnaryOperation::Neg,
+                        operand: 5,
+                        result: -5
+                    }
+                ),
+                step(
+                    0,
+                    Reduction::Op {
+                        op: Operation::Mul,
+                        left: 8,
+                        right: -5,
+                        result: -40
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn explanation() {
+        assert_eq!(
+            explain("let sq(a) = a * a in sq(x - 4) + min(x, 2)"),
+            [
+                "        x = 7",
+                "      7 - 4 = 3",
+                "    call sq(3)",
+                "        a = 3",
+                "        a = 3",
+                "      3 * 3 = 9",
+                "    sq returns 9",
+                "      x = 7",
+                "    min(7, 2) = 2",
+                "  9 + 2 = 11",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            explain("if x > 5 && !(x == 6) || y then 1 else 0"),
+            [
+                "        x = 7",
+                "      7 > 5 is true",
+                "          x = 7",
+                "        7 == 6 is false",
+                "      !false is true",
+                "    true && true is true",
+                "  true || ... is true",
+                "condition is true, taking `then`",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            explain("let y = x / 2 in y % 0"),
+            "    x = 7\n  7 / 2 = 3\nlet y = 3\n    y = 3\nerror: remainder by zero\n"
+        );
+    }
+
+    #[test]
+    fn other_numbers() {
+        let e = "x / 2 + 1 / 3".parse().unwrap();
+        let mut env = Env::new();
+        env.bind("x", Rational::from(1));
+        assert_eq!(
+            trace(&e, &env).to_string(),
+            "    x = 1\n  1 / 2 = 1/2\n  1 / 3 = 1/3\n1/2 + 1/3 = 5/6\n"
+        );
+        let mut env = Env::new();
+        env.bind("x", 0.5);
+        let trace = trace(&"x / (x - x)".parse().unwrap(), &env);
+        assert_eq!(
+            trace.result.map_err(|err| err.kind),
+            Err(EvalErrorKind::DivisionByZero)
+        );
+    }
+}
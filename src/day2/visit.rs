@@ -0,0 +1,371 @@
+//! Traversals of [`Expression`] trees: [`Visitor`] to inspect one and [`Folder`] to rebuild one.
+//!
+//! Both keep an explicit stack rather than recursing, so arbitrarily deep trees are fine.
+
+use super::{Expression, Step};
+use std::convert::Infallible;
+
+impl Expression {
+    /// The subexpression with index `i` in [`Expression::children`], and the step to it.
+    pub fn child(&self, i: usize) -> Option<(Step, &Expression)> {
+        let (step, child): (_, &Expression) = match (self, i) {
+            (
+                Expression::Op { left, .. }
+                | Expression::Compare { left, .. }
+                | Expression::Logic { left, .. },
+                0,
+            ) => (Step::Left, left),
+            (
+                Expression::Op { right, .. }
+                | Expression::Compare { right, .. }
+                | Expression::Logic { right, .. },
+                1,
+            ) => (Step::Right, right),
+            (Expression::Unary { operand, .. } | Expression::Not(operand), 0) => {
+                (Step::Operand, operand)
+            }
+            (Expression::If { cond, .. }, 0) => (Step::Cond, cond),
+            (Expression::If { then, .. }, 1) => (Step::Then, then),
+            (Expression::If { otherwise, .. }, 2) => (Step::Else, otherwise),
+            (Expression::Let { value, .. }, 0) => (Step::Bound, value),
+            (Expression::Define { function, .. }, 0) => (Step::Bound, &function.body),
+            (Expression::Let { body, .. } | Expression::Define { body, .. }, 1) => {
+                (Step::Body, body)
+            }
+            (Expression::Call { args, .. }, i) => (Step::Arg(i), args.get(i)?),
+            _ => return None,
+        };
+        Some((step, child))
+    }
+
+    /// The subexpressions of `self` in evaluation order, each with the step to it. The body of
+    /// the function an [`Expression::Define`] defines is one of them.
+    pub fn children(&self) -> Vec<(Step, &Expression)> {
+        (0..).map_while(|i| self.child(i)).collect()
+    }
+
+    /// The subexpressions of `self`, in the order of [`Expression::children`].
+    pub fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Value(_) | Expression::Var(_) => Vec::new(),
+            Expression::Unary { operand, .. } | Expression::Not(operand) => vec![operand],
+            Expression::Op { left, right, .. }
+            | Expression::Compare { left, right, .. }
+            | Expression::Logic { left, right, .. } => vec![left, right],
+            Expression::If {
+                cond,
+                then,
+                otherwise,
+            } => vec![cond, then, otherwise],
+            Expression::Let { value, body, .. } => vec![value, body],
+            Expression::Define { function, body, .. } => vec![&mut function.body, body],
+            Expression::Call { args, .. } => args.iter_mut().collect(),
+        }
+    }
+
+    /// How many nodes `self` has, counting those in the bodies of functions it defines.
+    pub fn size(&self) -> usize {
+        #[derive(Default)]
+        struct Size(usize);
+
+        impl Visitor<'_> for Size {
+            type Error = Infallible;
+
+            fn enter(&mut self, _: &Expression, _: usize) -> Result<(), Infallible> {
+                self.0 += 1;
+                Ok(())
+            }
+        }
+
+        let mut size = Size::default();
+        let Ok(()) = visit(self, &mut size);
+        size.0
+    }
+
+    /// How many levels the deepest node is below `self`, which is zero for a leaf.
+    pub fn depth(&self) -> usize {
+        #[derive(Default)]
+        struct Depth(usize);
+
+        impl Visitor<'_> for Depth {
+            type Error = Infallible;
+
+            fn enter(&mut self, _: &Expression, depth: usize) -> Result<(), Infallible> {
+                self.0 = self.0.max(depth);
+                Ok(())
+            }
+        }
+
+        let mut depth = Depth::default();
+        let Ok(()) = visit(self, &mut depth);
+        depth.0
+    }
+}
+
+/// Where a [`visit`] goes next from an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next<'e> {
+    /// Into a subexpression, reached by a step.
+    Child(Step, &'e Expression),
+    /// Into an expression that is not part of the tree, such as the body of a function from the
+    /// [`Env`](super::Env). It is entered and left like a child, but steps inside it do not count
+    /// towards paths.
+    Outside(&'e Expression),
+    /// Back to the parent.
+    Leave,
+}
+
+/// Inspects an expression node by node as [`visit`] traverses it. Every method has a default, so
+/// an analysis only implements the ones it needs.
+///
+/// `depth` is how many expressions the traversal is inside, the root being at depth zero.
+pub trait Visitor<'e> {
+    /// Why the traversal stops early, [`Infallible`] if it never does.
+    type Error;
+
+    /// Called on reaching `e`, before any of its subexpressions.
+    fn enter(&mut self, _e: &'e Expression, _depth: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Where to go from `e` once the traversal has been into and back out of it `visited` times.
+    ///
+    /// Goes into each of the [`Expression::children`] in turn by default. Overriding this can
+    /// skip subexpressions, repeat them, or go outside the tree.
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _depth: usize,
+    ) -> Result<Next<'e>, Self::Error> {
+        Ok(match e.child(visited) {
+            Some((step, child)) => Next::Child(step, child),
+            None => Next::Leave,
+        })
+    }
+
+    /// Called on leaving `e`, after all of its subexpressions.
+    fn leave(&mut self, _e: &'e Expression, _depth: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// How the traversal reached an expression on the stack of [`visit`].
+enum Via {
+    Root,
+    Child,
+    Outside,
+}
+
+/// Traverse `e` with `visitor`.
+///
+/// Stops at the first error of the visitor, returning it with the path to the expression it was
+/// called on. Steps taken outside the tree are not part of the path, so an error in the body of a
+/// function is reported where it was entered from.
+pub fn visit<'e, V: Visitor<'e>>(
+    e: &'e Expression,
+    visitor: &mut V,
+) -> Result<(), (V::Error, Vec<Step>)> {
+    let mut path = Vec::new();
+    // How many of the expressions on the stack were reached outside the tree.
+    let mut outside = 0;
+    visitor.enter(e, 0).map_err(|err| (err, Vec::new()))?;
+    let mut stack = vec![(e, 0, Via::Root)];
+    while let Some(depth) = stack.len().checked_sub(1) {
+        let (e, visited, _) = &mut stack[depth];
+        let e = *e;
+        let result = match visitor.next(e, *visited, depth) {
+            Ok(Next::Child(step, child)) => {
+                *visited += 1;
+                if outside == 0 {
+                    path.push(step);
+                }
+                stack.push((child, 0, Via::Child));
+                visitor.enter(child, depth + 1)
+            }
+            Ok(Next::Outside(child)) => {
+                *visited += 1;
+                outside += 1;
+                stack.push((child, 0, Via::Outside));
+                visitor.enter(child, depth + 1)
+            }
+            Ok(Next::Leave) => {
+                let result = visitor.leave(e, depth);
+                if result.is_ok() {
+                    match stack.pop() {
+                        Some((_, _, Via::Outside)) => outside -= 1,
+                        Some((_, _, Via::Child)) if outside == 0 => {
+                            path.pop();
+                        }
+                        _ => {}
+                    }
+                }
+                result
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            return Err((err, path));
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds an expression bottom up as [`fold`] traverses it.
+pub trait Folder {
+    /// Why folding fails, [`Infallible`] if it cannot.
+    type Error;
+
+    /// The replacement for `e`, whose subexpressions have already been folded. Keeps `e` as it
+    /// is by default.
+    fn fold(&mut self, e: Expression) -> Result<Expression, Self::Error> {
+        Ok(e)
+    }
+}
+
+/// Rebuild `e` by folding each of its nodes with `folder`, children before their parents.
+pub fn fold<F: Folder>(e: Expression, folder: &mut F) -> Result<Expression, F::Error> {
+    enum Task {
+        /// Fold the children of an expression, then the expression.
+        Expand(Expression),
+        /// Put this many folded expressions back into an expression and fold it.
+        Rebuild(Expression, usize),
+    }
+
+    let mut tasks = vec![Task::Expand(e)];
+    let mut folded = Vec::new();
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Expand(mut e) => {
+                let children: Vec<_> = e
+                    .children_mut()
+                    .into_iter()
+                    .map(|child| std::mem::replace(child, Expression::Value(0)))
+                    .collect();
+                tasks.push(Task::Rebuild(e, children.len()));
+                tasks.extend(children.into_iter().rev().map(Task::Expand));
+            }
+            Task::Rebuild(mut e, len) => {
+                let children = folded.split_off(folded.len() - len);
+                for (slot, child) in e.children_mut().into_iter().zip(children) {
+                    *slot = child;
+                }
+                folded.push(folder.fold(e)?);
+            }
+        }
+    }
+    Ok(folded.pop().expect("missing result"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{deep_sum, parse::parse, Operation};
+
+    /// The names of the variables in an expression, in order of appearance.
+    #[derive(Default)]
+    struct Vars(Vec<String>);
+
+    impl<'e> Visitor<'e> for Vars {
+        type Error = Infallible;
+
+        fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+            if let Expression::Var(name) = e {
+                self.0.push(name.clone());
+            }
+            Ok(())
+        }
+    }
+
+    /// Fails on the first division, skipping the right operand of connectives.
+    struct FindDivision;
+
+    impl<'e> Visitor<'e> for FindDivision {
+        type Error = &'e Expression;
+
+        fn enter(&mut self, e: &'e Expression, _: usize) -> Result<(), &'e Expression> {
+            match e {
+                Expression::Op {
+                    op: Operation::Div, ..
+                } => Err(e),
+                _ => Ok(()),
+            }
+        }
+
+        fn next(
+            &mut self,
+            e: &'e Expression,
+            visited: usize,
+            _: usize,
+        ) -> Result<Next<'e>, &'e Expression> {
+            Ok(match (e, e.child(visited)) {
+                (Expression::Logic { .. }, _) if visited == 1 => Next::Leave,
+                (_, Some((step, child))) => Next::Child(step, child),
+                (_, None) => Next::Leave,
+            })
+        }
+    }
+
+    /// Replaces variables with literals.
+    struct Substitute(i64);
+
+    impl Folder for Substitute {
+        type Error = String;
+
+        fn fold(&mut self, e: Expression) -> Result<Expression, String> {
+            match e {
+                Expression::Var(ref name) if name == "fail" => Err(name.clone()),
+                Expression::Var(_) => Ok(Expression::Value(self.0)),
+                e => Ok(e),
+            }
+        }
+    }
+
+    #[test]
+    fn children() {
+        let e = parse("let f(a) = a + 1 in f(2, x)").unwrap();
+        let steps: Vec<_> = e.children().into_iter().map(|(step, _)| step).collect();
+        assert_eq!(steps, [Step::Bound, Step::Body]);
+        let (_, call) = e.child(1).unwrap();
+        assert_eq!(call.child(1), Some((Step::Arg(1), &parse("x").unwrap())));
+        assert_eq!(call.child(2), None);
+        assert!(parse("x").unwrap().children().is_empty());
+    }
+
+    #[test]
+    fn visitor() {
+        let e = parse("if x < y then let z = 1 in z * x else w").unwrap();
+        let mut vars = Vars::default();
+        assert_eq!(visit(&e, &mut vars), Ok(()));
+        assert_eq!(vars.0, ["x", "y", "z", "x", "w"]);
+        assert_eq!(e.size(), 10);
+        assert_eq!(e.depth(), 3);
+        assert_eq!(parse("7").unwrap().depth(), 0);
+
+        let e = parse("x > 0 || 1 / 0 == 1").unwrap();
+        assert_eq!(visit(&e, &mut FindDivision), Ok(()));
+        let e = parse("(let y = 3 in x / y) > 0 && 1 / 0 == 1").unwrap();
+        let (div, path) = visit(&e, &mut FindDivision).unwrap_err();
+        assert_eq!(div.to_string(), "x / y");
+        assert_eq!(path, [Step::Left, Step::Left, Step::Body]);
+        assert_eq!(e.at(&path), Some(div));
+    }
+
+    #[test]
+    fn folder() {
+        let e = parse("let f(a) = a * x in f(y) + z").unwrap();
+        let folded = fold(e, &mut Substitute(3)).unwrap();
+        assert_eq!(folded, parse("let f(a) = 3 * 3 in f(3) + 3").unwrap());
+        let e = parse("x + max(y, fail)").unwrap();
+        assert_eq!(fold(e, &mut Substitute(3)), Err(String::from("fail")));
+    }
+
+    #[test]
+    fn deep() {
+        let e = deep_sum(100_000, true);
+        assert_eq!(e.size(), 200_001);
+        assert_eq!(e.depth(), 100_000);
+        let e = fold(e, &mut Substitute(0)).unwrap();
+        assert_eq!(e.size(), 200_001);
+    }
+}
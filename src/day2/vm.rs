@@ -0,0 +1,863 @@
+//! Compiling an [`Expression`] to bytecode for a small stack machine.
+//!
+//! A compiled [`Program`] gives the same results and errors as [`Arithmetic::eval`], but avoids
+//! walking the boxed tree and looking up variables by name on every evaluation.
+
+use super::visit::{self, Next, Visitor};
+use super::{
+    Arithmetic, Comparison, Connective, Env, EvalError, EvalErrorKind, Expression, Function,
+    Operation, Step, UnaryOperation, Value,
+};
+use std::convert::Infallible;
+
+/// A stack machine instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    /// Push a literal.
+    Push(i64),
+    /// Push the value of the variable in the given slot.
+    Load(usize),
+    /// Push a copy of a local: a `let` value or parameter `slot` places above the base of the
+    /// frame reached by following `up` static links.
+    Local { up: usize, slot: usize },
+    /// Pop the right then the left operand, and push the result.
+    Op(Operation),
+    /// Pop one operand and push the result.
+    Unary(UnaryOperation),
+    /// Pop the right then the left operand, and push whether they compare as given.
+    Compare(Comparison),
+    /// Pop a boolean and push its negation.
+    Not,
+    /// Check the top of the stack is a boolean. If it decides the connective, keep it and jump
+    /// to the given instruction, otherwise pop it.
+    Connect(Connective, usize),
+    /// Check the top of the stack is a boolean.
+    AssertBool,
+    /// Pop a boolean, and jump to the given instruction if it is false.
+    Cond(usize),
+    /// Jump to the given instruction.
+    Jump(usize),
+    /// Remove the given number of values from under the top one, at the end of a `let`.
+    Slide(usize),
+    /// Start a frame whose locals are the top `args` values and jump to `target`. The static link
+    /// is the frame where the function was defined, `up` links out from the current one.
+    Call {
+        target: usize,
+        args: usize,
+        up: usize,
+    },
+    /// End the current frame, leaving only the result in place of its locals.
+    Return,
+    /// Fail with the error in the given slot, for calls that cannot succeed.
+    Fail(usize),
+}
+
+/// A function call in progress, or the expression itself at the bottom of the stack.
+struct Frame {
+    /// Stack index of the first local.
+    base: usize,
+    /// Index of the frame for the code the function was defined in.
+    link: usize,
+    /// The call instruction, to return after and to report errors at.
+    call: usize,
+}
+
+/// Bytecode for one expression, in postfix order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<Instr>,
+    /// The last step of the path to the expression each instruction came from, for error
+    /// reporting, or `None` for the root.
+    paths: Vec<Option<usize>>,
+    /// Steps of those paths, each with the one before it. Paths share their beginnings, so deep
+    /// trees do not need a whole path per instruction.
+    steps: Vec<(Option<usize>, Step)>,
+    /// Variable names, indexed by slot in order of first evaluation.
+    vars: Vec<String>,
+    /// Errors for [`Instr::Fail`], by slot.
+    errors: Vec<EvalErrorKind>,
+    call_limit: usize,
+    max_stack: usize,
+}
+
+/// A name in scope while compiling.
+enum Binding<'e> {
+    /// A local `slot` places above the base of a frame at the given nesting level.
+    Var {
+        name: &'e str,
+        level: usize,
+        slot: usize,
+    },
+    /// A function whose code starts at `entry`, defined at the given nesting level.
+    Function {
+        name: &'e str,
+        level: usize,
+        entry: usize,
+        params: usize,
+    },
+}
+
+/// The function a call refers to.
+enum Callee<'e> {
+    /// Defined in the expression, `up` levels out from the call.
+    Defined {
+        entry: usize,
+        params: usize,
+        up: usize,
+    },
+    /// From the environment, to be emitted after the expression.
+    Linked(&'e Function),
+}
+
+impl Callee<'_> {
+    fn params(&self) -> usize {
+        match self {
+            Callee::Defined { params, .. } => *params,
+            Callee::Linked(function) => function.params.len(),
+        }
+    }
+}
+
+/// State while compiling one expression.
+struct Compiler<'e> {
+    program: Program,
+    env: &'e Env<'e>,
+    /// Bindings in scope, innermost last.
+    scope: Vec<Binding<'e>>,
+    /// How many function bodies the current code is nested in.
+    level: usize,
+    /// Stack depth above the current frame base.
+    depth: usize,
+    /// Functions from the environment that are called, with their entry points once emitted.
+    linked: Vec<(&'e str, &'e Function, Option<usize>)>,
+    /// Calls to linked functions, by instruction and index in `linked`, to fill in at the end.
+    fixups: Vec<(usize, usize)>,
+    /// The last step of the path to the expression being compiled, see [`Program::steps`].
+    path: Option<usize>,
+    /// Instructions waiting for their jump target, innermost last.
+    jumps: Vec<usize>,
+    /// Stack depth and scope length outside each function body being compiled, innermost last.
+    frames: Vec<(usize, usize)>,
+    /// What each call being compiled refers to, innermost last, or `None` if it fails.
+    calls: Vec<Option<Callee<'e>>>,
+}
+
+/// Compile `e` to bytecode.
+pub fn compile(e: &Expression) -> Program {
+    compile_with(e, &Env::new())
+}
+
+/// Compile `e` to bytecode, linking calls to functions it does not define to those in `env`.
+///
+/// Variables are still looked up when the program runs, but the functions and call depth limit of
+/// `env` are fixed from now on.
+pub fn compile_with(e: &Expression, env: &Env) -> Program {
+    let mut compiler = Compiler {
+        program: Program {
+            code: Vec::new(),
+            paths: Vec::new(),
+            steps: Vec::new(),
+            vars: Vec::new(),
+            errors: Vec::new(),
+            call_limit: env.call_limit(),
+            max_stack: 0,
+        },
+        env,
+        scope: Vec::new(),
+        level: 0,
+        depth: 0,
+        linked: Vec::new(),
+        fixups: Vec::new(),
+        path: None,
+        jumps: Vec::new(),
+        frames: Vec::new(),
+        calls: Vec::new(),
+    };
+    let Ok(()) = visit::visit(e, &mut compiler);
+    compiler.emit_linked();
+    compiler.program
+}
+
+/// Appends the code for each expression, which leaves its value on top of the stack. Operands
+/// come before the instruction that uses them, and jumps are filled in once their target is
+/// emitted.
+impl<'e> Visitor<'e> for Compiler<'e> {
+    type Error = Infallible;
+
+    fn next(
+        &mut self,
+        e: &'e Expression,
+        visited: usize,
+        _: usize,
+    ) -> Result<Next<'e>, Infallible> {
+        match (e, visited) {
+            (Expression::Logic { op, .. }, 1) => {
+                let connect = self.push(Instr::Connect(*op, 0));
+                self.jumps.push(connect);
+            }
+            (Expression::If { .. }, 1) => {
+                let branch = self.push(Instr::Cond(0));
+                self.jumps.push(branch);
+            }
+            (Expression::If { .. }, 2) => {
+                let jump = self.push(Instr::Jump(0));
+                let branch = self.jumps.pop().expect("missing branch");
+                self.patch(branch, Instr::Cond(self.program.code.len()));
+                self.jumps.push(jump);
+                // Only one branch runs, so the other starts from the same depth.
+                self.depth -= 1;
+            }
+            (Expression::Let { name, .. }, 1) => self.scope.push(Binding::Var {
+                name,
+                level: self.level,
+                slot: self.depth - 1,
+            }),
+            (Expression::Define { name, function, .. }, 0) => {
+                let jump = self.push(Instr::Jump(0));
+                self.jumps.push(jump);
+                self.scope.push(Binding::Function {
+                    name,
+                    level: self.level,
+                    entry: self.program.code.len(),
+                    params: function.params.len(),
+                });
+                self.enter_function(function);
+            }
+            (Expression::Define { .. }, 1) => {
+                self.step(Step::Bound);
+                self.leave_function();
+                self.step_back();
+                let jump = self.jumps.pop().expect("missing jump");
+                self.patch(jump, Instr::Jump(self.program.code.len()));
+            }
+            (Expression::Call { name, args }, 0) => {
+                let callee = self.callee(name);
+                let error = match &callee {
+                    None => Some(EvalErrorKind::UnknownFunction(name.clone())),
+                    Some(callee) if callee.params() != args.len() => {
+                        Some(EvalErrorKind::ArgumentCount {
+                            name: name.clone(),
+                            expected: callee.params(),
+                            found: args.len(),
+                        })
+                    }
+                    Some(_) => None,
+                };
+                if let Some(error) = error {
+                    // The arguments are never evaluated.
+                    self.program.errors.push(error);
+                    self.push(Instr::Fail(self.program.errors.len() - 1));
+                    self.calls.push(None);
+                    return Ok(Next::Leave);
+                }
+                self.calls.push(callee);
+            }
+            _ => {}
+        }
+        Ok(match e.child(visited) {
+            Some((step, child)) => {
+                self.step(step);
+                Next::Child(step, child)
+            }
+            None => Next::Leave,
+        })
+    }
+
+    fn leave(&mut self, e: &'e Expression, _: usize) -> Result<(), Infallible> {
+        match e {
+            Expression::Value(val) => {
+                self.push(Instr::Push(*val));
+            }
+            Expression::Var(name) => {
+                let local = self.scope.iter().rev().find_map(|binding| match binding {
+                    Binding::Var {
+                        name: bound,
+                        level,
+                        slot,
+                    } if bound == name => Some(Instr::Local {
+                        up: self.level - level,
+                        slot: *slot,
+                    }),
+                    _ => None,
+                });
+                let instr = local.unwrap_or_else(|| {
+                    let vars = &mut self.program.vars;
+                    Instr::Load(match vars.iter().position(|v| v == name) {
+                        Some(slot) => slot,
+                        None => {
+                            vars.push(name.clone());
+                            vars.len() - 1
+                        }
+                    })
+                });
+                self.push(instr);
+            }
+            Expression::Op { op, .. } => {
+                self.push(Instr::Op(*op));
+            }
+            Expression::Compare { op, .. } => {
+                self.push(Instr::Compare(*op));
+            }
+            Expression::Unary { op, .. } => {
+                self.push(Instr::Unary(*op));
+            }
+            Expression::Not(_) => {
+                self.push(Instr::Not);
+            }
+            Expression::Logic { op, .. } => {
+                self.push(Instr::AssertBool);
+                let connect = self.jumps.pop().expect("missing connective");
+                self.patch(connect, Instr::Connect(*op, self.program.code.len()));
+            }
+            Expression::If { .. } => {
+                let jump = self.jumps.pop().expect("missing jump");
+                self.patch(jump, Instr::Jump(self.program.code.len()));
+            }
+            Expression::Let { .. } => {
+                self.scope.pop();
+                self.push(Instr::Slide(1));
+            }
+            Expression::Define { .. } => {
+                self.scope.pop();
+            }
+            Expression::Call { name, args } => {
+                let callee = self.calls.pop().expect("missing call");
+                if let Some(callee) = callee {
+                    let (target, up) = match callee {
+                        Callee::Defined { entry, up, .. } => (entry, up),
+                        // Linked functions are outside everything, and get their entry later.
+                        Callee::Linked(_) => (0, self.level),
+                    };
+                    let at = self.push(Instr::Call {
+                        target,
+                        args: args.len(),
+                        up,
+                    });
+                    if let Callee::Linked(function) = callee {
+                        let index = self.link(name, function);
+                        self.fixups.push((at, index));
+                    }
+                }
+            }
+        }
+        self.step_back();
+        Ok(())
+    }
+}
+
+impl<'e> Compiler<'e> {
+    /// The function `name` refers to at this point, if any.
+    fn callee(&self, name: &str) -> Option<Callee<'e>> {
+        self.scope
+            .iter()
+            .rev()
+            .find_map(|binding| match binding {
+                Binding::Function {
+                    name: bound,
+                    level,
+                    entry,
+                    params,
+                } if *bound == name => Some(Callee::Defined {
+                    entry: *entry,
+                    params: *params,
+                    up: self.level - level,
+                }),
+                _ => None,
+            })
+            .or_else(|| self.env.function(name).map(Callee::Linked))
+    }
+
+    /// Start the body of `function` as a new frame level.
+    fn enter_function(&mut self, function: &'e Function) {
+        self.frames.push((self.depth, self.scope.len()));
+        self.level += 1;
+        for (slot, param) in function.params.iter().enumerate() {
+            self.scope.push(Binding::Var {
+                name: param,
+                level: self.level,
+                slot,
+            });
+        }
+        self.depth = function.params.len();
+    }
+
+    /// End the body of the innermost function with [`Instr::Return`].
+    fn leave_function(&mut self) {
+        self.push(Instr::Return);
+        let (outer_depth, outer_scope) = self.frames.pop().expect("missing frame");
+        self.scope.truncate(outer_scope);
+        self.level -= 1;
+        self.depth = outer_depth;
+    }
+
+    /// The index of `function` in [`Compiler::linked`], adding it if needed.
+    fn link(&mut self, name: &'e str, function: &'e Function) -> usize {
+        match self
+            .linked
+            .iter()
+            .position(|(linked, _, _)| *linked == name)
+        {
+            Some(index) => index,
+            None => {
+                self.linked.push((name, function, None));
+                self.linked.len() - 1
+            }
+        }
+    }
+
+    /// Append the functions from the environment that were called, and point the calls at them.
+    fn emit_linked(&mut self) {
+        if self.linked.is_empty() {
+            return;
+        }
+        let jump = self.push(Instr::Jump(0));
+        // Their bodies see nothing but their parameters and the environment.
+        let scope = std::mem::take(&mut self.scope);
+        let mut i = 0;
+        // Emitting a body can link more functions.
+        while let Some(&(_, function, _)) = self.linked.get(i) {
+            self.linked[i].2 = Some(self.program.code.len());
+            self.enter_function(function);
+            let Ok(()) = visit::visit(&function.body, self);
+            self.leave_function();
+            i += 1;
+        }
+        self.scope = scope;
+        self.patch(jump, Instr::Jump(self.program.code.len()));
+        for &(at, index) in &self.fixups {
+            if let Instr::Call { target, .. } = &mut self.program.code[at] {
+                *target = self.linked[index].2.expect("linked function was emitted");
+            }
+        }
+    }
+
+    /// Append one instruction, returning its index so that jumps can be filled in later.
+    fn push(&mut self, instr: Instr) -> usize {
+        self.depth = match instr {
+            Instr::Push(_) | Instr::Load(_) | Instr::Local { .. } | Instr::Fail(_) => {
+                self.depth + 1
+            }
+            Instr::Op(_) | Instr::Compare(_) | Instr::Cond(_) => self.depth - 1,
+            // A short circuit leaves the value that the right operand would otherwise replace.
+            Instr::Connect(..) => self.depth - 1,
+            Instr::Slide(n) => self.depth - n,
+            Instr::Call { args, .. } => self.depth + 1 - args,
+            Instr::Unary(_) | Instr::Not | Instr::AssertBool | Instr::Jump(_) | Instr::Return => {
+                self.depth
+            }
+        };
+        self.program.max_stack = self.program.max_stack.max(self.depth);
+        let code = &mut self.program.code;
+        code.push(instr);
+        self.program.paths.push(self.path);
+        code.len() - 1
+    }
+
+    /// Go down a step from the expression being compiled.
+    fn step(&mut self, step: Step) {
+        self.program.steps.push((self.path, step));
+        self.path = Some(self.program.steps.len() - 1);
+    }
+
+    /// Go back up a step, if not at the root.
+    fn step_back(&mut self) {
+        self.path = self.path.and_then(|last| self.program.steps[last].0);
+    }
+
+    /// Replace the placeholder instruction at `at` once its jump target is known.
+    fn patch(&mut self, at: usize, instr: Instr) {
+        self.program.code[at] = instr;
+    }
+}
+
+impl Program {
+    /// The instructions, in execution order.
+    pub fn code(&self) -> &[Instr] {
+        &self.code
+    }
+
+    /// Variable names in slot order, as expected by [`Program::run_slots`].
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    /// Run with [`Arithmetic::Checked`], looking up variables in `env`.
+    pub fn run(&self, env: &Env) -> Result<Value, EvalError> {
+        self.run_in(Arithmetic::Checked, env)
+    }
+
+    /// Run under the given semantics, looking up variables in `env`.
+    pub fn run_in(&self, arithmetic: Arithmetic, env: &Env) -> Result<Value, EvalError> {
+        let values: Vec<Option<i64>> = self.vars.iter().map(|name| env.get(name)).collect();
+        self.exec(arithmetic, |slot| values[slot])
+    }
+
+    /// Run with variable values given by slot, see [`Program::vars`].
+    ///
+    /// # Panics
+    ///
+    /// If `values` does not have one entry per variable.
+    pub fn run_slots(&self, arithmetic: Arithmetic, values: &[i64]) -> Result<Value, EvalError> {
+        assert_eq!(values.len(), self.vars.len(), "wrong number of variables");
+        self.exec(arithmetic, |slot| Some(values[slot]))
+    }
+
+    fn exec(
+        &self,
+        arithmetic: Arithmetic,
+        load: impl Fn(usize) -> Option<i64>,
+    ) -> Result<Value, EvalError> {
+        let mut stack: Vec<Value> = Vec::with_capacity(self.max_stack);
+        // The outermost frame stands for the expression itself and is never returned from.
+        let mut frames = vec![Frame {
+            base: 0,
+            link: 0,
+            call: 0,
+        }];
+        let mut pc = 0;
+        while let Some(&instr) = self.code.get(pc) {
+            let mut next = pc + 1;
+            let mut pop = || stack.pop().expect("stack underflow");
+            // The value to push, if any.
+            let result = match instr {
+                Instr::Push(val) => Ok(Some(Value::Num(val))),
+                Instr::Load(slot) => load(slot)
+                    .map(|val| Some(Value::Num(val)))
+                    .ok_or_else(|| EvalErrorKind::UnboundVariable(self.vars[slot].clone())),
+                Instr::Op(op) => {
+                    let right = pop();
+                    let left = pop();
+                    left.number()
+                        .and_then(|left| arithmetic.apply(op, left, right.number()?))
+                        .map(|val| Some(Value::Num(val)))
+                }
+                Instr::Unary(op) => pop()
+                    .number()
+                    .and_then(|val| arithmetic.apply_unary(op, val))
+                    .map(|val| Some(Value::Num(val))),
+                Instr::Compare(op) => {
+                    let right = pop();
+                    let left = pop();
+                    left.compare(op, right).map(|val| Some(Value::Bool(val)))
+                }
+                Instr::Not => pop().boolean().map(|val| Some(Value::Bool(!val))),
+                Instr::Connect(op, target) => {
+                    stack.last().expect("stack underflow").boolean().map(|val| {
+                        if val == op.short_circuit() {
+                            next = target;
+                        } else {
+                            stack.pop();
+                        }
+                        None
+                    })
+                }
+                Instr::AssertBool => stack
+                    .last()
+                    .expect("stack underflow")
+                    .boolean()
+                    .map(|_| None),
+                Instr::Cond(target) => pop().boolean().map(|cond| {
+                    if !cond {
+                        next = target;
+                    }
+                    None
+                }),
+                Instr::Jump(target) => {
+                    next = target;
+                    Ok(None)
+                }
+                Instr::Local { up, slot } => {
+                    let frame = (0..up).fold(frames.len() - 1, |f, _| frames[f].link);
+                    Ok(Some(stack[frames[frame].base + slot]))
+                }
+                Instr::Slide(n) => {
+                    let val = pop();
+                    stack.truncate(stack.len() - n);
+                    Ok(Some(val))
+                }
+                Instr::Call { .. } if frames.len() > self.call_limit => {
+                    Err(EvalErrorKind::CallDepth(self.call_limit))
+                }
+                Instr::Call { target, args, up } => {
+                    let link = (0..up).fold(frames.len() - 1, |f, _| frames[f].link);
+                    frames.push(Frame {
+                        base: stack.len() - args,
+                        link,
+                        call: pc,
+                    });
+                    next = target;
+                    Ok(None)
+                }
+                Instr::Return => {
+                    let val = pop();
+                    let frame = frames.pop().expect("return outside a call");
+                    stack.truncate(frame.base);
+                    next = frame.call + 1;
+                    Ok(Some(val))
+                }
+                Instr::Fail(slot) => Err(self.errors[slot].clone()),
+            };
+            match result {
+                Ok(Some(val)) => stack.push(val),
+                Ok(None) => {}
+                // Errors inside a function are reported at the outermost call, like `eval` does.
+                Err(kind) => return Err(self.error(frames.get(1).map_or(pc, |f| f.call), kind)),
+            }
+            pc = next;
+        }
+        Ok(stack.pop().expect("empty program"))
+    }
+
+    fn error(&self, pc: usize, kind: EvalErrorKind) -> EvalError {
+        EvalError {
+            kind,
+            path: self.path(pc),
+        }
+    }
+
+    /// The path to the expression instruction `pc` came from.
+    fn path(&self, pc: usize) -> Vec<Step> {
+        let mut path = Vec::new();
+        let mut last = self.paths[pc];
+        while let Some(i) = last {
+            let (before, step) = self.steps[i];
+            path.push(step);
+            last = before;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::day2::{deep_sum, eval_with, property::check_expressions, random::Generator};
+
+    #[test]
+    fn bytecode() {
+        let program = compile(&"x * (y + 2) - x".parse().unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Load(0),
+                Instr::Load(1),
+                Instr::Push(2),
+                Instr::Op(Operation::Add),
+                Instr::Op(Operation::Mul),
+                Instr::Load(0),
+                Instr::Op(Operation::Sub),
+            ]
+        );
+        assert_eq!(program.vars(), ["x", "y"]);
+        assert_eq!(program.max_stack, 3);
+    }
+
+    #[test]
+    fn jumps() {
+        let program = compile(&"if x > 0 then x && y else 1".parse().unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Load(0),
+                Instr::Push(0),
+                Instr::Compare(Comparison::Gt),
+                Instr::Cond(9),
+                Instr::Load(0),
+                Instr::Connect(Connective::And, 8),
+                Instr::Load(1),
+                Instr::AssertBool,
+                Instr::Jump(10),
+                Instr::Push(1),
+            ]
+        );
+        assert_eq!(program.max_stack, 2);
+    }
+
+    #[test]
+    fn calls() {
+        let program = compile(&"let sq(a) = a * a in sq(x) + 1".parse().unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Jump(5),
+                Instr::Local { up: 0, slot: 0 },
+                Instr::Local { up: 0, slot: 0 },
+                Instr::Op(Operation::Mul),
+                Instr::Return,
+                Instr::Load(0),
+                Instr::Call {
+                    target: 1,
+                    args: 1,
+                    up: 0,
+                },
+                Instr::Push(1),
+                Instr::Op(Operation::Add),
+            ]
+        );
+        let program = compile(&"let y = x + 1 in y * y".parse().unwrap());
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Load(0),
+                Instr::Push(1),
+                Instr::Op(Operation::Add),
+                Instr::Local { up: 0, slot: 0 },
+                Instr::Local { up: 0, slot: 0 },
+                Instr::Op(Operation::Mul),
+                Instr::Slide(1),
+            ]
+        );
+        assert_eq!(program.max_stack, 3);
+    }
+
+    #[test]
+    fn same_as_eval() {
+        let mut env = Env::new();
+        env.bind("x", 7).bind("y", -3).bind("zero", 0);
+        env.bind("big", i64::MAX);
+        let function = |params: &[&str], body: &str| Function {
+            params: params.iter().map(|&param| String::from(param)).collect(),
+            body: body.parse().unwrap(),
+        };
+        env.define("sq", function(&["a"], "a * a"))
+            .define("quad", function(&["a"], "sq(sq(a)) + x"))
+            .define(
+                "even",
+                function(&["n"], "if n == 0 then 0 < 1 else odd(n - 1)"),
+            )
+            .define(
+                "odd",
+                function(&["n"], "if n == 0 then 0 > 1 else even(n - 1)"),
+            )
+            .define("inv", function(&["n"], "1 / n"));
+        for text in [
+            "42",
+            "x",
+            "10 * 9 + (3 - 4) * 5",
+            "x * x + y / 2 - (x - y) * (x + y)",
+            "1 / zero + unbound",
+            "unbound + 1 / zero",
+            "x + big",
+            "(0 - big - 1) / -1",
+            "-x ^ 2 % 5 + abs(y) - min(x, max(y, 3))",
+            "(0 - big - 1) % -1 + abs(0 - big - 1)",
+            "x ^ (y + 2)",
+            "-y ^ big",
+            "x > y && !(y == -3) || zero <= 0",
+            "zero != 0 && 1 / zero > 0",
+            "x == 7 || unbound",
+            "y < 0 && x",
+            "x > 0 || y",
+            "x && y",
+            "if y < 0 then x + big else 1 / zero",
+            "if x >= 7 then y < 0 else unbound",
+            "if x then 1 else 2",
+            "(x < y) * 2",
+            "(x < y) == (y < x)",
+            "1 + (if x < y then 1 else big)",
+            "let z = x * 2 in z + y",
+            "let b = x > y in if b then 1 else 2",
+            "(let x = 1 in x) + x",
+            "let f(n) = if n <= 1 then 1 else n * f(n - 1) in f(x)",
+            "let k = 3 in let add(a) = a + k + x in let k = 100 in add(1)",
+            "let outer(a) = let inner(b) = a * b + x in inner(2) + inner(y) in outer(5)",
+            "let f(a, b) = a - b in f(x, let c = 2 in c * y)",
+            "let f(n) = f(n + 1) in f(0)",
+            "let f(a) = a / zero in 1 + f(1)",
+            "let f(p) = !p in f(x < y) || f(1)",
+            "let f(a) = a + unbound in let unbound = 1 in f(1)",
+            "let sq(a) = -a in sq(x) + quad(y)",
+            "even(x) || odd(x) && even(y)",
+            "2 * inv(zero)",
+            "quad(big)",
+            "undefined(1)",
+            "sq(1, 2)",
+            "let f(a) = a in f()",
+        ] {
+            let e = text.parse().unwrap();
+            let program = compile_with(&e, &env);
+            assert_eq!(program.run(&env), eval_with(&e, &env), "{text}");
+            for arithmetic in [Arithmetic::Wrapping, Arithmetic::Saturating] {
+                assert_eq!(
+                    program.run_in(arithmetic, &env),
+                    arithmetic.eval(&e, &env),
+                    "{text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slots() {
+        let e = "a * a - b".parse().unwrap();
+        let program = compile(&e);
+        assert_eq!(
+            program.run_slots(Arithmetic::Checked, &[5, 4]),
+            Ok(Value::Num(21))
+        );
+        assert_eq!(
+            program.run_slots(Arithmetic::Checked, &[-2, 0]),
+            Ok(Value::Num(4))
+        );
+        assert_eq!(
+            program.run_slots(Arithmetic::Checked, &[i64::MAX, 0]),
+            Err(EvalError {
+                kind: EvalErrorKind::Overflow,
+                path: vec![Step::Left],
+            })
+        );
+
+        let e = "let down(n) = if n == 0 then 0 else down(n - 1) in down(a)"
+            .parse()
+            .unwrap();
+        let mut env = Env::new();
+        env.limit_calls(5);
+        let program = compile_with(&e, &env);
+        assert_eq!(
+            program.run_slots(Arithmetic::Checked, &[4]),
+            Ok(Value::Num(0))
+        );
+        assert_eq!(
+            program.run_slots(Arithmetic::Checked, &[5]),
+            Err(EvalError {
+                kind: EvalErrorKind::CallDepth(5),
+                path: vec![Step::Body],
+            })
+        );
+    }
+
+    #[test]
+    fn random_same_as_eval() {
+        let mut env = Env::new();
+        env.bind("x", 3).bind("y", -7);
+        let property = |e: &Expression| {
+            let program = compile(e);
+            for arithmetic in [
+                Arithmetic::Checked,
+                Arithmetic::Wrapping,
+                Arithmetic::Saturating,
+            ] {
+                let (run, eval) = (program.run_in(arithmetic, &env), arithmetic.eval(e, &env));
+                if run != eval {
+                    return Err(format!("{arithmetic:?} runs to {run:?}, not {eval:?}"));
+                }
+            }
+            Ok(())
+        };
+        if let Err(failure) = check_expressions(&Generator::new(), 1000, 0, property) {
+            panic!("{failure}");
+        }
+    }
+
+    #[test]
+    fn deep() {
+        for left_deep in [true, false] {
+            let e = deep_sum(100_000, left_deep);
+            let program = compile(&e);
+            assert_eq!(program.code().len(), 200_001);
+            assert_eq!(program.run(&Env::new()), Ok(Value::Num(100_001)));
+            assert_eq!(program.run(&Env::new()), eval_with(&e, &Env::new()));
+        }
+    }
+}
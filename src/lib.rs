@@ -1,2 +1,3 @@
+pub mod bigint;
 pub mod day1;
 pub mod day2;
@@ -1 +1,6 @@
-fn main() {}
+use comprehensive_rust::day2::repl;
+use std::io;
+
+fn main() -> io::Result<()> {
+    repl::run(io::stdin().lock(), io::stdout())
+}